@@ -1 +0,0 @@
-pub mod auth; 
\ No newline at end of file
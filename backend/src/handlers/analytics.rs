@@ -0,0 +1,653 @@
+use actix_web::{ get, web, HttpResponse, Result, error::ErrorInternalServerError };
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::{ doc, Bson, Document };
+use mongodb::bson::oid::ObjectId;
+use mongodb::Database;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{ Mutex, PoisonError };
+use crate::handlers::auth::now_secs;
+use crate::handlers::team::load_team;
+use crate::middleware::auth::{ ApiUser, MaybeUser };
+use crate::models::analytics::{
+    AnalyticsQuery,
+    AnalyticsReport,
+    AnalyticsTotals,
+    AccuracyReport,
+    AccuracyTotals,
+    Assessment,
+    EffortBaseline,
+    FlaggedGroup,
+    PersonAccuracy,
+    ScoreBucketAccuracy,
+    TagAccuracy,
+};
+use crate::models::api_token::TokenScope;
+use crate::models::game_room::{ GameRoom, EffortUnit, UNKNOWN_CARD };
+
+const DEFAULT_CACHE_SECS: i64 = 300;
+// Udløbne rapporter ryddes væk når cachen når denne størrelse
+const PRUNE_THRESHOLD: usize = 1_000;
+const WEEK_SECS: i64 = 7 * 24 * 3600;
+// Unix tid starter en torsdag, så uger forskydes til at starte mandag
+const MONDAY_OFFSET_SECS: i64 = 4 * 24 * 3600;
+
+// Færdige rapporter gemmes i en periode, så dashboards ikke kører aggregeringen ved hvert kald.
+// Nye historier dukker derfor først op når rapporten udløber.
+pub struct AnalyticsCache {
+    ttl_secs: i64,
+    reports: Mutex<HashMap<String, AnalyticsReport>>,
+}
+
+impl AnalyticsCache {
+    pub fn from_env() -> Self {
+        let ttl_secs = std::env
+            ::var("ANALYTICS_CACHE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CACHE_SECS);
+        AnalyticsCache { ttl_secs, reports: Mutex::new(HashMap::new()) }
+    }
+
+    fn get(&self, key: &str) -> Option<AnalyticsReport> {
+        let now = now_secs();
+        self.reports
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .filter(|report| report.generated_at + self.ttl_secs > now)
+            .cloned()
+    }
+
+    fn put(&self, key: String, report: AnalyticsReport) {
+        let now = now_secs();
+        // Cachen indeholder kun færdige rapporter, så den kan bruges selvom en anden tråd er gået i panik
+        let mut reports = self.reports.lock().unwrap_or_else(PoisonError::into_inner);
+        if reports.len() >= PRUNE_THRESHOLD {
+            reports.retain(|_, report| report.generated_at + self.ttl_secs > now);
+        }
+        reports.insert(key, report);
+    }
+}
+
+// Deltagerens adgang til rummet, eller svaret der skal sendes hvis de ikke har den
+async fn check_room_access(
+    db: &Database,
+    room_id: &str,
+    user_id: &str
+) -> Result<Result<(), HttpResponse>> {
+    let Ok(object_id) = ObjectId::parse_str(room_id) else {
+        return Ok(
+            Err(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt rum ID"
+                    })
+                )
+            )
+        );
+    };
+    let room = db
+        .collection::<GameRoom>("game_rooms")
+        .find_one(doc! { "_id": object_id }, None).await
+        .map_err(ErrorInternalServerError)?;
+    match room {
+        Some(room) if room.participants.iter().any(|id| id == user_id) => Ok(Ok(())),
+        Some(_) =>
+            Ok(
+                Err(
+                    HttpResponse::Forbidden().json(
+                        serde_json::json!({
+                            "message": "Du har ikke adgang til dette spilrum"
+                        })
+                    )
+                )
+            ),
+        None =>
+            Ok(
+                Err(
+                    HttpResponse::NotFound().json(
+                        serde_json::json!({
+                            "message": "Spilrum ikke fundet"
+                        })
+                    )
+                )
+            ),
+    }
+}
+
+// Id'erne på teamets rum, hvis brugeren er medlem af teamet
+async fn team_room_ids(
+    db: &Database,
+    team_id: &str,
+    user_id: &str
+) -> Result<Result<Vec<String>, HttpResponse>> {
+    match load_team(db, team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+        Some(team) if team.role_of(user_id).is_some() => {}
+        _ => {
+            return Ok(
+                Err(
+                    HttpResponse::NotFound().json(
+                        serde_json::json!({
+                            "message": "Team ikke fundet"
+                        })
+                    )
+                )
+            );
+        }
+    }
+
+    let mut cursor = db
+        .collection::<GameRoom>("game_rooms")
+        .find(doc! { "team_id": team_id }, None).await
+        .map_err(ErrorInternalServerError)?;
+    let mut room_ids = Vec::new();
+    while let Some(room) = cursor.try_next().await.map_err(ErrorInternalServerError)? {
+        if let Some(id) = room.id {
+            room_ids.push(id.to_string());
+        }
+    }
+    Ok(Ok(room_ids))
+}
+
+fn cache_key(scope: &str, id: &str, query: &AnalyticsQuery) -> String {
+    format!("{}:{}:{:?}:{:?}", scope, id, query.from, query.to)
+}
+
+fn with_period(mut filter: Document, query: &AnalyticsQuery) -> Document {
+    let mut period = Document::new();
+    if let Some(from) = query.from {
+        period.insert("$gte", from);
+    }
+    if let Some(to) = query.to {
+        period.insert("$lt", to);
+    }
+    if !period.is_empty() {
+        filter.insert("completed_at", period);
+    }
+    filter
+}
+
+// Én aggregering over completed_stories med et $facet pr. nøgletal.
+// `bias_filter` anvendes på de enkelte stemmer efter $unwind.
+fn analytics_pipeline(filter: Document, bias_filter: Document) -> Vec<Document> {
+    // Point summeres kun over historier med en talscore. "?" og WSJF-prioriteter er ikke point.
+    let points_filter = doc! { "final_score": { "$gt": UNKNOWN_CARD }, "wsjf": null };
+    vec![
+        doc! { "$match": filter },
+        doc! {
+            "$facet": {
+                "totals": [
+                    { "$match": points_filter.clone() },
+                    {
+                        "$group": {
+                            "_id": null,
+                            "story_count": { "$sum": 1 },
+                            "total_points": { "$sum": "$final_score" },
+                            "average_score": { "$avg": "$final_score" },
+                            "average_rounds": { "$avg": { "$ifNull": ["$rounds", 1] } },
+                            "average_story_secs": {
+                                "$avg": {
+                                    "$cond": [
+                                        { "$ifNull": ["$started_at", false] },
+                                        { "$subtract": ["$completed_at", "$started_at"] },
+                                        null
+                                    ]
+                                }
+                            }
+                        }
+                    },
+                    { "$project": { "_id": 0 } }
+                ],
+                "points_per_session": [
+                    { "$match": points_filter },
+                    {
+                        "$group": {
+                            "_id": { "room_id": "$room_id", "session_id": "$session_id" },
+                            "story_count": { "$sum": 1 },
+                            "total_points": { "$sum": "$final_score" },
+                            "first_completed_at": { "$min": "$completed_at" }
+                        }
+                    },
+                    { "$sort": { "first_completed_at": 1 } },
+                    {
+                        "$project": {
+                            "_id": 0,
+                            "room_id": "$_id.room_id",
+                            "session_id": { "$ifNull": ["$_id.session_id", null] },
+                            "story_count": 1,
+                            "total_points": 1,
+                            "first_completed_at": 1
+                        }
+                    }
+                ],
+                "score_distribution": [
+                    { "$group": { "_id": "$final_score", "count": { "$sum": 1 } } },
+                    { "$sort": { "_id": 1 } },
+                    { "$project": { "_id": 0, "final_score": "$_id", "count": 1 } }
+                ],
+                "vote_spread": [
+                    {
+                        "$project": {
+                            "completed_at": 1,
+                            "values": {
+                                "$filter": {
+                                    "input": "$votes.value",
+                                    "as": "value",
+                                    "cond": { "$gt": ["$$value", UNKNOWN_CARD] }
+                                }
+                            }
+                        }
+                    },
+                    { "$match": { "values.1": { "$exists": true } } },
+                    {
+                        "$project": {
+                            "week_start": {
+                                "$subtract": [
+                                    "$completed_at",
+                                    { "$mod": [{ "$subtract": ["$completed_at", MONDAY_OFFSET_SECS] }, WEEK_SECS] }
+                                ]
+                            },
+                            "spread": { "$subtract": [{ "$max": "$values" }, { "$min": "$values" }] }
+                        }
+                    },
+                    {
+                        "$group": {
+                            "_id": "$week_start",
+                            "story_count": { "$sum": 1 },
+                            "average_spread": { "$avg": "$spread" }
+                        }
+                    },
+                    { "$sort": { "_id": 1 } },
+                    { "$project": { "_id": 0, "week_start": "$_id", "story_count": 1, "average_spread": 1 } }
+                ],
+                "user_bias": [
+                    { "$unwind": "$votes" },
+                    { "$match": { "votes.value": { "$gt": UNKNOWN_CARD }, "votes.userId": { "$ne": "" } } },
+                    { "$match": bias_filter },
+                    {
+                        "$project": {
+                            "user_id": "$votes.userId",
+                            "username": "$votes.username",
+                            "completed_at": 1,
+                            "diff": { "$subtract": ["$votes.value", "$final_score"] }
+                        }
+                    },
+                    { "$sort": { "completed_at": 1 } },
+                    {
+                        "$group": {
+                            "_id": "$user_id",
+                            "username": { "$last": "$username" },
+                            "vote_count": { "$sum": 1 },
+                            "average_bias": { "$avg": "$diff" },
+                            "average_abs_bias": { "$avg": { "$abs": "$diff" } }
+                        }
+                    },
+                    { "$sort": { "vote_count": -1 } },
+                    {
+                        "$project": {
+                            "_id": 0,
+                            "user_id": "$_id",
+                            "username": 1,
+                            "vote_count": 1,
+                            "average_bias": 1,
+                            "average_abs_bias": 1
+                        }
+                    }
+                ]
+            }
+        }
+    ]
+}
+
+fn facet<T: DeserializeOwned>(facets: &Document, name: &str) -> Result<Vec<T>, mongodb::bson::de::Error> {
+    let Ok(items) = facets.get_array(name) else {
+        return Ok(Vec::new());
+    };
+    items
+        .iter()
+        .filter_map(Bson::as_document)
+        .map(|item| mongodb::bson::from_document(item.clone()))
+        .collect()
+}
+
+pub async fn build_report(
+    db: &Database,
+    filter: Document,
+    bias_filter: Document
+) -> Result<AnalyticsReport, Box<dyn std::error::Error>> {
+    let mut cursor = db
+        .collection::<Document>("completed_stories")
+        .aggregate(analytics_pipeline(filter, bias_filter), None).await?;
+    let facets = cursor.try_next().await?.unwrap_or_default();
+
+    Ok(AnalyticsReport {
+        generated_at: now_secs(),
+        totals: facet::<AnalyticsTotals>(&facets, "totals")?.into_iter().next().unwrap_or_default(),
+        points_per_session: facet(&facets, "points_per_session")?,
+        score_distribution: facet(&facets, "score_distribution")?,
+        vote_spread: facet(&facets, "vote_spread")?,
+        user_bias: facet(&facets, "user_bias")?,
+    })
+}
+
+async fn cached_report(
+    db: &Database,
+    cache: &AnalyticsCache,
+    key: String,
+    filter: Document,
+    bias_filter: Document
+) -> Result<AnalyticsReport> {
+    if let Some(report) = cache.get(&key) {
+        return Ok(report);
+    }
+    let report = build_report(db, filter, bias_filter).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    cache.put(key, report.clone());
+    Ok(report)
+}
+
+// Historier estimeret anonymt indgår ikke i deltagernes bias
+#[get("/rooms/{room_id}/analytics")]
+pub async fn room_analytics(
+    user: MaybeUser,
+    db: web::Data<Database>,
+    cache: web::Data<AnalyticsCache>,
+    room_id: web::Path<String>,
+    query: web::Query<AnalyticsQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.id_in_room(room_id.as_str())?.to_string();
+    if let Err(response) = check_room_access(&db, &room_id, &user_id).await? {
+        return Ok(response);
+    }
+
+    let report = cached_report(
+        &db,
+        &cache,
+        cache_key("room", &room_id, &query),
+        with_period(doc! { "room_id": room_id.as_str() }, &query),
+        doc! { "anonymous": { "$ne": true } }
+    ).await?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+// Samlet for alle teamets rum
+#[get("/teams/{team_id}/analytics")]
+pub async fn team_analytics(
+    user: ApiUser,
+    db: web::Data<Database>,
+    cache: web::Data<AnalyticsCache>,
+    team_id: web::Path<String>,
+    query: web::Query<AnalyticsQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::RoomsRead)?.to_string();
+    let room_ids = match team_room_ids(&db, &team_id, &user_id).await? {
+        Ok(room_ids) => room_ids,
+        Err(response) => {
+            return Ok(response);
+        }
+    };
+
+    let key = cache_key("team", &team_id, &query);
+
+    let report = cached_report(
+        &db,
+        &cache,
+        key,
+        with_period(doc! { "room_id": { "$in": room_ids } }, &query),
+        doc! { "anonymous": { "$ne": true } }
+    ).await?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+// Historier brugeren har stemt på. Bias vises kun for brugeren selv.
+#[get("/users/me/analytics")]
+pub async fn my_analytics(
+    user: ApiUser,
+    db: web::Data<Database>,
+    cache: web::Data<AnalyticsCache>,
+    query: web::Query<AnalyticsQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::RoomsRead)?.to_string();
+
+    let report = cached_report(
+        &db,
+        &cache,
+        cache_key("user", &user_id, &query),
+        with_period(doc! { "votes.userId": &user_id }, &query),
+        doc! { "votes.userId": &user_id }
+    ).await?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+// Mindste antal historier før en gruppe vurderes
+const MIN_ASSESSMENT_STORIES: u64 = 3;
+// Over denne faktor var indsatsen systematisk større end estimeret
+const UNDER_ESTIMATED_RATIO: f64 = 1.25;
+const OVER_ESTIMATED_RATIO: f64 = 0.8;
+
+fn assess(average_ratio: f64, story_count: u64) -> Assessment {
+    if story_count < MIN_ASSESSMENT_STORIES {
+        Assessment::Insufficient
+    } else if average_ratio > UNDER_ESTIMATED_RATIO {
+        Assessment::UnderEstimated
+    } else if average_ratio < OVER_ESTIMATED_RATIO {
+        Assessment::OverEstimated
+    } else {
+        Assessment::Accurate
+    }
+}
+
+// Gennemsnitlig faktisk indsats pr. point for hver enhed
+async fn effort_baselines(db: &Database, filter: Document) -> Result<Vec<EffortBaseline>, Box<dyn std::error::Error>> {
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! {
+            "$group": {
+                "_id": "$actual.unit",
+                "per_point": { "$avg": { "$divide": ["$actual.value", "$final_score"] } },
+                "story_count": { "$sum": 1 }
+            }
+        },
+        doc! { "$project": { "_id": 0, "unit": "$_id", "per_point": 1, "story_count": 1 } }
+    ];
+    let mut cursor = db.collection::<Document>("completed_stories").aggregate(pipeline, None).await?;
+    let mut baselines = Vec::new();
+    while let Some(baseline) = cursor.try_next().await? {
+        baselines.push(mongodb::bson::from_document(baseline)?);
+    }
+    Ok(baselines)
+}
+
+// Faktisk indsats pr. estimeret point, divideret med enhedens baseline.
+// Timer og dage sammenlignes med periodens gennemsnit, faktiske point direkte med estimatet.
+fn ratio_expression(estimate: &str, baselines: &[EffortBaseline]) -> Document {
+    let branches: Vec<Document> = baselines
+        .iter()
+        .filter(|baseline| baseline.unit != EffortUnit::Points && baseline.per_point > 0.0)
+        .map(|baseline| {
+            doc! {
+                "case": { "$eq": ["$actual.unit", mongodb::bson::to_bson(&baseline.unit).unwrap()] },
+                "then": baseline.per_point
+            }
+        })
+        .collect();
+    let per_point = if branches.is_empty() {
+        // $switch kræver mindst én gren
+        Bson::Double(1.0)
+    } else {
+        Bson::Document(doc! { "$switch": { "branches": branches, "default": 1.0 } })
+    };
+    doc! {
+        "$divide": [{ "$divide": ["$actual.value", estimate] }, per_point]
+    }
+}
+
+fn accuracy_pipeline(filter: Document, baselines: &[EffortBaseline]) -> Vec<Document> {
+    vec![
+        doc! { "$match": filter },
+        doc! { "$addFields": { "ratio": ratio_expression("$final_score", baselines) } },
+        doc! {
+            "$facet": {
+                "totals": [
+                    { "$group": { "_id": null, "story_count": { "$sum": 1 }, "average_ratio": { "$avg": "$ratio" } } },
+                    { "$project": { "_id": 0 } }
+                ],
+                "by_score": [
+                    {
+                        "$group": {
+                            "_id": { "final_score": "$final_score", "unit": "$actual.unit" },
+                            "count": { "$sum": 1 },
+                            "ratio_sum": { "$sum": "$ratio" },
+                            "average": { "$avg": "$actual.value" }
+                        }
+                    },
+                    {
+                        "$group": {
+                            "_id": "$_id.final_score",
+                            "story_count": { "$sum": "$count" },
+                            "ratio_sum": { "$sum": "$ratio_sum" },
+                            "actuals": { "$push": { "unit": "$_id.unit", "average": "$average", "story_count": "$count" } }
+                        }
+                    },
+                    { "$sort": { "_id": 1 } },
+                    {
+                        "$project": {
+                            "_id": 0,
+                            "final_score": "$_id",
+                            "story_count": 1,
+                            "average_ratio": { "$divide": ["$ratio_sum", "$story_count"] },
+                            "actuals": 1
+                        }
+                    }
+                ],
+                "by_tag": [
+                    { "$unwind": "$tags" },
+                    { "$group": { "_id": "$tags", "story_count": { "$sum": 1 }, "average_ratio": { "$avg": "$ratio" } } },
+                    { "$sort": { "story_count": -1 } },
+                    { "$project": { "_id": 0, "tag": "$_id", "story_count": 1, "average_ratio": 1 } }
+                ],
+                // Sammenligner hver deltagers egen stemme med den faktiske indsats
+                "by_person": [
+                    { "$match": { "anonymous": { "$ne": true } } },
+                    { "$unwind": "$votes" },
+                    { "$match": { "votes.value": { "$gt": 0 }, "votes.userId": { "$ne": "" } } },
+                    {
+                        "$group": {
+                            "_id": "$votes.userId",
+                            "username": { "$last": "$votes.username" },
+                            "story_count": { "$sum": 1 },
+                            "average_ratio": { "$avg": ratio_expression("$votes.value", baselines) }
+                        }
+                    },
+                    { "$sort": { "story_count": -1 } },
+                    { "$project": { "_id": 0, "user_id": "$_id", "username": 1, "story_count": 1, "average_ratio": 1 } }
+                ]
+            }
+        }
+    ]
+}
+
+pub async fn build_accuracy_report(db: &Database, filter: Document) -> Result<AccuracyReport, Box<dyn std::error::Error>> {
+    // Kun historier med en registreret indsats og et estimat der kan divideres med
+    let mut filter = filter;
+    filter.insert("actual.value", doc! { "$gt": 0 });
+    filter.insert("final_score", doc! { "$gt": 0 });
+
+    let baselines = effort_baselines(db, filter.clone()).await?;
+    let mut cursor = db
+        .collection::<Document>("completed_stories")
+        .aggregate(accuracy_pipeline(filter, &baselines), None).await?;
+    let facets = cursor.try_next().await?.unwrap_or_default();
+
+    let mut by_score: Vec<ScoreBucketAccuracy> = facet(&facets, "by_score")?;
+    let mut by_person: Vec<PersonAccuracy> = facet(&facets, "by_person")?;
+    let mut by_tag: Vec<TagAccuracy> = facet(&facets, "by_tag")?;
+    let mut flagged = Vec::new();
+
+    for bucket in by_score.iter_mut() {
+        bucket.assessment = assess(bucket.average_ratio, bucket.story_count);
+        if matches!(bucket.assessment, Assessment::UnderEstimated | Assessment::OverEstimated) {
+            flagged.push(FlaggedGroup {
+                kind: "score",
+                key: bucket.final_score.to_string(),
+                assessment: bucket.assessment,
+                average_ratio: bucket.average_ratio,
+                story_count: bucket.story_count,
+            });
+        }
+    }
+    for tag in by_tag.iter_mut() {
+        tag.assessment = assess(tag.average_ratio, tag.story_count);
+        if matches!(tag.assessment, Assessment::UnderEstimated | Assessment::OverEstimated) {
+            flagged.push(FlaggedGroup {
+                kind: "tag",
+                key: tag.tag.clone(),
+                assessment: tag.assessment,
+                average_ratio: tag.average_ratio,
+                story_count: tag.story_count,
+            });
+        }
+    }
+    for person in by_person.iter_mut() {
+        person.assessment = assess(person.average_ratio, person.story_count);
+        if matches!(person.assessment, Assessment::UnderEstimated | Assessment::OverEstimated) {
+            flagged.push(FlaggedGroup {
+                kind: "person",
+                key: person.user_id.clone(),
+                assessment: person.assessment,
+                average_ratio: person.average_ratio,
+                story_count: person.story_count,
+            });
+        }
+    }
+
+    Ok(AccuracyReport {
+        generated_at: now_secs(),
+        totals: facet::<AccuracyTotals>(&facets, "totals")?.into_iter().next().unwrap_or_default(),
+        baselines,
+        by_score,
+        by_person,
+        by_tag,
+        flagged,
+    })
+}
+
+#[get("/rooms/{room_id}/accuracy")]
+pub async fn room_accuracy(
+    user: MaybeUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>,
+    query: web::Query<AnalyticsQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.id_in_room(room_id.as_str())?.to_string();
+    if let Err(response) = check_room_access(&db, &room_id, &user_id).await? {
+        return Ok(response);
+    }
+
+    let report = build_accuracy_report(&db, with_period(doc! { "room_id": room_id.as_str() }, &query)).await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[get("/teams/{team_id}/accuracy")]
+pub async fn team_accuracy(
+    user: ApiUser,
+    db: web::Data<Database>,
+    team_id: web::Path<String>,
+    query: web::Query<AnalyticsQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::RoomsRead)?.to_string();
+    let room_ids = match team_room_ids(&db, &team_id, &user_id).await? {
+        Ok(room_ids) => room_ids,
+        Err(response) => {
+            return Ok(response);
+        }
+    };
+
+    let report = build_accuracy_report(&db, with_period(doc! { "room_id": { "$in": room_ids } }, &query)).await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,168 @@
+use actix_web::{ get, post, delete, web, HttpResponse, Result, error::ErrorInternalServerError };
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::Database;
+use crate::handlers::auth::{ generate_token, hash_token, now_secs };
+use crate::middleware::auth::{ AuthUser, API_TOKEN_PREFIX };
+use crate::models::api_token::{ ApiToken, ApiTokenResponse, CreateApiTokenDto, CreatedApiTokenResponse };
+
+const MAX_TOKEN_NAME_LENGTH: usize = 100;
+const MAX_TOKEN_LIFETIME_DAYS: i64 = 365;
+const MAX_ACTIVE_TOKENS: u64 = 50;
+// Antal tegn af tokenet der vises i oversigten
+const TOKEN_PREFIX_LENGTH: usize = 10;
+
+// Tokens administreres kun med en rigtig login-session, ikke med et andet token
+#[post("/users/me/tokens")]
+pub async fn create_api_token(
+    user: AuthUser,
+    db: web::Data<Database>,
+    token_data: web::Json<CreateApiTokenDto>
+) -> Result<HttpResponse> {
+    let name = token_data.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("Navnet skal være mellem 1 og {} tegn", MAX_TOKEN_NAME_LENGTH)
+                })
+            )
+        );
+    }
+    if token_data.scopes.is_empty() {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Vælg mindst én adgang til tokenet"
+                })
+            )
+        );
+    }
+    if let Some(days) = token_data.expires_in_days {
+        if !(1..=MAX_TOKEN_LIFETIME_DAYS).contains(&days) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": format!("Udløb skal være mellem 1 og {} dage", MAX_TOKEN_LIFETIME_DAYS)
+                    })
+                )
+            );
+        }
+    }
+
+    let collection = db.collection::<ApiToken>("api_tokens");
+    let now = now_secs();
+    let active_tokens = collection
+        .count_documents(
+            doc! {
+                "user_id": &user.id,
+                "revoked_at": null,
+                "$or": [{ "expires_at": null }, { "expires_at": { "$gt": now } }]
+            },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+    if active_tokens >= MAX_ACTIVE_TOKENS {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("Du kan højst have {} aktive tokens", MAX_ACTIVE_TOKENS)
+                })
+            )
+        );
+    }
+
+    let mut scopes = token_data.scopes.clone();
+    scopes.sort_unstable();
+    scopes.dedup();
+    let token = format!("{}{}", API_TOKEN_PREFIX, generate_token());
+    let mut api_token = ApiToken {
+        id: None,
+        user_id: user.id.clone(),
+        name: name.to_string(),
+        token_hash: hash_token(&token),
+        token_prefix: token[..TOKEN_PREFIX_LENGTH].to_string(),
+        scopes,
+        created_at: now,
+        last_used_at: None,
+        expires_at: token_data.expires_in_days.map(|days| now + days * 24 * 3600),
+        revoked_at: None,
+    };
+
+    let result = collection.insert_one(&api_token, None).await.map_err(ErrorInternalServerError)?;
+    api_token.id = result.inserted_id.as_object_id();
+    println!("API token '{}' oprettet for bruger {}", api_token.name, user.id);
+
+    Ok(
+        HttpResponse::Created().json(CreatedApiTokenResponse {
+            token,
+            info: api_token.into(),
+        })
+    )
+}
+
+#[get("/users/me/tokens")]
+pub async fn list_api_tokens(user: AuthUser, db: web::Data<Database>) -> Result<HttpResponse> {
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .build();
+    let mut cursor = db
+        .collection::<ApiToken>("api_tokens")
+        .find(doc! { "user_id": &user.id }, options).await
+        .map_err(ErrorInternalServerError)?;
+
+    let mut tokens: Vec<ApiTokenResponse> = Vec::new();
+    while let Some(token) = cursor.try_next().await.map_err(ErrorInternalServerError)? {
+        tokens.push(token.into());
+    }
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[delete("/users/me/tokens/{token_id}")]
+pub async fn revoke_api_token(
+    user: AuthUser,
+    db: web::Data<Database>,
+    token_id: web::Path<String>
+) -> Result<HttpResponse> {
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(token_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt token ID"
+                    })
+                )
+            );
+        }
+    };
+
+    let result = db
+        .collection::<ApiToken>("api_tokens")
+        .update_one(
+            doc! { "_id": object_id, "user_id": &user.id, "revoked_at": null },
+            doc! { "$set": { "revoked_at": now_secs() } },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Token ikke fundet"
+                })
+            )
+        );
+    }
+
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "message": "Tokenet er tilbagekaldt"
+            })
+        )
+    )
+}
@@ -1,22 +1,46 @@
-use actix_web::{post, get, web, HttpResponse, Result, error::ErrorInternalServerError};
+use actix_web::{post, get, web, HttpRequest, HttpResponse, Result, error::ErrorInternalServerError};
 use bcrypt::{hash, verify, DEFAULT_COST};
 use jsonwebtoken::{encode, EncodingKey, Header};
+use mongodb::bson::doc;
 use mongodb::Database;
-use serde::{Deserialize, Serialize};
-use crate::models::user::{User, CreateUserDto, LoginDto, AuthResponse, UserResponse};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use crate::config::get_jwt_secret;
+use crate::handlers::two_factor::start_login_challenge;
+use crate::handlers::user::merge_guest_into_user;
+use crate::mailer::{app_base_url, Mail, Mailer};
+use crate::middleware::auth::{AuthUser, Claims, decode_guest_token};
+use crate::models::session::{Session, RefreshTokenDto};
+use crate::oidc::sso_required;
+use crate::rate_limit::{client_ip, Quota, RateLimiter};
+use crate::models::user::{
+    User, CreateUserDto, LoginDto, AuthResponse, TokenResponse, UserResponse, UserToken, TokenPurpose,
+    PasswordResetRequestDto, PasswordResetConfirmDto, VerifyEmailDto,
+};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    exp: usize,
-}
+// Access tokens er kortlivede; klienten fornyer dem med sit refresh token
+const ACCESS_TOKEN_TTL_SECS: i64 = 15 * 60;
+// Et refresh token der ikke bruges i 30 dage udløber
+const REFRESH_TOKEN_TTL_SECS: i64 = 30 * 24 * 3600;
+const PASSWORD_RESET_TTL_SECS: i64 = 3600;
+pub(crate) const EMAIL_VERIFICATION_TTL_SECS: i64 = 48 * 3600;
 
 #[post("/auth/register")]
 pub async fn register(
+    req: HttpRequest,
     db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+    limiter: web::Data<RateLimiter>,
     user_data: web::Json<CreateUserDto>,
 ) -> Result<HttpResponse> {
+    if sso_required() {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "message": "Log ind med din organisations single sign-on"
+        })));
+    }
+    limiter.check_quota(Quota::Register, &client_ip(&req)).await?;
+
     let collection = db.collection::<User>("users");
     
     // Check if user already exists
@@ -32,6 +56,12 @@ pub async fn register(
         })));
     }
 
+    // Et ugyldigt gæstetoken afvises før kontoen oprettes
+    let guest_id = match guest_id_from_token(user_data.guest_token.as_deref()) {
+        Ok(guest_id) => guest_id,
+        Err(response) => return Ok(response),
+    };
+
     // Hash password
     let password_hash = hash(user_data.password.as_bytes(), DEFAULT_COST)
         .map_err(ErrorInternalServerError)?;
@@ -43,36 +73,67 @@ pub async fn register(
         username: user_data.username.clone(),
         password_hash,
         profile_image: user_data.profile_image.clone(),
+        email_verified: false,
+        identities: Vec::new(),
+        two_factor: None,
+        is_admin: false,
     };
 
     // Insert user into database
     let insert_result = collection.insert_one(&new_user, None).await
         .map_err(ErrorInternalServerError)?;
 
-    // Generate JWT
-    let token = create_jwt(insert_result.inserted_id.as_object_id().unwrap().to_string())?;
+    let user_id = insert_result.inserted_id.as_object_id().unwrap().to_string();
+    let mut profile_image = new_user.profile_image;
+    if let Some(guest_id) = guest_id {
+        profile_image = merge_guest_into_user(&db, &guest_id, &user_id)
+            .await
+            .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    }
+
+    // En fejl ved afsendelse må ikke forhindre oprettelsen; brugeren kan få mailen igen
+    if let Err(e) = send_verification_mail(&db, mailer.get_ref(), &user_id, &new_user.email).await {
+        println!("Kunne ikke sende bekræftelsesmail: {}", e);
+    }
+
+    // Start en ny session
+    let tokens = create_session(&db, &user_id, &req).await?;
 
     let user_response = UserResponse {
-        id: insert_result.inserted_id.as_object_id().unwrap().to_string(),
+        id: user_id,
         email: new_user.email,
         username: new_user.username,
-        profile_image: new_user.profile_image,
+        profile_image,
+        email_verified: false,
+        two_factor_enabled: false,
     };
 
     Ok(HttpResponse::Ok().json(AuthResponse {
-        token,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
         user: user_response,
     }))
 }
 
 #[post("/auth/login")]
 pub async fn login(
+    req: HttpRequest,
     db: web::Data<Database>,
+    limiter: web::Data<RateLimiter>,
     login_data: web::Json<LoginDto>,
 ) -> Result<HttpResponse> {
+    if sso_required() {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "message": "Log ind med din organisations single sign-on"
+        })));
+    }
+
+    // Låste konti og IP'er afvises før bcrypt, så forsøgene også er billige for serveren
+    let ip = client_ip(&req);
+    limiter.check_login(&ip, &login_data.email).await?;
+
     let collection = db.collection::<User>("users");
-    println!("Login data: {:?}", login_data);
-    println!("Email: {:?}", login_data.email);
     // Find user by email
     let user = match collection
         .find_one(
@@ -84,46 +145,91 @@ pub async fn login(
     {
         Some(user) => user,
         None => {
+            limiter.login_failed(&ip, &login_data.email).await;
             return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
                 "message": "Ugyldig email eller adgangskode"
             })));
         }
     };
-    println!("User: {:?}", user);
 
     // Verify password
     if !verify(&login_data.password, &user.password_hash)
         .map_err(ErrorInternalServerError)?
     {
+        limiter.login_failed(&ip, &login_data.email).await;
         return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
             "message": "Ugyldig email eller adgangskode"
         })));
     }
 
-    // Generate JWT
-    let token = create_jwt(user.id.unwrap().to_string())?;
+    if require_verified_email() && !user.email_verified {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "message": "Bekræft din email før du logger ind",
+            "email_not_verified": true
+        })));
+    }
+
+    let guest_id = match guest_id_from_token(login_data.guest_token.as_deref()) {
+        Ok(guest_id) => guest_id,
+        Err(response) => return Ok(response),
+    };
+
+    // Med to-faktor slået til udstedes sessionen først når koden er bekræftet
+    if user.two_factor.as_ref().is_some_and(|two_factor| two_factor.enabled) {
+        let user_id = user.id.unwrap().to_string();
+        let challenge = start_login_challenge(&db, &user_id, guest_id)
+            .await
+            .map_err(ErrorInternalServerError)?;
+        return Ok(HttpResponse::Ok().json(challenge));
+    }
+
+    limiter.login_succeeded(&login_data.email).await;
+    complete_login(&db, &req, user, guest_id).await
+}
+
+// Fletter en eventuel gæst ind i kontoen og starter en ny session
+pub(crate) async fn complete_login(
+    db: &Database,
+    req: &HttpRequest,
+    user: User,
+    guest_id: Option<String>,
+) -> Result<HttpResponse> {
+    let user_id = user.id.unwrap().to_string();
+    let mut profile_image = user.profile_image;
+    if let Some(guest_id) = guest_id {
+        profile_image = merge_guest_into_user(db, &guest_id, &user_id)
+            .await
+            .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    }
+
+    // Start en ny session
+    let tokens = create_session(db, &user_id, req).await?;
 
     let user_response = UserResponse {
-        id: user.id.unwrap().to_string(),
+        id: user_id,
         email: user.email,
         username: user.username,
-        profile_image: user.profile_image,
+        profile_image,
+        email_verified: user.email_verified,
+        two_factor_enabled: user.two_factor.is_some_and(|two_factor| two_factor.enabled),
     };
 
     Ok(HttpResponse::Ok().json(AuthResponse {
-        token,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
         user: user_response,
     }))
 }
 
 #[get("/auth/me")]
 pub async fn get_me(
+    user: AuthUser,
     db: web::Data<Database>,
-    user_id: String,
 ) -> Result<HttpResponse> {
     let collection = db.collection::<User>("users");
-    
-    let object_id = mongodb::bson::oid::ObjectId::parse_str(&user_id)
+
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(&user.id)
         .map_err(|_| ErrorInternalServerError("Ugyldigt bruger ID"))?;
 
     // Find user by ID
@@ -148,28 +254,499 @@ pub async fn get_me(
         email: user.email,
         username: user.username,
         profile_image: user.profile_image,
+        email_verified: user.email_verified,
+        two_factor_enabled: user.two_factor.is_some_and(|two_factor| two_factor.enabled),
     };
 
     Ok(HttpResponse::Ok().json(user_response))
 }
 
-fn create_jwt(user_id: String) -> Result<String> {
-    let expiration = SystemTime::now()
+#[post("/auth/refresh")]
+pub async fn refresh(
+    db: web::Data<Database>,
+    refresh_data: web::Json<RefreshTokenDto>,
+) -> Result<HttpResponse> {
+    let collection = db.collection::<Session>("sessions");
+    let token_hash = hash_token(&refresh_data.refresh_token);
+    let now = now_secs();
+
+    // Roter tokenet atomisk, så det samme refresh token kun kan bruges én gang
+    let new_refresh_token = generate_token();
+    let session = collection
+        .find_one_and_update(
+            doc! {
+                "refresh_token_hash": &token_hash,
+                "revoked_at": null,
+                "expires_at": { "$gt": now }
+            },
+            doc! {
+                "$set": {
+                    "refresh_token_hash": hash_token(&new_refresh_token),
+                    "previous_refresh_token_hash": &token_hash,
+                    "last_used_at": now,
+                    "expires_at": now + REFRESH_TOKEN_TTL_SECS
+                }
+            },
+            None,
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let session = match session {
+        Some(session) => session,
+        None => {
+            // Et allerede roteret token der bruges igen tyder på at det er lækket.
+            // Luk hele sessionen så både angriber og bruger skal logge ind igen.
+            let reuse = collection
+                .update_one(
+                    doc! { "previous_refresh_token_hash": &token_hash, "revoked_at": null },
+                    doc! { "$set": { "revoked_at": now } },
+                    None,
+                )
+                .await
+                .map_err(ErrorInternalServerError)?;
+            if reuse.modified_count > 0 {
+                println!("Genbrug af refresh token opdaget - session tilbagekaldt");
+            }
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "message": "Ugyldigt eller udløbet refresh token"
+            })));
+        }
+    };
+
+    let session_id = session.id.unwrap().to_string();
+    let token = create_jwt(session.user_id, session_id)?;
+
+    Ok(HttpResponse::Ok().json(TokenResponse {
+        token,
+        refresh_token: new_refresh_token,
+        expires_in: ACCESS_TOKEN_TTL_SECS,
+    }))
+}
+
+// Log ud af den session det aktuelle access token tilhører
+#[post("/auth/logout")]
+pub async fn logout(
+    user: AuthUser,
+    db: web::Data<Database>,
+) -> Result<HttpResponse> {
+    let session_id = mongodb::bson::oid::ObjectId::parse_str(&user.session_id)
+        .map_err(|_| ErrorInternalServerError("Ugyldigt session ID"))?;
+
+    db.collection::<Session>("sessions")
+        .update_one(
+            doc! { "_id": session_id, "user_id": &user.id },
+            doc! { "$set": { "revoked_at": now_secs() } },
+            None,
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Du er logget ud"
+    })))
+}
+
+// Log ud på alle enheder, fx hvis et token er lækket
+#[post("/auth/logout-all")]
+pub async fn logout_all(
+    user: AuthUser,
+    db: web::Data<Database>,
+) -> Result<HttpResponse> {
+    let revoked = revoke_all_sessions(&db, &user.id)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Du er logget ud på alle enheder",
+        "revoked_sessions": revoked
+    })))
+}
+
+pub async fn revoke_all_sessions(db: &Database, user_id: &str) -> mongodb::error::Result<u64> {
+    let result = db
+        .collection::<Session>("sessions")
+        .update_many(
+            doc! { "user_id": user_id, "revoked_at": null },
+            doc! { "$set": { "revoked_at": now_secs() } },
+            None,
+        )
+        .await?;
+    Ok(result.modified_count)
+}
+
+// Logger alle andre sessioner ud, fx når adgangskoden er skiftet
+pub async fn revoke_other_sessions(db: &Database, user_id: &str, session_id: &str) -> mongodb::error::Result<u64> {
+    let keep = mongodb::bson::oid::ObjectId::parse_str(session_id)
+        .map_err(|e| mongodb::error::Error::custom(e.to_string()))?;
+    let result = db
+        .collection::<Session>("sessions")
+        .update_many(
+            doc! { "user_id": user_id, "revoked_at": null, "_id": { "$ne": keep } },
+            doc! { "$set": { "revoked_at": now_secs() } },
+            None,
+        )
+        .await?;
+    Ok(result.modified_count)
+}
+
+// Svarer altid ens, så endpointet ikke afslører hvilke emails der har en konto
+#[post("/auth/password-reset/request")]
+pub async fn request_password_reset(
+    req: HttpRequest,
+    db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+    limiter: web::Data<RateLimiter>,
+    reset_data: web::Json<PasswordResetRequestDto>,
+) -> Result<HttpResponse> {
+    limiter.check_quota(Quota::PasswordReset, &client_ip(&req)).await?;
+
+    let user = db.collection::<User>("users")
+        .find_one(doc! { "email": &reset_data.email }, None)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(user) = user {
+        let user_id = user.id.unwrap().to_string();
+        let token = issue_user_token(&db, &user_id, TokenPurpose::PasswordReset, PASSWORD_RESET_TTL_SECS, None)
+            .await
+            .map_err(ErrorInternalServerError)?;
+        let mail = Mail {
+            to: user.email,
+            subject: "Nulstil din adgangskode".to_string(),
+            body: format!(
+                "Hej {}\n\nBrug linket herunder til at vælge en ny adgangskode. Linket udløber om en time.\n\n{}/reset-password?token={}\n\nHvis du ikke har bedt om at nulstille din adgangskode, kan du se bort fra denne mail.",
+                user.username,
+                app_base_url(),
+                token
+            ),
+        };
+        if let Err(e) = mailer.send(mail).await {
+            println!("Kunne ikke sende mail om nulstilling: {}", e);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Hvis emailen findes, har vi sendt et link til at nulstille adgangskoden"
+    })))
+}
+
+#[post("/auth/password-reset/confirm")]
+pub async fn confirm_password_reset(
+    db: web::Data<Database>,
+    reset_data: web::Json<PasswordResetConfirmDto>,
+) -> Result<HttpResponse> {
+    let user_id = match consume_user_token(&db, &reset_data.token, TokenPurpose::PasswordReset)
+        .await
+        .map_err(ErrorInternalServerError)?
+    {
+        Some(user_token) => user_token.user_id,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "message": "Linket er ugyldigt eller udløbet"
+            })));
+        }
+    };
+
+    let password_hash = hash(reset_data.new_password.as_bytes(), DEFAULT_COST)
+        .map_err(ErrorInternalServerError)?;
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(&user_id)
+        .map_err(|_| ErrorInternalServerError("Ugyldigt bruger ID"))?;
+
+    // Linket kom frem til brugerens indbakke, så emailen er samtidig bekræftet
+    db.collection::<User>("users")
+        .update_one(
+            doc! { "_id": object_id },
+            doc! { "$set": { "password_hash": password_hash, "email_verified": true } },
+            None,
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    // Den gamle adgangskode kan være kompromitteret
+    revoke_all_sessions(&db, &user_id)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Din adgangskode er ændret. Log ind med den nye adgangskode."
+    })))
+}
+
+#[post("/auth/verify-email")]
+pub async fn verify_email(
+    db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+    verify_data: web::Json<VerifyEmailDto>,
+) -> Result<HttpResponse> {
+    let user_token = match consume_user_token(&db, &verify_data.token, TokenPurpose::EmailVerification)
+        .await
+        .map_err(ErrorInternalServerError)?
+    {
+        Some(user_token) => user_token,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "message": "Linket er ugyldigt eller udløbet"
+            })));
+        }
+    };
+
+    let collection = db.collection::<User>("users");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(&user_token.user_id)
+        .map_err(|_| ErrorInternalServerError("Ugyldigt bruger ID"))?;
+    let user = collection
+        .find_one(doc! { "_id": object_id }, None)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorInternalServerError("Bruger ikke fundet"))?;
+
+    // Et token til en ny adresse afslutter et skift af email
+    let new_email = user_token.email.filter(|email| *email != user.email);
+    if let Some(new_email) = &new_email {
+        if collection
+            .find_one(doc! { "email": new_email }, None)
+            .await
+            .map_err(ErrorInternalServerError)?
+            .is_some()
+        {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "message": "Emailen bruges allerede af en anden konto"
+            })));
+        }
+    }
+
+    let email = new_email.clone().unwrap_or_else(|| user.email.clone());
+    collection
+        .update_one(
+            doc! { "_id": object_id },
+            doc! { "$set": { "email": &email, "email_verified": true } },
+            None,
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if new_email.is_some() {
+        // Den gamle adresse får besked, så et skift man ikke selv har lavet opdages
+        let notice = Mail {
+            to: user.email,
+            subject: "Din email er ændret".to_string(),
+            body: format!(
+                "Hej {}\n\nEmailen på din konto er ændret til {}.\n\nHvis det ikke var dig, så nulstil din adgangskode med det samme.",
+                user.username,
+                email
+            ),
+        };
+        if let Err(e) = mailer.send(notice).await {
+            println!("Kunne ikke sende besked om ændret email: {}", e);
+        }
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Din email er ændret",
+            "email": email
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Din email er bekræftet"
+    })))
+}
+
+#[post("/auth/verify-email/resend")]
+pub async fn resend_verification_email(
+    user: AuthUser,
+    db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+) -> Result<HttpResponse> {
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(&user.id)
+        .map_err(|_| ErrorInternalServerError("Ugyldigt bruger ID"))?;
+    let account = db.collection::<User>("users")
+        .find_one(doc! { "_id": object_id }, None)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorInternalServerError("Bruger ikke fundet"))?;
+
+    if account.email_verified {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "message": "Din email er allerede bekræftet"
+        })));
+    }
+
+    send_verification_mail(&db, mailer.get_ref(), &user.id, &account.email)
+        .await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Vi har sendt en ny bekræftelsesmail"
+    })))
+}
+
+async fn send_verification_mail(
+    db: &Database,
+    mailer: &dyn Mailer,
+    user_id: &str,
+    email: &str,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let token = issue_user_token(db, user_id, TokenPurpose::EmailVerification, EMAIL_VERIFICATION_TTL_SECS, Some(email)).await?;
+    mailer.send(Mail {
+        to: email.to_string(),
+        subject: "Bekræft din email".to_string(),
+        body: format!(
+            "Velkommen!\n\nBekræft din email ved at åbne linket herunder. Linket udløber om 48 timer.\n\n{}/verify-email?token={}",
+            app_base_url(),
+            token
+        ),
+    }).await?;
+    Ok(())
+}
+
+// Opretter et nyt engangstoken og ugyldiggør brugerens tidligere tokens til samme formål
+pub(crate) async fn issue_user_token(
+    db: &Database,
+    user_id: &str,
+    purpose: TokenPurpose,
+    ttl_secs: i64,
+    email: Option<&str>,
+) -> mongodb::error::Result<String> {
+    let collection = db.collection::<UserToken>("user_tokens");
+    let now = now_secs();
+    let purpose_bson = mongodb::bson::to_bson(&purpose)?;
+
+    collection
+        .update_many(
+            doc! { "user_id": user_id, "purpose": purpose_bson, "used_at": null },
+            doc! { "$set": { "used_at": now } },
+            None,
+        )
+        .await?;
+
+    let token = generate_token();
+    collection
+        .insert_one(
+            UserToken {
+                id: None,
+                user_id: user_id.to_string(),
+                purpose,
+                token_hash: hash_token(&token),
+                email: email.map(|email| email.to_string()),
+                created_at: now,
+                expires_at: now + ttl_secs,
+                used_at: None,
+            },
+            None,
+        )
+        .await?;
+    Ok(token)
+}
+
+// Bruger et engangstoken, hvis det er gyldigt
+pub(crate) async fn consume_user_token(
+    db: &Database,
+    token: &str,
+    purpose: TokenPurpose,
+) -> mongodb::error::Result<Option<UserToken>> {
+    let now = now_secs();
+    let user_token = db.collection::<UserToken>("user_tokens")
+        .find_one_and_update(
+            doc! {
+                "token_hash": hash_token(token),
+                "purpose": mongodb::bson::to_bson(&purpose)?,
+                "used_at": null,
+                "expires_at": { "$gt": now }
+            },
+            doc! { "$set": { "used_at": now } },
+            None,
+        )
+        .await?;
+    Ok(user_token)
+}
+
+fn require_verified_email() -> bool {
+    std::env::var("REQUIRE_VERIFIED_EMAIL").map(|v| v == "true").unwrap_or(false)
+}
+
+// Gæstens id fra et valgfrit gæstetoken. Et ugyldigt token giver et 400 svar.
+pub(crate) fn guest_id_from_token(guest_token: Option<&str>) -> std::result::Result<Option<String>, HttpResponse> {
+    match guest_token {
+        Some(token) => decode_guest_token(token)
+            .map(|claims| Some(claims.sub))
+            .map_err(|_| HttpResponse::BadRequest().json(serde_json::json!({
+                "message": "Ugyldigt eller udløbet gæstetoken"
+            }))),
+        None => Ok(None),
+    }
+}
+
+pub(crate) struct SessionTokens {
+    pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+pub(crate) async fn create_session(db: &Database, user_id: &str, req: &HttpRequest) -> Result<SessionTokens> {
+    let now = now_secs();
+    let refresh_token = generate_token();
+    let user_agent = req
+        .headers()
+        .get(actix_web::http::header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    let session = Session {
+        id: None,
+        user_id: user_id.to_string(),
+        refresh_token_hash: hash_token(&refresh_token),
+        previous_refresh_token_hash: None,
+        user_agent,
+        created_at: now,
+        last_used_at: now,
+        expires_at: now + REFRESH_TOKEN_TTL_SECS,
+        revoked_at: None,
+    };
+
+    let insert_result = db.collection::<Session>("sessions")
+        .insert_one(&session, None)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let session_id = insert_result.inserted_id.as_object_id().unwrap().to_string();
+
+    Ok(SessionTokens {
+        token: create_jwt(user_id.to_string(), session_id)?,
+        refresh_token,
+        expires_in: ACCESS_TOKEN_TTL_SECS,
+    })
+}
+
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub(crate) fn now_secs() -> i64 {
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
-        .as_secs() as usize + 24 * 3600; // 24 timer
+        .as_secs() as i64
+}
+
+fn create_jwt(user_id: String, session_id: String) -> Result<String> {
+    let expiration = (now_secs() + ACCESS_TOKEN_TTL_SECS) as usize;
 
     let claims = Claims {
         sub: user_id,
         exp: expiration,
+        sid: session_id,
     };
 
     let token = encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(std::env::var("JWT_SECRET").expect("JWT_SECRET skal være sat").as_bytes()),
+        &EncodingKey::from_secret(get_jwt_secret().as_bytes()),
     )
     .map_err(ErrorInternalServerError)?;
 
     Ok(token)
-} 
\ No newline at end of file
+}
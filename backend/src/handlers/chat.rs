@@ -0,0 +1,174 @@
+use actix_web::{ get, web, HttpResponse, Result, error::ErrorInternalServerError };
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::Database;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use crate::middleware::auth::MaybeUser;
+use crate::models::chat::{ ChatMessage, ChatMessageResponse, ChatHistoryResponse, ChatHistoryQuery };
+use crate::models::game_room::GameRoom;
+
+// Maksimal længde på en chatbesked (i tegn)
+pub const MAX_CHAT_LENGTH: usize = 1000;
+// Antal beskeder der hentes pr. side hvis klienten ikke angiver andet
+pub const CHAT_PAGE_SIZE: i64 = 50;
+const MAX_CHAT_PAGE_SIZE: i64 = 200;
+
+// Validerer og normaliserer en chatbesked før den gemmes
+pub fn normalize_chat_text(text: &str) -> Result<String, &'static str> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Beskeden er tom");
+    }
+    if text.chars().count() > MAX_CHAT_LENGTH {
+        return Err("Beskeden er for lang");
+    }
+    Ok(text.to_string())
+}
+
+pub async fn save_chat_message(
+    db: &Database,
+    room_id: &str,
+    user_id: &str,
+    username: &str,
+    profile_image: Option<String>,
+    text: String
+) -> Result<ChatMessageResponse, Box<dyn std::error::Error>> {
+    let rooms_collection = db.collection::<GameRoom>("game_rooms");
+    let messages_collection = db.collection::<ChatMessage>("room_messages");
+
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
+    let room = rooms_collection
+        .find_one(doc! { "_id": object_id }, None).await?
+        .ok_or("Rum ikke fundet")?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    let mut message = ChatMessage {
+        id: None,
+        room_id: room_id.to_string(),
+        story_id: room.current_story.map(|story| story.id),
+        user_id: user_id.to_string(),
+        username: username.to_string(),
+        profile_image,
+        text,
+        created_at: now,
+    };
+
+    let insert_result = messages_collection.insert_one(&message, None).await?;
+    message.id = insert_result.inserted_id.as_object_id();
+
+    Ok(message.into())
+}
+
+// Henter en side af rummets chathistorik, nyeste først.
+// `before` er id'et på den ældste besked klienten allerede har.
+pub async fn load_chat_history(
+    db: &Database,
+    room_id: &str,
+    before: Option<&str>,
+    limit: i64
+) -> Result<ChatHistoryResponse, Box<dyn std::error::Error>> {
+    let messages_collection = db.collection::<ChatMessage>("room_messages");
+    let limit = limit.clamp(1, MAX_CHAT_PAGE_SIZE);
+
+    let mut filter = doc! { "room_id": room_id };
+    if let Some(before) = before {
+        let before_id = mongodb::bson::oid::ObjectId::parse_str(before)?;
+        filter.insert("_id", doc! { "$lt": before_id });
+    }
+
+    // Hent én ekstra for at vide om der er flere sider
+    let options = FindOptions::builder()
+        .sort(doc! { "_id": -1 })
+        .limit(limit + 1)
+        .build();
+
+    let mut cursor = messages_collection.find(filter, options).await?;
+    let mut messages: Vec<ChatMessageResponse> = Vec::new();
+    while let Some(message) = cursor.try_next().await? {
+        messages.push(message.into());
+    }
+
+    let has_more = messages.len() as i64 > limit;
+    messages.truncate(limit as usize);
+
+    Ok(ChatHistoryResponse { messages, has_more })
+}
+
+// Returnerer true hvis beskeden fandtes og blev slettet
+pub async fn delete_chat_message(
+    db: &Database,
+    room_id: &str,
+    message_id: &str
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let messages_collection = db.collection::<ChatMessage>("room_messages");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(message_id)?;
+
+    let delete_result = messages_collection.delete_one(
+        doc! { "_id": object_id, "room_id": room_id },
+        None
+    ).await?;
+
+    Ok(delete_result.deleted_count > 0)
+}
+
+#[get("/rooms/{room_id}/messages")]
+pub async fn get_room_messages(
+    user: MaybeUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>,
+    query: web::Query<ChatHistoryQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.id_in_room(room_id.as_str())?.to_string();
+
+    let rooms_collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(room_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt rum ID"
+                    })
+                )
+            );
+        }
+    };
+
+    let room = match
+        rooms_collection
+            .find_one(doc! { "_id": object_id }, None).await
+            .map_err(ErrorInternalServerError)?
+    {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Spilrum ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+
+    if !room.participants.contains(&user_id) {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Du har ikke adgang til dette spilrum"
+                })
+            )
+        );
+    }
+
+    let history = load_chat_history(
+        &db,
+        room_id.as_str(),
+        query.before.as_deref(),
+        query.limit.unwrap_or(CHAT_PAGE_SIZE)
+    ).await.map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(history))
+}
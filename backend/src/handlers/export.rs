@@ -0,0 +1,283 @@
+use actix_web::{ get, web, HttpResponse, Result, error::ErrorInternalServerError };
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::{ doc, oid::ObjectId };
+use mongodb::Database;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use crate::middleware::auth::{ ApiUser, AdminUser };
+use crate::models::api_token::TokenScope;
+use crate::models::chat::ChatMessage;
+use crate::models::export::{
+    UserExport,
+    AccountExport,
+    RoomMembershipExport,
+    TeamMembershipExport,
+    VoteExport,
+    ConfidenceVoteExport,
+    DotVoteExport,
+    SessionExport,
+    ExportQuery,
+};
+use crate::models::api_token::ApiToken;
+use crate::models::team::Team;
+use crate::models::game_room::{ GameRoom, CompletedStory };
+use crate::models::notification::Notification;
+use crate::models::session::Session;
+use crate::models::user::User;
+
+#[get("/users/me/export")]
+pub async fn export_my_data(
+    user: ApiUser,
+    db: web::Data<Database>,
+    query: web::Query<ExportQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::Export)?;
+    export_response(&db, user_id, &query).await
+}
+
+// Administratorens udtræk for en anden bruger, fx når anmodningen kommer via support
+#[get("/admin/users/{user_id}/export")]
+pub async fn export_user_data(
+    admin: AdminUser,
+    db: web::Data<Database>,
+    user_id: web::Path<String>,
+    query: web::Query<ExportQuery>
+) -> Result<HttpResponse> {
+    println!("Administrator {} eksporterer data for bruger {}", admin.id, user_id);
+    export_response(&db, &user_id, &query).await
+}
+
+async fn export_response(db: &Database, user_id: &str, query: &ExportQuery) -> Result<HttpResponse> {
+    let Some((export, images)) = build_export(db, user_id).await.map_err(|e|
+        ErrorInternalServerError(e.to_string())
+    )? else {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Bruger ikke fundet"
+                })
+            )
+        );
+    };
+
+    if query.format.as_deref() == Some("json") {
+        return Ok(HttpResponse::Ok().json(export));
+    }
+
+    let filename = format!("estimer-export-{}.zip", user_id);
+    let archive = web
+        ::block(move || write_archive(&export, &images)).await
+        .map_err(ErrorInternalServerError)?
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+
+    Ok(
+        HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header((
+                actix_web::http::header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ))
+            .body(archive)
+    )
+}
+
+// Samler brugerens data. Billeder returneres som (sti i arkivet, fil på disk).
+async fn build_export(
+    db: &Database,
+    user_id: &str
+) -> Result<Option<(UserExport, Vec<(String, PathBuf)>)>, Box<dyn std::error::Error>> {
+    let Ok(object_id) = ObjectId::parse_str(user_id) else {
+        return Ok(None);
+    };
+    let Some(user) = db.collection::<User>("users").find_one(doc! { "_id": object_id }, None).await? else {
+        return Ok(None);
+    };
+
+    let mut rooms = Vec::new();
+    let mut votes = Vec::new();
+    let mut dot_votes = Vec::new();
+
+    // Rum brugeren er med i, eller har stemt i før de forlod det
+    let room_filter =
+        doc! {
+        "$or": [
+            { "participants": user_id },
+            { "admin_id": user_id },
+            { "current_story.votes.userId": user_id },
+            { "stories.votes.userId": user_id },
+            { "completed_stories.votes.userId": user_id },
+            { format!("dot_votes.{}", user_id): { "$exists": true } },
+        ]
+    };
+    let mut cursor = db.collection::<GameRoom>("game_rooms").find(room_filter, None).await?;
+    while let Some(room) = cursor.try_next().await? {
+        let room_id = room.id.map(|id| id.to_string()).unwrap_or_default();
+        if room.participants.iter().any(|participant| participant == user_id) {
+            rooms.push(RoomMembershipExport {
+                room_id: room_id.clone(),
+                name: room.name.clone(),
+                is_admin: room.admin_id == user_id,
+                created_at: room.created_at,
+            });
+        }
+
+        // Samme historie kan ligge både som aktiv, i `stories` og i `completed_stories`
+        let mut seen = HashSet::new();
+        let stories = room.current_story.iter().chain(room.stories.iter()).chain(room.completed_stories.iter());
+        for story in stories {
+            if !seen.insert(story.id.clone()) {
+                continue;
+            }
+            for vote in story.votes.iter().filter(|vote| vote.user_id == user_id) {
+                votes.push(VoteExport {
+                    source: "game_rooms",
+                    room_id: room_id.clone(),
+                    story_id: story.id.clone(),
+                    title: story.title.clone(),
+                    value: vote.value,
+                    dimension_values: vote.dimension_values.clone(),
+                    timestamp: vote.timestamp,
+                });
+            }
+        }
+
+        if let Some(dots) = room.dot_votes.get(user_id) {
+            for (story_id, dots) in dots {
+                dot_votes.push(DotVoteExport {
+                    room_id: room_id.clone(),
+                    story_id: story_id.clone(),
+                    dots: *dots,
+                });
+            }
+        }
+    }
+
+    let mut confidence_votes = Vec::new();
+    let completed_filter =
+        doc! {
+        "$or": [{ "votes.userId": user_id }, { "confidence.votes.user_id": user_id }]
+    };
+    let mut cursor = db.collection::<CompletedStory>("completed_stories").find(completed_filter, None).await?;
+    while let Some(story) = cursor.try_next().await? {
+        for vote in story.votes.iter().filter(|vote| vote.user_id == user_id) {
+            votes.push(VoteExport {
+                source: "completed_stories",
+                room_id: story.room_id.clone(),
+                story_id: story.story_id.clone(),
+                title: story.title.clone(),
+                value: vote.value,
+                dimension_values: vote.dimension_values.clone(),
+                timestamp: vote.timestamp,
+            });
+        }
+        if let Some(confidence) = &story.confidence {
+            for vote in confidence.votes.iter().filter(|vote| vote.user_id == user_id) {
+                confidence_votes.push(ConfidenceVoteExport {
+                    room_id: story.room_id.clone(),
+                    story_id: story.story_id.clone(),
+                    title: story.title.clone(),
+                    value: vote.value,
+                });
+            }
+        }
+    }
+
+    let mut chat_messages = Vec::new();
+    let mut cursor = db.collection::<ChatMessage>("room_messages").find(doc! { "user_id": user_id }, None).await?;
+    while let Some(message) = cursor.try_next().await? {
+        chat_messages.push(message.into());
+    }
+
+    let mut notifications = Vec::new();
+    let mut cursor = db.collection::<Notification>("notifications").find(doc! { "user_id": user_id }, None).await?;
+    while let Some(notification) = cursor.try_next().await? {
+        notifications.push(notification.into());
+    }
+
+    let mut sessions = Vec::new();
+    let mut cursor = db.collection::<Session>("sessions").find(doc! { "user_id": user_id }, None).await?;
+    while let Some(session) = cursor.try_next().await? {
+        sessions.push(SessionExport {
+            id: session.id.map(|id| id.to_string()).unwrap_or_default(),
+            user_agent: session.user_agent,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            expires_at: session.expires_at,
+            revoked_at: session.revoked_at,
+        });
+    }
+
+    let mut teams = Vec::new();
+    let mut cursor = db.collection::<Team>("teams").find(doc! { "members.user_id": user_id }, None).await?;
+    while let Some(team) = cursor.try_next().await? {
+        if let Some(member) = team.members.iter().find(|m| m.user_id == user_id) {
+            teams.push(TeamMembershipExport {
+                team_id: team.id.map(|id| id.to_string()).unwrap_or_default(),
+                name: team.name.clone(),
+                role: member.role,
+                joined_at: member.joined_at,
+            });
+        }
+    }
+
+    let mut api_tokens = Vec::new();
+    let mut cursor = db.collection::<ApiToken>("api_tokens").find(doc! { "user_id": user_id }, None).await?;
+    while let Some(token) = cursor.try_next().await? {
+        api_tokens.push(token.into());
+    }
+
+    // Kun billeder i vores egen uploads mappe kan pakkes med
+    let images: Vec<(String, PathBuf)> = user.profile_image
+        .as_deref()
+        .and_then(|image| image.strip_prefix("/uploads/"))
+        .filter(|filename| !filename.contains(".."))
+        .map(|filename| (format!("images/{}", filename), PathBuf::from("uploads").join(filename)))
+        .filter(|(_, path)| path.exists())
+        .into_iter()
+        .collect();
+
+    let export = UserExport {
+        exported_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64,
+        account: AccountExport {
+            id: user_id.to_string(),
+            email: user.email,
+            username: user.username,
+            profile_image: user.profile_image,
+            email_verified: user.email_verified,
+            two_factor_enabled: user.two_factor.is_some_and(|two_factor| two_factor.enabled),
+            identities: user.identities,
+            is_admin: user.is_admin,
+        },
+        rooms,
+        teams,
+        votes,
+        confidence_votes,
+        dot_votes,
+        chat_messages,
+        notifications,
+        sessions,
+        api_tokens,
+        images: images
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect(),
+    };
+    Ok(Some((export, images)))
+}
+
+fn write_archive(export: &UserExport, images: &[(String, PathBuf)]) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file("data.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(export)?)?;
+
+    for (name, path) in images {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(&std::fs::read(path)?)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
@@ -1,6 +1,7 @@
 use actix_web::{
     post,
     get,
+    put,
     web,
     HttpResponse,
     Result,
@@ -10,21 +11,70 @@ use actix_web::{
 };
 use actix_web_actors::ws;
 use mongodb::Database;
+use mongodb::options::UpdateOptions;
 use crate::models::game_room::{
     GameRoom,
     CreateRoomDto,
     JoinRoomDto,
     GuestCreateRoomDto,
+    UpdateRoomSettingsDto,
+    SubmitVoteDto,
+    CreateStoryDto,
+    RecordActualDto,
+    ActualEffort,
+    ReferenceStory,
+    CompletedStoriesQuery,
+    RoomSettings,
     Story,
     Vote,
     CompletedStory,
+    RoomMode,
+    RankedStory,
+    VoteCount,
+    ConfidenceResult,
+    MIN_CONFIDENCE,
+    MAX_CONFIDENCE,
+    UNKNOWN_CARD,
+    anonymize_votes,
+    vote_distribution,
+    validate_deck,
+    validate_timer_secs,
+    normalize_tags,
 };
+use crate::models::estimation::{
+    DimensionStats,
+    dimension_stats,
+    wsjf_score,
+    validate_dimension_values,
+    validate_dimensions,
+};
+use std::collections::{ BTreeMap, HashMap };
 use crate::models::user::{ User, GuestUser, GuestJoinDto };
-use crate::middleware::auth::{ validate_token, validate_optional_token };
-use crate::websocket::{ WebSocketSession, GameServer, GameMessage, GAME_SERVER };
+use crate::models::api_token::TokenScope;
+use crate::middleware::auth::{
+    AuthUser,
+    ApiUser,
+    WsUser,
+    WsGuest,
+    AuthError,
+    MaybeUser,
+    create_guest_token,
+    legacy_guest_urls_enabled,
+};
+use crate::handlers::notification::notify_results_ready;
+use crate::handlers::team::load_team;
+use crate::handlers::reference::new_story_content;
+use crate::rate_limit::{ client_ip, Quota, RateLimiter };
+use crate::websocket::{
+    WebSocketSession,
+    WebSocketMessage,
+    GameServer,
+    GameMessage,
+    RoomSettingsChanged,
+    GAME_SERVER,
+    reveal_if_ready,
+};
 use actix::Addr;
-use jsonwebtoken::{ decode, Validation, Algorithm, DecodingKey };
-use crate::middleware::auth::Claims;
 use rand::Rng;
 use std::time::{ SystemTime, UNIX_EPOCH };
 use mongodb::bson::doc;
@@ -61,31 +111,112 @@ pub struct GameRoomResponse {
     pub current_story: Option<Story>,
     pub completed_stories: Vec<Story>,
     pub stories: Vec<Story>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_session_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reference_stories: Vec<ReferenceStory>,
+    pub settings: RoomSettings,
+    // Asynkrone rum: brugere der har stemt på den aktuelle historie,
+    // mens deres stemmer er skjult indtil afsløringen
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sealed_voters: Vec<String>,
     pub created_at: i64,
     pub updated_at: i64,
 }
 
+impl GameRoomResponse {
+    // Skjul hvem der har stemt hvad hvis rummet er i anonym tilstand,
+    // og skjul andres stemmer på en åben historie i et asynkront rum
+    fn apply_vote_privacy(mut self, viewer_id: &str) -> Self {
+        if self.settings.mode == RoomMode::Async {
+            if let Some(story) = self.current_story.as_mut() {
+                let (own, others): (Vec<Vote>, Vec<Vote>) = std::mem
+                    ::take(&mut story.votes)
+                    .into_iter()
+                    .partition(|vote| vote.user_id == viewer_id);
+                story.votes = own;
+                self.sealed_voters = others
+                    .into_iter()
+                    .map(|vote| vote.user_id)
+                    .collect();
+                self.sealed_voters.dedup();
+            }
+        }
+        // Afgøres pr. historie, så historier estimeret anonymt aldrig afslører
+        // hvem der stemte, heller ikke efter rummet har slået anonym tilstand fra
+        let room_anonymous = self.settings.anonymous_votes;
+        let anonymize_story = |mut story: Story| {
+            if room_anonymous || story.anonymous {
+                story.votes = anonymize_votes(story.votes, Some(viewer_id));
+            }
+            story
+        };
+        self.current_story = self.current_story.map(anonymize_story);
+        self.completed_stories = self.completed_stories.into_iter().map(anonymize_story).collect();
+        self.stories = self.stories.into_iter().map(anonymize_story).collect();
+        self
+    }
+}
+
 #[post("/rooms")]
 pub async fn create_room(
     req: HttpRequest,
+    user: ApiUser,
     db: web::Data<Database>,
+    limiter: web::Data<RateLimiter>,
     room_data: web::Json<CreateRoomDto>
 ) -> Result<HttpResponse> {
-    let user_id = validate_token(req.clone()).await?;
+    let user_id = user.require(TokenScope::RoomsWrite)?.to_string();
+    limiter.check_quota(Quota::RoomCreate, &client_ip(&req)).await?;
 
     let collection = db.collection::<GameRoom>("game_rooms");
 
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
 
+    // Rum i et team starter med teamets standarder og alle medlemmer som deltagere
+    let mut participants = vec![user_id.clone()];
+    let mut settings = RoomSettings {
+        mode: room_data.mode,
+        ..RoomSettings::default()
+    };
+    if let Some(team_id) = room_data.team_id.as_deref() {
+        let team = match load_team(&db, team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+            Some(team) if team.role_of(&user_id).is_some() => team,
+            _ => {
+                return Ok(
+                    HttpResponse::NotFound().json(
+                        serde_json::json!({
+                            "message": "Team ikke fundet"
+                        })
+                    )
+                );
+            }
+        };
+        for member_id in team.member_ids() {
+            if !participants.contains(&member_id) {
+                participants.push(member_id);
+            }
+        }
+        settings = team.defaults.room_settings(room_data.mode);
+    }
+
     let new_room = GameRoom {
         id: None,
         name: room_data.name.clone(),
         invite_code: generate_invite_code(),
         admin_id: user_id.clone(),
-        participants: vec![user_id],
+        participants,
         current_story: None,
         completed_stories: Vec::new(),
         stories: Vec::new(),
+        team_id: room_data.team_id.clone(),
+        sessions: Vec::new(),
+        active_session_id: None,
+        reference_stories: Vec::new(),
+        settings,
+        dot_votes: HashMap::new(),
         created_at: now,
         updated_at: now,
     };
@@ -110,6 +241,11 @@ pub async fn create_room(
         current_story: new_room.current_story,
         completed_stories: new_room.completed_stories,
         stories: new_room.stories,
+        team_id: new_room.team_id,
+        active_session_id: new_room.active_session_id,
+        reference_stories: new_room.reference_stories,
+        settings: new_room.settings,
+        sealed_voters: Vec::new(),
         created_at: new_room.created_at,
         updated_at: new_room.updated_at,
     };
@@ -119,15 +255,13 @@ pub async fn create_room(
 
 #[post("/rooms/join")]
 pub async fn join_room(
-    req: HttpRequest,
+    user: AuthUser,
     db: web::Data<Database>,
     join_data: web::Json<JoinRoomDto>
 ) -> Result<HttpResponse> {
     println!("=== REGULAR JOIN ROOM ENDPOINT HIT ===");
-    println!("Request path: {:?}", req.path());
-    println!("Request method: {:?}", req.method());
 
-    let user_id = validate_token(req.clone()).await?;
+    let user_id = user.id;
 
     let collection = db.collection::<GameRoom>("game_rooms");
 
@@ -199,20 +333,25 @@ pub async fn join_room(
         current_story: room.current_story,
         completed_stories: room.completed_stories,
         stories: room.stories,
+        team_id: room.team_id,
+        active_session_id: room.active_session_id,
+        reference_stories: room.reference_stories,
+        settings: room.settings,
+        sealed_voters: Vec::new(),
         created_at: room.created_at,
         updated_at: now,
     };
 
-    Ok(HttpResponse::Ok().json(room_response))
+    Ok(HttpResponse::Ok().json(room_response.apply_vote_privacy(&user_id)))
 }
 
 #[post("/rooms/{room_id}/join")]
 pub async fn join_room_by_id(
-    req: HttpRequest,
+    user: AuthUser,
     path: web::Path<String>,
     db: web::Data<Database>
 ) -> Result<HttpResponse> {
-    let user_id = validate_token(req.clone()).await?;
+    let user_id = user.id;
     let room_id = path.into_inner();
 
     let collection = db.collection::<GameRoom>("game_rooms");
@@ -264,11 +403,16 @@ pub async fn join_room_by_id(
             current_story: room.current_story,
             completed_stories: room.completed_stories,
             stories: room.stories,
+            team_id: room.team_id,
+            active_session_id: room.active_session_id,
+            reference_stories: room.reference_stories,
+            settings: room.settings,
+            sealed_voters: Vec::new(),
             created_at: room.created_at,
             updated_at: room.updated_at,
         };
 
-        return Ok(HttpResponse::Ok().json(room_response));
+        return Ok(HttpResponse::Ok().json(room_response.apply_vote_privacy(&user_id)));
     }
 
     // Add user to participants
@@ -311,11 +455,16 @@ pub async fn join_room_by_id(
         current_story: room.current_story,
         completed_stories: room.completed_stories,
         stories: room.stories,
+        team_id: room.team_id,
+        active_session_id: room.active_session_id,
+        reference_stories: room.reference_stories,
+        settings: room.settings,
+        sealed_voters: Vec::new(),
         created_at: room.created_at,
         updated_at: now,
     };
 
-    Ok(HttpResponse::Ok().json(room_response))
+    Ok(HttpResponse::Ok().json(room_response.apply_vote_privacy(&user_id)))
 }
 
 async fn get_participants_info(
@@ -360,11 +509,11 @@ async fn get_participants_info(
 
 #[get("/rooms/{room_id}")]
 pub async fn get_room(
-    req: HttpRequest,
+    user: ApiUser,
     db: web::Data<Database>,
     room_id: web::Path<String>
 ) -> Result<HttpResponse> {
-    let user_id = validate_token(req.clone()).await?;
+    let user_id = user.require(TokenScope::RoomsRead)?.to_string();
 
     let collection = db.collection::<GameRoom>("game_rooms");
 
@@ -414,18 +563,23 @@ pub async fn get_room(
         current_story: room.current_story,
         completed_stories: room.completed_stories,
         stories: room.stories,
+        team_id: room.team_id,
+        active_session_id: room.active_session_id,
+        reference_stories: room.reference_stories,
+        settings: room.settings,
+        sealed_voters: Vec::new(),
         created_at: room.created_at,
         updated_at: room.updated_at,
     };
 
-    Ok(HttpResponse::Ok().json(room_response))
+    Ok(HttpResponse::Ok().json(room_response.apply_vote_privacy(&user_id)))
 }
 
 pub async fn handle_new_story(
     db: &Database,
     room_id: &str,
     mut story: Story
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<Story, Box<dyn std::error::Error>> {
     println!("handle_new_story kaldt med room_id: {} og historie: {:?}", room_id, story);
     let collection = db.collection::<GameRoom>("game_rooms");
 
@@ -444,6 +598,15 @@ pub async fn handle_new_story(
 
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
 
+    // I asynkrone rum står historien åben indtil fristen
+    let settings = load_room_settings(db, room_id).await?.unwrap_or_default();
+    story.started_at = Some(now);
+    story.voting_deadline = if settings.mode == RoomMode::Async {
+        Some(now + (settings.async_voting_hours as i64) * 3600)
+    } else {
+        None
+    };
+
     let story_bson = mongodb::bson::to_bson(&story)?;
     println!("Historie konverteret til BSON: {:?}", story_bson);
 
@@ -472,7 +635,7 @@ pub async fn handle_new_story(
     }
 
     println!("Historie gemt succesfuldt i databasen");
-    Ok(())
+    Ok(story)
 }
 
 pub async fn handle_vote(
@@ -480,8 +643,9 @@ pub async fn handle_vote(
     room_id: &str,
     user_id: &str,
     story_id: &str,
-    value: i32
-) -> Result<(), Box<dyn std::error::Error>> {
+    value: i32,
+    dimension_values: Option<BTreeMap<String, i32>>
+) -> Result<Vote, Box<dyn std::error::Error>> {
     println!(
         "handle_vote kaldt med room_id: {}, user_id: {}, story_id: {}, value: {}",
         room_id,
@@ -503,12 +667,44 @@ pub async fn handle_vote(
         .find_one(doc! { "_id": user_object_id }, None).await?
         .ok_or("Bruger ikke fundet")?;
 
+    let room = collection
+        .find_one(doc! { "_id": object_id }, None).await?
+        .ok_or("Rum ikke fundet")?;
+
+    // En asynkron historie tager ikke imod stemmer efter fristen
+    let deadline = room.current_story
+        .as_ref()
+        .filter(|story| story.id == story_id)
+        .and_then(|story| story.voting_deadline);
+    if deadline.is_some_and(|deadline| deadline <= now) {
+        return Err("Afstemningen er lukket".into());
+    }
+
+    // Stemmer med flere dimensioner valideres mod rummets opsætning og
+    // får deres samlede værdi beregnet af serveren
+    let value = match &dimension_values {
+        Some(values) => {
+            validate_dimension_values(&room.settings.active_dimensions(), values)?;
+            room.settings.combined_score(values)
+        }
+        // Et rum med dimensioner kræver en værdi for hver af dem
+        None if !room.settings.active_dimensions().is_empty() => {
+            return Err("Angiv en værdi for hver dimension".into());
+        }
+        // Almindelige stemmer skal være et kort fra rummets kortbunke
+        None if !room.settings.deck.contains(&value) => {
+            return Err("Værdien findes ikke i rummets kortbunke".into());
+        }
+        None => value,
+    };
+
     let vote = Vote {
         user_id: user_id.to_string(),
         username: user.username,
         profile_image: user.profile_image,
         value,
         timestamp: now,
+        dimension_values,
     };
 
     // Opdater current_story.votes array
@@ -529,7 +725,46 @@ pub async fn handle_vote(
     ).await?;
 
     println!("Vote gemt - modified_count: {}", update_result.modified_count);
-    Ok(())
+    Ok(vote)
+}
+
+fn consensus_values(stats: &[DimensionStats]) -> BTreeMap<String, i32> {
+    stats
+        .iter()
+        .map(|stats| (stats.key.clone(), stats.consensus))
+        .collect()
+}
+
+fn room_wsjf(settings: &RoomSettings, consensus: &BTreeMap<String, i32>) -> Option<f64> {
+    if settings.mode == RoomMode::Wsjf { wsjf_score(consensus) } else { None }
+}
+
+// Resultatet af en afsløret runde som sendes til klienterne
+#[derive(Debug, Serialize)]
+pub struct RoundSummary {
+    pub final_score: i32,
+    pub wsjf: Option<f64>,
+    pub distribution: Vec<VoteCount>,
+    pub dimension_stats: Vec<DimensionStats>,
+}
+
+// I rum med flere dimensioner beregnes den endelige score ud fra hver dimensions konsensus
+pub fn summarize_round(settings: &RoomSettings, votes: &[Vote], final_score: i32) -> RoundSummary {
+    let dimension_stats = dimension_stats(&settings.active_dimensions(), votes);
+    let consensus = consensus_values(&dimension_stats);
+
+    let final_score = if dimension_stats.is_empty() {
+        final_score
+    } else {
+        settings.combined_score(&consensus)
+    };
+
+    RoundSummary {
+        final_score,
+        wsjf: room_wsjf(settings, &consensus),
+        distribution: vote_distribution(votes),
+        dimension_stats,
+    }
 }
 
 pub async fn handle_end_voting(
@@ -537,7 +772,7 @@ pub async fn handle_end_voting(
     room_id: &str,
     story_id: &str,
     final_score: i32
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<RoundSummary, Box<dyn std::error::Error>> {
     println!(
         "handle_end_voting kaldt med room_id: {}, story_id: {}, final_score: {}",
         room_id,
@@ -557,8 +792,13 @@ pub async fn handle_end_voting(
         .ok_or("Rum ikke fundet")?;
 
     let current_story = room.current_story.ok_or("Ingen aktiv historie fundet")?;
+    let summary = summarize_round(&room.settings, &current_story.votes, final_score);
+    let final_score = summary.final_score;
+    let anonymous = room.settings.anonymous_votes || current_story.anonymous;
 
-    // Opdater historien med den endelige score og flyt den til completed_stories
+    // Opdater historien med den endelige score og flyt den til completed_stories.
+    // Hver afsløring tæller som en runde på historien, og en historie der er
+    // estimeret anonymt forbliver anonym selvom rummet senere skifter tilstand.
     let update_result = collection.update_one(
         doc! { "_id": object_id },
         doc! {
@@ -568,19 +808,27 @@ pub async fn handle_end_voting(
                         "title": &current_story.title,
                         "description": &current_story.description,
                         "votes": &current_story.votes,
-                        "final_score": final_score
+                        "final_score": final_score,
+                        "rounds": current_story.rounds + 1,
+                        "anonymous": anonymous
                     }
                 },
+                "$inc": {
+                    "stories.$[story].rounds": 1
+                },
                 "$set": {
+                    "stories.$[story].anonymous": anonymous,
                     "current_story": null,
                     "updated_at": now
                 }
             },
-        None
+        UpdateOptions::builder()
+            .array_filters(vec![doc! { "story.id": &current_story.id }])
+            .build()
     ).await?;
 
     println!("Afstemning afsluttet - modified_count: {}", update_result.modified_count);
-    Ok(())
+    Ok(summary)
 }
 
 pub async fn handle_save_final_score(
@@ -589,7 +837,6 @@ pub async fn handle_save_final_score(
     story_json: &serde_json::Value
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("handle_save_final_score kaldt med story: {:?}", story_json);
-    let rooms_collection = db.collection::<GameRoom>("game_rooms");
     let completed_stories_collection = db.collection::<CompletedStory>("completed_stories");
 
     let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
@@ -597,6 +844,8 @@ pub async fn handle_save_final_score(
 
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
 
+    let room = db.collection::<GameRoom>("game_rooms").find_one(doc! { "_id": object_id }, None).await?;
+
     // Opret en ny historie med et nyt ID
     let story_id = mongodb::bson::oid::ObjectId::new().to_string();
 
@@ -626,6 +875,23 @@ pub async fn handle_save_final_score(
         .map(|v| v as i32)
         .unwrap_or(0);
 
+    // Runderne tælles på selve historien hver gang stemmerne afsløres
+    let room_story_id = story_obj.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+    let (settings, session_id, rounds, started_at) = match room {
+        Some(room) => {
+            let story = room.stories.iter().find(|story| story.id == room_story_id);
+            let rounds = story.map(|story| story.rounds).unwrap_or_default().max(1);
+            let started_at = story.and_then(|story| story.started_at);
+            (room.settings, room.active_session_id, rounds, started_at)
+        }
+        None => (RoomSettings::default(), None, 1, None),
+    };
+    // Historier estimeret i anonym tilstand forbliver anonyme selvom rummet senere skifter
+    let anonymous = settings.anonymous_votes;
+
+    let dimension_stats = dimension_stats(&settings.active_dimensions(), &votes);
+    let wsjf = room_wsjf(&settings, &consensus_values(&dimension_stats));
+
     // Opret completed_story
     let completed_story = CompletedStory {
         id: None,
@@ -636,6 +902,15 @@ pub async fn handle_save_final_score(
         votes: votes.clone(),
         final_score,
         completed_at: now,
+        anonymous,
+        dimension_stats: dimension_stats.clone(),
+        confidence: None,
+        wsjf,
+        session_id: session_id.clone(),
+        rounds,
+        started_at,
+        actual: None,
+        tags: Vec::new(),
     };
 
     println!("Forsøger at gemme completed_story: {:?}", completed_story);
@@ -657,19 +932,34 @@ pub async fn handle_save_final_score(
             votes,
             final_score,
             completed_at: now,
+            anonymous,
+            dimension_stats,
+            confidence: None,
+            wsjf,
+            session_id,
+            rounds,
+            started_at,
+            actual: None,
+            tags: Vec::new(),
         };
 
         // Konverter til en version med string id før vi sender via WebSocket
         let websocket_story =
             serde_json::json!({
-            "id": completed_story.id.unwrap().to_string(),
+            "_id": completed_story.id.unwrap().to_string(),
             "story_id": completed_story.story_id,
             "room_id": completed_story.room_id,
             "title": completed_story.title,
             "description": completed_story.description,
             "votes": completed_story.votes,
             "final_score": completed_story.final_score,
-            "completed_at": completed_story.completed_at
+            "completed_at": completed_story.completed_at,
+            "anonymous": completed_story.anonymous,
+            "dimension_stats": completed_story.dimension_stats,
+            "wsjf": completed_story.wsjf,
+            "session_id": completed_story.session_id,
+            "rounds": completed_story.rounds,
+            "started_at": completed_story.started_at
         });
 
         println!("Sender completed_story besked via WebSocket: {:?}", websocket_story);
@@ -682,198 +972,1145 @@ pub async fn handle_save_final_score(
     Ok(())
 }
 
-#[get("/rooms/{room_id}/completed-stories")]
-pub async fn get_completed_stories(
-    req: HttpRequest,
-    db: web::Data<Database>,
-    room_id: web::Path<String>
-) -> Result<HttpResponse> {
-    let user_id = validate_token(req.clone()).await?;
+// Den mest stemte værdi. Ved uafgjort vælges den højeste værdi.
+// "?" kort tæller ikke med, så None betyder at ingen har givet et tal.
+pub fn most_voted_score(votes: &[Vote]) -> Option<i32> {
+    let mut counts: std::collections::HashMap<i32, usize> = std::collections::HashMap::new();
+    for vote in votes.iter().filter(|vote| vote.value > UNKNOWN_CARD) {
+        *counts.entry(vote.value).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(value, count)| (*count, *value))
+        .map(|(value, _)| value)
+}
 
-    // Tjek om brugeren har adgang til rummet
-    let rooms_collection = db.collection::<GameRoom>("game_rooms");
-    let object_id = mongodb::bson::oid::ObjectId
-        ::parse_str(room_id.as_str())
-        .map_err(|_| ErrorInternalServerError("Ugyldigt rum ID"))?;
+// Afslutter den aktuelle runde når rundens timer udløber.
+// Afslører altid stemmerne, og gemmer den endelige score hvis `close` er sat.
+pub async fn handle_round_timeout(
+    db: &Database,
+    room_id: &str,
+    close: bool
+) -> Result<Option<(Story, RoundSummary)>, Box<dyn std::error::Error>> {
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
 
-    let room = match
-        rooms_collection
-            .find_one(doc! { "_id": object_id }, None).await
-            .map_err(ErrorInternalServerError)?
-    {
-        Some(room) => room,
+    let room = collection
+        .find_one(doc! { "_id": object_id }, None).await?
+        .ok_or("Rum ikke fundet")?;
+
+    let story = match room.current_story {
+        Some(story) => story,
         None => {
-            return Ok(
-                HttpResponse::NotFound().json(
-                    serde_json::json!({
-                "message": "Spilrum ikke fundet"
-            })
-                )
-            );
+            return Ok(None);
         }
     };
+    let score = most_voted_score(&story.votes);
+    let summary = handle_end_voting(db, room_id, &story.id, score.unwrap_or(0)).await?;
 
-    if !room.participants.contains(&user_id) {
-        return Ok(
-            HttpResponse::Forbidden().json(
-                serde_json::json!({
-            "message": "Du har ikke adgang til dette spilrum"
-        })
-            )
-        );
+    // Uden et talkort at vælge afsløres stemmerne kun, og admin sætter selv scoren
+    if close && score.is_some() {
+        let story_json =
+            serde_json::json!({
+            "id": story.id,
+            "title": story.title,
+            "description": story.description.clone().unwrap_or_default(),
+            "votes": story.votes,
+            "final_score": summary.final_score
+        });
+        handle_save_final_score(db, room_id, &story_json).await?;
     }
 
-    // Hent gemte historier
-    let completed_stories_collection = db.collection::<CompletedStory>("completed_stories");
-    let mut completed_stories = Vec::new();
+    Ok(Some((story, summary)))
+}
 
-    let mut cursor = completed_stories_collection
-        .find(doc! { "room_id": room_id.as_str() }, None).await
-        .map_err(ErrorInternalServerError)?;
+// Afslører den åbne historie i et asynkront rum når fristen er udløbet eller
+// alle deltagere har stemt, og giver deltagerne besked om resultatet
+pub async fn reveal_async_round(
+    db: &Database,
+    room_id: &str
+) -> Result<Option<(Story, RoundSummary)>, Box<dyn std::error::Error>> {
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
 
-    while let Ok(Some(story)) = cursor.try_next().await {
-        completed_stories.push(story);
+    let room = collection
+        .find_one(doc! { "_id": object_id }, None).await?
+        .ok_or("Rum ikke fundet")?;
+
+    if room.settings.mode != RoomMode::Async {
+        return Ok(None);
     }
+    let story = match room.current_story {
+        Some(story) => story,
+        None => {
+            return Ok(None);
+        }
+    };
 
-    Ok(HttpResponse::Ok().json(completed_stories))
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let deadline_passed = story.voting_deadline.is_some_and(|deadline| deadline <= now);
+    let everyone_voted =
+        !room.participants.is_empty() &&
+        room.participants.iter().all(|id| story.votes.iter().any(|vote| &vote.user_id == id));
+    if !deadline_passed && !everyone_voted {
+        return Ok(None);
+    }
+
+    println!(
+        "Afslører asynkron historie {} i rum {} (frist udløbet: {}, alle har stemt: {})",
+        story.id,
+        room_id,
+        deadline_passed,
+        everyone_voted
+    );
+    let summary = handle_end_voting(
+        db,
+        room_id,
+        &story.id,
+        most_voted_score(&story.votes).unwrap_or(0)
+    ).await?;
+
+    if let Err(e) = notify_results_ready(db, room_id, &room.participants, &story, summary.final_score).await {
+        println!("Fejl ved oprettelse af notifikationer: {:?}", e);
+    }
+
+    Ok(Some((story, summary)))
 }
 
-#[get("/rooms/{room_id}/ws")]
-pub async fn room_ws(
-    req: HttpRequest,
-    room_id: web::Path<String>,
-    stream: web::Payload,
-    srv: web::Data<actix::Addr<GameServer>>,
-    db: web::Data<Database>
-) -> Result<HttpResponse> {
-    println!("WebSocket forbindelse forsøgt oprettet for rum: {}", room_id);
-    println!("Query string: {}", req.query_string());
-
-    // Hent token fra query parameter
-    let token = req
-        .query_string()
-        .split('&')
-        .find(|s| s.starts_with("token="))
-        .and_then(|s| s.strip_prefix("token="))
-        .ok_or_else(|| {
-            println!("Ingen token fundet i query string");
-            ErrorUnauthorized("Ingen token fundet")
-        })?;
-
-    println!("Token fundet i query string");
-
-    // Valider token
-    let jwt_secret = std::env::var("JWT_SECRET").map_err(|e| {
-        println!("JWT_SECRET fejl: {:?}", e);
-        ErrorUnauthorized("JWT_SECRET er ikke konfigureret")
-    })?;
-
-    println!("JWT_SECRET hentet fra env");
-
-    let mut validation = Validation::new(Algorithm::HS256);
-    validation.validate_exp = true;
-
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation
-    ).map_err(|e| {
-        println!("Token validering fejlede: {:?}", e);
-        ErrorUnauthorized("Ugyldig token")
-    })?;
-
-    let user_id = token_data.claims.sub;
-    println!("Token valideret succesfuldt. User ID: {}", user_id);
+// Asynkrone rum hvor den åbne historie har overskredet sin frist
+pub async fn due_async_rooms(db: &Database) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
 
-    // Hent brugerinfo
-    let users_collection = db.collection::<User>("users");
-    let user_object_id = mongodb::bson::oid::ObjectId
-        ::parse_str(&user_id)
-        .map_err(|_| ErrorInternalServerError("Ugyldigt bruger ID"))?;
+    let mut cursor = collection.find(
+        doc! {
+            "settings.mode": "async",
+            "current_story.votingDeadline": { "$lte": now }
+        },
+        None
+    ).await?;
 
-    let user = users_collection
-        .find_one(doc! { "_id": user_object_id }, None).await
-        .map_err(ErrorInternalServerError)?
-        .ok_or_else(|| ErrorInternalServerError("Bruger ikke fundet"))?;
+    let mut room_ids = Vec::new();
+    while let Some(room) = cursor.try_next().await? {
+        if let Some(id) = room.id {
+            room_ids.push(id.to_string());
+        }
+    }
+    Ok(room_ids)
+}
 
-    // Opret en ny WebSocket session
-    let ws = WebSocketSession::new(
-        room_id.into_inner(),
-        user_id.clone(),
-        user.username,
-        user.profile_image,
-        srv.get_ref().clone(),
-        db.get_ref().clone()
-    );
+// Returnerer den aktive historie og de brugere der har stemt på den
+pub async fn current_round_voters(
+    db: &Database,
+    room_id: &str
+) -> Result<Option<(String, Vec<String>)>, Box<dyn std::error::Error>> {
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
 
-    println!("WebSocket session oprettet, opgraderer forbindelse...");
+    let room = collection
+        .find_one(doc! { "_id": object_id }, None).await?
+        .ok_or("Rum ikke fundet")?;
 
-    // Opgrader HTTP forbindelsen til WebSocket
-    ws::start(ws, &req, stream)
+    Ok(
+        room.current_story.map(|story| {
+            let voted = story.votes
+                .into_iter()
+                .map(|vote| vote.user_id)
+                .collect();
+            (story.id, voted)
+        })
+    )
 }
 
-#[get("/rooms/{room_id}/guest-ws/{guest_id}")]
-pub async fn guest_room_ws(
-    req: HttpRequest,
-    stream: web::Payload,
-    path: web::Path<(String, String)>,
-    srv: web::Data<Addr<GameServer>>,
-    db: web::Data<Database>
-) -> Result<HttpResponse> {
-    let (room_id, guest_id) = path.into_inner();
+pub async fn is_room_admin(
+    db: &Database,
+    room_id: &str,
+    user_id: &str
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
 
-    println!("Guest WebSocket forbindelse for rum {} med guest ID: {}", room_id, guest_id);
+    let room = collection.find_one(doc! { "_id": object_id }, None).await?;
+    Ok(room.map(|room| room.admin_id == user_id).unwrap_or(false))
+}
 
-    // Hent guest info
-    let guests_collection = db.collection::<GuestUser>("guest_users");
-    let guest_object_id = mongodb::bson::oid::ObjectId
-        ::parse_str(&guest_id)
-        .map_err(|_| ErrorInternalServerError("Ugyldigt guest ID"))?;
+pub async fn save_confidence_result(
+    db: &Database,
+    room_id: &str,
+    completed_story_id: &str,
+    result: &ConfidenceResult
+) -> Result<(), Box<dyn std::error::Error>> {
+    let completed_stories_collection = db.collection::<CompletedStory>("completed_stories");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(completed_story_id)?;
 
-    let guest = guests_collection
-        .find_one(doc! { "_id": guest_object_id }, None).await
-        .map_err(ErrorInternalServerError)?
-        .ok_or_else(|| ErrorInternalServerError("Guest ikke fundet"))?;
+    let update_result = completed_stories_collection.update_one(
+        doc! { "_id": object_id, "room_id": room_id },
+        doc! { "$set": { "confidence": mongodb::bson::to_bson(result)? } },
+        None
+    ).await?;
 
-    // Opret en ny WebSocket session
-    let ws = WebSocketSession::new(
-        room_id.clone(),
-        guest_id.clone(),
-        guest.username,
-        guest.profile_image,
-        srv.get_ref().clone(),
-        db.get_ref().clone()
-    );
+    if update_result.matched_count == 0 {
+        return Err("Afsluttet historie ikke fundet".into());
+    }
+    Ok(())
+}
 
-    println!("Guest WebSocket session oprettet, opgraderer forbindelse...");
+pub async fn load_room_settings(
+    db: &Database,
+    room_id: &str
+) -> Result<Option<RoomSettings>, Box<dyn std::error::Error>> {
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
 
-    // Opgrader HTTP forbindelsen til WebSocket
-    ws::start(ws, &req, stream)
+    let room = collection.find_one(doc! { "_id": object_id }, None).await?;
+    Ok(room.map(|room| room.settings))
 }
 
-#[get("/rooms/{room_id}/info")]
-pub async fn get_room_info(
-    db: web::Data<Database>,
-    room_id: web::Path<String>
-) -> Result<HttpResponse> {
+const MAX_DOT_BUDGET: u32 = 50;
+// Længste periode en historie kan stå åben i et asynkront rum (en uge)
+const MAX_ASYNC_VOTING_HOURS: u32 = 168;
+
+// Fordeler en deltagers prikker på backloggen. Erstatter deltagerens tidligere fordeling.
+pub async fn handle_dot_vote(
+    db: &Database,
+    room_id: &str,
+    user_id: &str,
+    allocations: BTreeMap<String, u32>
+) -> Result<Vec<RankedStory>, Box<dyn std::error::Error>> {
     let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
 
-    let object_id = match mongodb::bson::oid::ObjectId::parse_str(room_id.as_str()) {
-        Ok(id) => id,
-        Err(_) => {
-            return Ok(
-                HttpResponse::BadRequest().json(
-                    serde_json::json!({
-                        "message": "Ugyldigt rum ID"
-                    })
-                )
-            );
-        }
-    };
+    let room = collection
+        .find_one(doc! { "_id": object_id }, None).await?
+        .ok_or("Rum ikke fundet")?;
 
-    let room = match collection.find_one(doc! { "_id": object_id }, None).await {
-        Ok(Some(room)) => room,
-        Ok(None) => {
+    if room.settings.mode != RoomMode::DotVoting {
+        return Err("Rummet bruger ikke dot voting".into());
+    }
+    if !room.participants.iter().any(|id| id == user_id) {
+        return Err("Du er ikke deltager i rummet".into());
+    }
+    let allocations = validate_dot_allocation(&room, allocations)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    collection.update_one(
+        doc! { "_id": object_id },
+        doc! {
+            "$set": {
+                format!("dot_votes.{}", user_id): mongodb::bson::to_bson(&allocations)?,
+                "updated_at": now
+            }
+        },
+        None
+    ).await?;
+
+    let mut room = room;
+    room.dot_votes.insert(user_id.to_string(), allocations);
+    Ok(dot_ranking(&room))
+}
+
+// Tjekker en fordeling mod rummets budget og backlog og fjerner tomme poster
+fn validate_dot_allocation(
+    room: &GameRoom,
+    allocations: BTreeMap<String, u32>
+) -> Result<BTreeMap<String, u32>, String> {
+    // En summering der løber over er under alle omstændigheder over budget
+    let used = allocations.values().try_fold(0u32, |sum, dots| sum.checked_add(*dots));
+    if used.is_none_or(|used| used > room.settings.dot_budget) {
+        return Err(format!("Du har kun {} prikker", room.settings.dot_budget));
+    }
+    if allocations.keys().any(|story_id| !room.stories.iter().any(|s| &s.id == story_id)) {
+        return Err("Ukendt historie i fordelingen".to_string());
+    }
+    Ok(
+        allocations
+            .into_iter()
+            .filter(|(_, dots)| *dots > 0)
+            .collect()
+    )
+}
+
+fn dot_ranking(room: &GameRoom) -> Vec<RankedStory> {
+    let mut ranked: Vec<RankedStory> = room.stories
+        .iter()
+        .map(|story| {
+            let dots: u32 = room.dot_votes
+                .values()
+                .filter_map(|allocation| allocation.get(&story.id))
+                .sum();
+            RankedStory {
+                rank: 0,
+                story_id: story.id.clone(),
+                title: story.title.clone(),
+                score: dots as f64,
+            }
+        })
+        .collect();
+    rank_stories(&mut ranked);
+    ranked
+}
+
+// Sorter efter score, højeste først, og nummerer rækkefølgen
+fn rank_stories(stories: &mut [RankedStory]) {
+    stories.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    for (i, story) in stories.iter_mut().enumerate() {
+        story.rank = i + 1;
+    }
+}
+
+// Rummets prioriterede rækkefølge. Tom for klassiske estimeringsrum.
+pub async fn room_ranking(
+    db: &Database,
+    room_id: &str
+) -> Result<(RoomMode, Vec<RankedStory>), Box<dyn std::error::Error>> {
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = mongodb::bson::oid::ObjectId::parse_str(room_id)?;
+
+    let room = collection
+        .find_one(doc! { "_id": object_id }, None).await?
+        .ok_or("Rum ikke fundet")?;
+
+    let ranking = match room.settings.mode {
+        RoomMode::Estimation | RoomMode::Async => Vec::new(),
+        RoomMode::DotVoting => dot_ranking(&room),
+        RoomMode::Wsjf => {
+            let completed_stories_collection = db.collection::<CompletedStory>("completed_stories");
+            let mut cursor = completed_stories_collection.find(
+                doc! { "room_id": room_id, "wsjf": { "$ne": null } },
+                None
+            ).await?;
+
+            let mut ranked = Vec::new();
+            while let Some(story) = cursor.try_next().await? {
+                ranked.push(RankedStory {
+                    rank: 0,
+                    story_id: story.story_id,
+                    title: story.title,
+                    score: story.wsjf.unwrap_or(0.0),
+                });
+            }
+            rank_stories(&mut ranked);
+            ranked
+        }
+    };
+
+    Ok((room.settings.mode, ranking))
+}
+
+#[get("/rooms/{room_id}/ranking")]
+pub async fn get_room_ranking(
+    user: MaybeUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>
+) -> Result<HttpResponse> {
+    let user_id = user.id_in_room(room_id.as_str())?.to_string();
+
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(room_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt rum ID"
+                    })
+                )
+            );
+        }
+    };
+
+    let room = match
+        collection
+            .find_one(doc! { "_id": object_id }, None).await
+            .map_err(ErrorInternalServerError)?
+    {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Spilrum ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+
+    if !room.participants.contains(&user_id) {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Du har ikke adgang til dette spilrum"
+                })
+            )
+        );
+    }
+
+    let (mode, ranking) = room_ranking(&db, room_id.as_str()).await.map_err(
+        ErrorInternalServerError
+    )?;
+
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "mode": mode,
+                "ranking": ranking
+            })
+        )
+    )
+}
+
+// Maksimalt antal emojis et rum kan tillade
+const MAX_ALLOWED_EMOJIS: usize = 24;
+// En enkelt emoji kan bestå af flere code points (hudfarve, ZWJ-sekvenser osv.)
+const MAX_EMOJI_LENGTH: usize = 32;
+
+#[put("/rooms/{room_id}/settings")]
+pub async fn update_room_settings(
+    user: ApiUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>,
+    settings_data: web::Json<UpdateRoomSettingsDto>,
+    srv: web::Data<Addr<GameServer>>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::RoomsWrite)?.to_string();
+
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(room_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt rum ID"
+                    })
+                )
+            );
+        }
+    };
+
+    let room = match
+        collection
+            .find_one(doc! { "_id": object_id }, None).await
+            .map_err(ErrorInternalServerError)?
+    {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Spilrum ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+
+    if room.admin_id != user_id {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Kun admin kan ændre rummets indstillinger"
+                })
+            )
+        );
+    }
+
+    let mut settings = room.settings;
+
+    if let Some(anonymous_votes) = settings_data.anonymous_votes {
+        settings.anonymous_votes = anonymous_votes;
+    }
+
+    if let Some(dimensions) = settings_data.dimensions.clone() {
+        settings.dimensions = dimensions;
+    }
+    if let Some(combination) = settings_data.combination.clone() {
+        settings.combination = combination;
+    }
+    if let Some(mode) = settings_data.mode {
+        settings.mode = mode;
+    }
+    if let Some(dot_budget) = settings_data.dot_budget {
+        if !(1..=MAX_DOT_BUDGET).contains(&dot_budget) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": format!("Antal prikker skal være mellem 1 og {}", MAX_DOT_BUDGET)
+                    })
+                )
+            );
+        }
+        settings.dot_budget = dot_budget;
+    }
+    if let Some(hours) = settings_data.async_voting_hours {
+        if !(1..=MAX_ASYNC_VOTING_HOURS).contains(&hours) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": format!(
+                            "Afstemningsperioden skal være mellem 1 og {} timer",
+                            MAX_ASYNC_VOTING_HOURS
+                        )
+                    })
+                )
+            );
+        }
+        settings.async_voting_hours = hours;
+    }
+
+    if let Some(confidence_check) = settings_data.confidence_check {
+        settings.confidence_check = confidence_check;
+    }
+    if let Some(threshold) = settings_data.low_confidence_threshold {
+        if !(MIN_CONFIDENCE as f64..=MAX_CONFIDENCE as f64).contains(&threshold) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": format!(
+                            "Grænsen skal ligge mellem {} og {}",
+                            MIN_CONFIDENCE,
+                            MAX_CONFIDENCE
+                        )
+                    })
+                )
+            );
+        }
+        settings.low_confidence_threshold = threshold;
+    }
+
+    if let Some(deck) = settings_data.deck.clone() {
+        if let Err(e) = validate_deck(&deck) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": e
+                    })
+                )
+            );
+        }
+        settings.deck = deck;
+    }
+    if let Some(timer_secs) = settings_data.timer_secs {
+        if timer_secs == 0 {
+            settings.timer_secs = None;
+        } else if let Err(e) = validate_timer_secs(timer_secs) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": e
+                    })
+                )
+            );
+        } else {
+            settings.timer_secs = Some(timer_secs);
+        }
+    }
+
+    if let Err(e) = validate_dimensions(&settings.dimensions, &settings.combination) {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": e
+                })
+            )
+        );
+    }
+
+    if let Some(allowed_emojis) = settings_data.allowed_emojis.clone() {
+        let mut emojis: Vec<String> = Vec::new();
+        for emoji in allowed_emojis {
+            let emoji = emoji.trim().to_string();
+            if !emoji.is_empty() && !emojis.contains(&emoji) {
+                emojis.push(emoji);
+            }
+        }
+
+        if
+            emojis.is_empty() ||
+            emojis.len() > MAX_ALLOWED_EMOJIS ||
+            emojis.iter().any(|e| e.len() > MAX_EMOJI_LENGTH)
+        {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": format!("Angiv mellem 1 og {} emojis", MAX_ALLOWED_EMOJIS)
+                    })
+                )
+            );
+        }
+        settings.allowed_emojis = emojis;
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    collection
+        .update_one(
+            doc! { "_id": object_id },
+            doc! {
+                "$set": {
+                    "settings": mongodb::bson::to_bson(&settings).map_err(ErrorInternalServerError)?,
+                    "updated_at": now
+                }
+            },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+
+    srv.do_send(RoomSettingsChanged {
+        room_id: room_id.to_string(),
+        settings: settings.clone(),
+    });
+
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+// Opretter en ny historie og gør den til rummets aktuelle, som `new_story` over WebSocket
+#[post("/rooms/{room_id}/stories")]
+pub async fn create_story(
+    user: ApiUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>,
+    story_data: web::Json<CreateStoryDto>,
+    srv: web::Data<Addr<GameServer>>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::StoriesWrite)?.to_string();
+
+    let title = story_data.title.trim();
+    if title.is_empty() {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Historien skal have en titel"
+                })
+            )
+        );
+    }
+
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(room_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt rum ID"
+                    })
+                )
+            );
+        }
+    };
+    let is_participant = db
+        .collection::<GameRoom>("game_rooms")
+        .find_one(doc! { "_id": object_id, "participants": &user_id }, None).await
+        .map_err(ErrorInternalServerError)?
+        .is_some();
+    if !is_participant {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Du har ikke adgang til dette spilrum"
+                })
+            )
+        );
+    }
+
+    let story = Story {
+        id: mongodb::bson::oid::ObjectId::new().to_string(),
+        room_id: room_id.to_string(),
+        title: title.to_string(),
+        description: story_data.description.clone(),
+        votes: Vec::new(),
+        final_score: None,
+        voting_deadline: None,
+        started_at: None,
+        rounds: 0,
+        anonymous: false,
+    };
+    let story = handle_new_story(&db, &room_id, story).await.map_err(|e|
+        ErrorInternalServerError(e.to_string())
+    )?;
+
+    srv.do_send(WebSocketMessage {
+        message_type: "new_story".to_string(),
+        content: new_story_content(&db, &room_id, &story).await,
+        room_id: room_id.to_string(),
+        user_id,
+    });
+
+    Ok(HttpResponse::Created().json(story))
+}
+
+// Afgiv en stemme uden at være forbundet via WebSocket
+#[post("/rooms/{room_id}/votes")]
+pub async fn submit_vote(
+    user: AuthUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>,
+    vote_data: web::Json<SubmitVoteDto>,
+    srv: web::Data<Addr<GameServer>>
+) -> Result<HttpResponse> {
+    let user_id = user.id;
+
+    let collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(room_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt rum ID"
+                    })
+                )
+            );
+        }
+    };
+
+    let room = match
+        collection
+            .find_one(doc! { "_id": object_id }, None).await
+            .map_err(ErrorInternalServerError)?
+    {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Spilrum ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+
+    if !room.participants.contains(&user_id) {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Du har ikke adgang til dette spilrum"
+                })
+            )
+        );
+    }
+
+    if room.current_story.as_ref().map(|story| &story.id) != Some(&vote_data.story_id) {
+        return Ok(
+            HttpResponse::Conflict().json(
+                serde_json::json!({
+                    "message": "Historien er ikke åben for afstemning"
+                })
+            )
+        );
+    }
+
+    let vote_data = vote_data.into_inner();
+    if vote_data.value.is_none() && vote_data.values.is_none() {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Angiv en værdi"
+                })
+            )
+        );
+    }
+
+    let vote = match
+        handle_vote(
+            &db,
+            room_id.as_str(),
+            &user_id,
+            &vote_data.story_id,
+            vote_data.value.unwrap_or(0),
+            vote_data.values
+        ).await
+    {
+        Ok(vote) => vote,
+        Err(e) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": e.to_string()
+                    })
+                )
+            );
+        }
+    };
+
+    // Fortæl de forbundne deltagere at der er stemt
+    srv.do_send(WebSocketMessage {
+        message_type: "vote".to_string(),
+        content: serde_json::json!({
+            "story_id": vote_data.story_id,
+            "value": vote.value,
+            "values": vote.dimension_values
+        }),
+        room_id: room_id.to_string(),
+        user_id: user_id.clone(),
+    });
+
+    reveal_if_ready(&db, room_id.as_str(), srv.get_ref()).await;
+
+    Ok(HttpResponse::Ok().json(vote))
+}
+
+const MAX_ACTUAL_VALUE: f64 = 10_000.0;
+const MAX_ACTUAL_NOTE_LENGTH: usize = 500;
+
+// Registrerer den faktiske indsats på en afsluttet historie, fx når den er leveret
+#[put("/rooms/{room_id}/completed-stories/{story_id}/actual")]
+pub async fn record_actual_effort(
+    user: ApiUser,
+    db: web::Data<Database>,
+    path: web::Path<(String, String)>,
+    actual_data: web::Json<RecordActualDto>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::StoriesWrite)?.to_string();
+    let (room_id, story_id) = path.into_inner();
+
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(&room_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt rum ID"
+                    })
+                )
+            );
+        }
+    };
+    let room = match
+        db
+            .collection::<GameRoom>("game_rooms")
+            .find_one(doc! { "_id": object_id }, None).await
+            .map_err(ErrorInternalServerError)?
+    {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Spilrum ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+    if !room.participants.contains(&user_id) {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Du har ikke adgang til dette spilrum"
+                })
+            )
+        );
+    }
+
+    let value = actual_data.value;
+    if !value.is_finite() || value <= 0.0 || value > MAX_ACTUAL_VALUE {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("Den faktiske indsats skal være mellem 0 og {}", MAX_ACTUAL_VALUE)
+                })
+            )
+        );
+    }
+    let note = actual_data.note
+        .as_deref()
+        .map(str::trim)
+        .filter(|note| !note.is_empty())
+        .map(str::to_string);
+    if note.as_ref().is_some_and(|note| note.chars().count() > MAX_ACTUAL_NOTE_LENGTH) {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("Noten må højst være {} tegn", MAX_ACTUAL_NOTE_LENGTH)
+                })
+            )
+        );
+    }
+
+    let actual = ActualEffort {
+        value,
+        unit: actual_data.unit,
+        note,
+        recorded_by: user_id,
+        recorded_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64,
+    };
+    let mut update = doc! { "actual": mongodb::bson::to_bson(&actual).map_err(ErrorInternalServerError)? };
+    if let Some(tags) = actual_data.tags.clone() {
+        match normalize_tags(tags) {
+            Ok(tags) => {
+                update.insert("tags", tags);
+            }
+            Err(e) => {
+                return Ok(
+                    HttpResponse::BadRequest().json(
+                        serde_json::json!({
+                            "message": e
+                        })
+                    )
+                );
+            }
+        }
+    }
+
+    let collection = db.collection::<CompletedStory>("completed_stories");
+    let filter = doc! { "room_id": &room_id, "story_id": &story_id };
+    let result = collection
+        .update_one(filter.clone(), doc! { "$set": update }, None).await
+        .map_err(ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Historie ikke fundet"
+                })
+            )
+        );
+    }
+
+    let story = collection.find_one(filter, None).await.map_err(ErrorInternalServerError)?;
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "story_id": story_id,
+                "actual": story.as_ref().and_then(|story| story.actual.clone()),
+                "tags": story.map(|story| story.tags).unwrap_or_default()
+            })
+        )
+    )
+}
+
+#[get("/rooms/{room_id}/completed-stories")]
+pub async fn get_completed_stories(
+    user: MaybeUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>,
+    query: web::Query<CompletedStoriesQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.id_in_room(room_id.as_str())?.to_string();
+
+    // Tjek om brugeren har adgang til rummet
+    let rooms_collection = db.collection::<GameRoom>("game_rooms");
+    let object_id = mongodb::bson::oid::ObjectId
+        ::parse_str(room_id.as_str())
+        .map_err(|_| ErrorInternalServerError("Ugyldigt rum ID"))?;
+
+    let room = match
+        rooms_collection
+            .find_one(doc! { "_id": object_id }, None).await
+            .map_err(ErrorInternalServerError)?
+    {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                "message": "Spilrum ikke fundet"
+            })
+                )
+            );
+        }
+    };
+
+    if !room.participants.contains(&user_id) {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+            "message": "Du har ikke adgang til dette spilrum"
+        })
+            )
+        );
+    }
+
+    // Hent gemte historier
+    let completed_stories_collection = db.collection::<CompletedStory>("completed_stories");
+    let mut completed_stories = Vec::new();
+
+    let mut filter = doc! { "room_id": room_id.as_str() };
+    if let Some(session_id) = query.session_id.as_deref() {
+        filter.insert("session_id", session_id);
+    }
+    let mut cursor = completed_stories_collection
+        .find(filter, None).await
+        .map_err(ErrorInternalServerError)?;
+
+    while let Ok(Some(mut story)) = cursor.try_next().await {
+        if room.settings.anonymous_votes || story.anonymous {
+            story.votes = anonymize_votes(story.votes, Some(&user_id));
+            story.confidence = story.confidence.map(|c| c.anonymized_for(Some(&user_id)));
+        }
+        completed_stories.push(story);
+    }
+
+    Ok(HttpResponse::Ok().json(completed_stories))
+}
+
+#[get("/rooms/{room_id}/ws")]
+pub async fn room_ws(
+    req: HttpRequest,
+    WsUser(user): WsUser,
+    room_id: web::Path<String>,
+    stream: web::Payload,
+    srv: web::Data<actix::Addr<GameServer>>,
+    db: web::Data<Database>
+) -> Result<HttpResponse> {
+    println!("WebSocket forbindelse forsøgt oprettet for rum: {}", room_id);
+    let user_id = user.id;
+
+    // Hent brugerinfo
+    let users_collection = db.collection::<User>("users");
+    let user_object_id = mongodb::bson::oid::ObjectId
+        ::parse_str(&user_id)
+        .map_err(|_| ErrorInternalServerError("Ugyldigt bruger ID"))?;
+
+    let user = users_collection
+        .find_one(doc! { "_id": user_object_id }, None).await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorInternalServerError("Bruger ikke fundet"))?;
+
+    let room_id = room_id.into_inner();
+    let is_admin = is_room_admin(&db, &room_id, &user_id).await.map_err(
+        ErrorInternalServerError
+    )?;
+
+    // Indstillingerne hentes før sessionen tilmeldes, så fx anonym afstemning gælder fra start
+    let settings = load_room_settings(&db, &room_id).await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?
+        .unwrap_or_default();
+
+    // Opret en ny WebSocket session
+    let mut ws = WebSocketSession::new(
+        room_id,
+        user_id.clone(),
+        user.username,
+        user.profile_image,
+        is_admin,
+        srv.get_ref().clone(),
+        db.get_ref().clone()
+    );
+    ws.settings = settings;
+
+    println!("WebSocket session oprettet, opgraderer forbindelse...");
+
+    // Opgrader HTTP forbindelsen til WebSocket
+    ws::start(ws, &req, stream)
+}
+
+// WebSocket for gæster. Kræver det rumbundne gæstetoken fra /guest/join eller /guest/create.
+#[get("/rooms/{room_id}/guest-ws")]
+pub async fn guest_room_ws(
+    req: HttpRequest,
+    WsGuest(guest): WsGuest,
+    stream: web::Payload,
+    room_id: web::Path<String>,
+    srv: web::Data<Addr<GameServer>>,
+    db: web::Data<Database>
+) -> Result<HttpResponse> {
+    let room_id = room_id.into_inner();
+    if guest.room_id != room_id {
+        return Err(AuthError::WrongRoom.into());
+    }
+
+    start_guest_ws(req, stream, room_id, guest.id, srv, db).await
+}
+
+// Den gamle gæste-URL uden token. Slås kun til med ALLOW_LEGACY_GUEST_URLS
+// mens ældre klienter udfases, og fjernes derefter.
+#[get("/rooms/{room_id}/guest-ws/{guest_id}")]
+pub async fn legacy_guest_room_ws(
+    req: HttpRequest,
+    stream: web::Payload,
+    path: web::Path<(String, String)>,
+    srv: web::Data<Addr<GameServer>>,
+    db: web::Data<Database>
+) -> Result<HttpResponse> {
+    if !legacy_guest_urls_enabled() {
+        return Ok(
+            HttpResponse::Gone().json(
+                serde_json::json!({
+                    "message": "Denne URL er udfaset. Brug /rooms/{room_id}/guest-ws?token=..."
+                })
+            )
+        );
+    }
+    let (room_id, guest_id) = path.into_inner();
+    println!("ADVARSEL: Gæst {} bruger den udfasede guest-ws URL", guest_id);
+
+    start_guest_ws(req, stream, room_id, guest_id, srv, db).await
+}
+
+async fn start_guest_ws(
+    req: HttpRequest,
+    stream: web::Payload,
+    room_id: String,
+    guest_id: String,
+    srv: web::Data<Addr<GameServer>>,
+    db: web::Data<Database>
+) -> Result<HttpResponse> {
+    println!("Guest WebSocket forbindelse for rum {} med guest ID: {}", room_id, guest_id);
+
+    // Hent guest info
+    let guests_collection = db.collection::<GuestUser>("guest_users");
+    let guest_object_id = mongodb::bson::oid::ObjectId
+        ::parse_str(&guest_id)
+        .map_err(|_| ErrorUnauthorized("Ugyldigt guest ID"))?;
+
+    let guest = guests_collection
+        .find_one(doc! { "_id": guest_object_id }, None).await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorUnauthorized("Guest ikke fundet"))?;
+
+    let is_admin = is_room_admin(&db, &room_id, &guest_id).await.map_err(
+        ErrorInternalServerError
+    )?;
+
+    let settings = load_room_settings(&db, &room_id).await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?
+        .unwrap_or_default();
+
+    // Opret en ny WebSocket session
+    let mut ws = WebSocketSession::new(
+        room_id.clone(),
+        guest_id.clone(),
+        guest.username,
+        guest.profile_image,
+        is_admin,
+        srv.get_ref().clone(),
+        db.get_ref().clone()
+    );
+    ws.settings = settings;
+
+    println!("Guest WebSocket session oprettet, opgraderer forbindelse...");
+
+    // Opgrader HTTP forbindelsen til WebSocket
+    ws::start(ws, &req, stream)
+}
+
+#[get("/rooms/{room_id}/info")]
+pub async fn get_room_info(
+    db: web::Data<Database>,
+    room_id: web::Path<String>
+) -> Result<HttpResponse> {
+    let collection = db.collection::<GameRoom>("game_rooms");
+
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(room_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt rum ID"
+                    })
+                )
+            );
+        }
+    };
+
+    let room = match collection.find_one(doc! { "_id": object_id }, None).await {
+        Ok(Some(room)) => room,
+        Ok(None) => {
             return Ok(
                 HttpResponse::NotFound().json(
                     serde_json::json!({
@@ -910,9 +2147,13 @@ pub async fn get_room_info(
 
 #[post("/guest/join")]
 pub async fn guest_join_room(
+    req: HttpRequest,
     db: web::Data<Database>,
+    limiter: web::Data<RateLimiter>,
     join_data: web::Json<GuestJoinDto>
 ) -> Result<HttpResponse> {
+    limiter.check_quota(Quota::GuestCreate, &client_ip(&req)).await?;
+
     println!("=== GUEST JOIN ROOM ENDPOINT HIT ===");
     println!("Username: {}", join_data.username);
     println!("Room code: {}", join_data.room_code);
@@ -996,16 +2237,24 @@ pub async fn guest_join_room(
         current_story: room.current_story,
         completed_stories: room.completed_stories,
         stories: room.stories,
+        team_id: room.team_id,
+        active_session_id: room.active_session_id,
+        reference_stories: room.reference_stories,
+        settings: room.settings,
+        sealed_voters: Vec::new(),
         created_at: room.created_at,
         updated_at: now,
     };
 
+    let guest_token = create_guest_token(&guest_user_id, &room_response.id)?;
+
     // Return response with guest session info
     Ok(
         HttpResponse::Ok().json(
             serde_json::json!({
-        "room": room_response,
+        "room": room_response.apply_vote_privacy(&guest_user_id),
         "guest_id": guest_user_id,
+        "guest_token": guest_token,
         "is_guest": true
     })
         )
@@ -1014,9 +2263,16 @@ pub async fn guest_join_room(
 
 #[post("/guest/create")]
 pub async fn guest_create_room(
+    req: HttpRequest,
     db: web::Data<Database>,
+    limiter: web::Data<RateLimiter>,
     room_data: web::Json<GuestCreateRoomDto>
 ) -> Result<HttpResponse> {
+    // Opretter både en gæst og et rum
+    let ip = client_ip(&req);
+    limiter.check_quota(Quota::GuestCreate, &ip).await?;
+    limiter.check_quota(Quota::RoomCreate, &ip).await?;
+
     let collection = db.collection::<GameRoom>("game_rooms");
     let guests_collection = db.collection::<GuestUser>("guest_users");
 
@@ -1047,6 +2303,15 @@ pub async fn guest_create_room(
         current_story: None,
         completed_stories: Vec::new(),
         stories: Vec::new(),
+        team_id: None,
+        sessions: Vec::new(),
+        active_session_id: None,
+        reference_stories: Vec::new(),
+        settings: RoomSettings {
+            mode: room_data.mode,
+            ..RoomSettings::default()
+        },
+        dot_votes: HashMap::new(),
         created_at: now,
         updated_at: now,
     };
@@ -1071,18 +2336,133 @@ pub async fn guest_create_room(
         current_story: new_room.current_story,
         completed_stories: new_room.completed_stories,
         stories: new_room.stories,
+        team_id: new_room.team_id,
+        active_session_id: new_room.active_session_id,
+        reference_stories: new_room.reference_stories,
+        settings: new_room.settings,
+        sealed_voters: Vec::new(),
         created_at: new_room.created_at,
         updated_at: now,
     };
 
+    let guest_token = create_guest_token(&guest_user_id, &room_id)?;
+
     // Return response with guest session info
     Ok(
         HttpResponse::Created().json(
             serde_json::json!({
         "room": room_response,
         "guest_id": guest_user_id,
+        "guest_token": guest_token,
         "is_guest": true
     })
         )
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn story(id: &str) -> Story {
+        Story {
+            id: id.to_string(),
+            room_id: "room".to_string(),
+            title: format!("Historie {}", id),
+            description: None,
+            votes: Vec::new(),
+            final_score: None,
+            voting_deadline: None,
+            started_at: None,
+            rounds: 0,
+            anonymous: false,
+        }
+    }
+
+    fn dot_room(story_ids: &[&str], dot_votes: &[(&str, &[(&str, u32)])]) -> GameRoom {
+        GameRoom {
+            id: None,
+            name: "Rum".to_string(),
+            invite_code: "abc".to_string(),
+            admin_id: "admin".to_string(),
+            participants: vec!["admin".to_string()],
+            current_story: None,
+            completed_stories: Vec::new(),
+            stories: story_ids.iter().map(|id| story(id)).collect(),
+            team_id: None,
+            sessions: Vec::new(),
+            active_session_id: None,
+            reference_stories: Vec::new(),
+            settings: RoomSettings {
+                mode: RoomMode::DotVoting,
+                dot_budget: 5,
+                ..RoomSettings::default()
+            },
+            dot_votes: dot_votes
+                .iter()
+                .map(|(user_id, allocation)| {
+                    let allocation = allocation
+                        .iter()
+                        .map(|(story_id, dots)| (story_id.to_string(), *dots))
+                        .collect();
+                    (user_id.to_string(), allocation)
+                })
+                .collect(),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn allocation(entries: &[(&str, u32)]) -> BTreeMap<String, u32> {
+        entries
+            .iter()
+            .map(|(story_id, dots)| (story_id.to_string(), *dots))
+            .collect()
+    }
+
+    #[test]
+    fn dot_ranking_sums_dots_across_participants() {
+        let room = dot_room(&["a", "b", "c"], &[("u1", &[("b", 3), ("c", 1)]), ("u2", &[("c", 4)])]);
+        let ranking = dot_ranking(&room);
+        let order: Vec<(&str, f64, usize)> = ranking
+            .iter()
+            .map(|r| (r.story_id.as_str(), r.score, r.rank))
+            .collect();
+        assert_eq!(order, vec![("c", 5.0, 1), ("b", 3.0, 2), ("a", 0.0, 3)]);
+    }
+
+    #[test]
+    fn dot_ranking_keeps_backlog_order_on_ties() {
+        let room = dot_room(&["a", "b", "c"], &[("u1", &[("c", 2), ("a", 2)])]);
+        let order: Vec<String> = dot_ranking(&room)
+            .into_iter()
+            .map(|r| r.story_id)
+            .collect();
+        assert_eq!(order, vec!["a", "c", "b"]);
+    }
+
+    #[test]
+    fn dot_allocation_within_budget_drops_empty_entries() {
+        let room = dot_room(&["a", "b"], &[]);
+        let result = validate_dot_allocation(&room, allocation(&[("a", 5), ("b", 0)]));
+        assert_eq!(result, Ok(allocation(&[("a", 5)])));
+    }
+
+    #[test]
+    fn dot_allocation_over_budget_is_rejected() {
+        let room = dot_room(&["a", "b"], &[]);
+        assert!(validate_dot_allocation(&room, allocation(&[("a", 3), ("b", 3)])).is_err());
+    }
+
+    #[test]
+    fn dot_allocation_overflow_is_rejected() {
+        let room = dot_room(&["a", "b"], &[]);
+        assert!(validate_dot_allocation(&room, allocation(&[("a", u32::MAX), ("b", 2)])).is_err());
+    }
+
+    #[test]
+    fn dot_allocation_to_unknown_story_is_rejected() {
+        let room = dot_room(&["a"], &[]);
+        assert!(validate_dot_allocation(&room, allocation(&[("x", 1)])).is_err());
+    }
+}
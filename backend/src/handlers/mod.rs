@@ -1,3 +1,13 @@
 pub mod auth;
 pub mod game_room; 
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod chat;
+pub mod notification;
+pub mod oidc;
+pub mod two_factor;
+pub mod export;
+pub mod api_token;
+pub mod team;
+pub mod room_session;
+pub mod analytics;
+pub mod reference;
\ No newline at end of file
@@ -0,0 +1,127 @@
+use actix_web::{ get, post, web, HttpResponse, Result, error::ErrorInternalServerError };
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::Database;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use crate::middleware::auth::AuthUser;
+use crate::models::game_room::Story;
+use crate::models::notification::{
+    Notification,
+    NotificationKind,
+    NotificationResponse,
+    NotificationQuery,
+};
+
+// Antal notifikationer der returneres pr. kald
+const NOTIFICATION_PAGE_SIZE: i64 = 50;
+
+// Giver alle rummets deltagere besked om at stemmerne på en historie er afsløret
+pub async fn notify_results_ready(
+    db: &Database,
+    room_id: &str,
+    participants: &[String],
+    story: &Story,
+    final_score: i32
+) -> Result<(), Box<dyn std::error::Error>> {
+    if participants.is_empty() {
+        return Ok(());
+    }
+    let collection = db.collection::<Notification>("notifications");
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    let notifications: Vec<Notification> = participants
+        .iter()
+        .map(|user_id| Notification {
+            id: None,
+            user_id: user_id.clone(),
+            kind: NotificationKind::ResultsReady,
+            room_id: room_id.to_string(),
+            story_id: story.id.clone(),
+            title: story.title.clone(),
+            final_score: Some(final_score),
+            read: false,
+            created_at: now,
+        })
+        .collect();
+
+    collection.insert_many(notifications, None).await?;
+    println!("Resultat-notifikationer oprettet for historie {} i rum {}", story.id, room_id);
+    Ok(())
+}
+
+#[get("/notifications")]
+pub async fn get_notifications(
+    user: AuthUser,
+    db: web::Data<Database>,
+    query: web::Query<NotificationQuery>
+) -> Result<HttpResponse> {
+    let user_id = user.id;
+    let collection = db.collection::<Notification>("notifications");
+
+    let mut filter = doc! { "user_id": &user_id };
+    if query.unread_only {
+        filter.insert("read", false);
+    }
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .limit(NOTIFICATION_PAGE_SIZE)
+        .build();
+
+    let mut cursor = collection.find(filter, options).await.map_err(ErrorInternalServerError)?;
+    let mut notifications: Vec<NotificationResponse> = Vec::new();
+    while let Some(notification) = cursor.try_next().await.map_err(ErrorInternalServerError)? {
+        notifications.push(notification.into());
+    }
+
+    Ok(HttpResponse::Ok().json(notifications))
+}
+
+#[post("/notifications/{notification_id}/read")]
+pub async fn mark_notification_read(
+    user: AuthUser,
+    db: web::Data<Database>,
+    notification_id: web::Path<String>
+) -> Result<HttpResponse> {
+    let user_id = user.id;
+    let collection = db.collection::<Notification>("notifications");
+
+    let object_id = match mongodb::bson::oid::ObjectId::parse_str(notification_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": "Ugyldigt notifikations ID"
+                    })
+                )
+            );
+        }
+    };
+
+    let update_result = collection
+        .update_one(
+            doc! { "_id": object_id, "user_id": &user_id },
+            doc! { "$set": { "read": true } },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+
+    if update_result.matched_count == 0 {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Notifikation ikke fundet"
+                })
+            )
+        );
+    }
+
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "message": "Notifikation markeret som læst"
+            })
+        )
+    )
+}
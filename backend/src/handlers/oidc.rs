@@ -0,0 +1,236 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Result, error::ErrorInternalServerError};
+use bcrypt::{hash, DEFAULT_COST};
+use mongodb::bson::doc;
+use mongodb::Database;
+use crate::handlers::auth::{create_session, generate_token, guest_id_from_token, now_secs};
+use crate::handlers::user::merge_guest_into_user;
+use crate::mailer::app_base_url;
+use crate::models::oidc::{OidcLoginState, OidcLoginQuery, OidcCallbackQuery};
+use crate::models::user::{User, ExternalIdentity};
+use crate::oidc::{sso_required, IdentityClaims, OidcClient};
+
+// Tid brugeren har til at logge ind hos udbyderen
+const OIDC_LOGIN_TTL_SECS: i64 = 600;
+
+// Fortæller klienten om SSO-knappen skal vises, og om password-login er slået fra
+#[get("/auth/oidc/config")]
+pub async fn oidc_config(oidc: Option<web::Data<OidcClient>>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "enabled": oidc.is_some(),
+        "sso_required": sso_required()
+    })))
+}
+
+#[get("/auth/oidc/login")]
+pub async fn oidc_login(
+    db: web::Data<Database>,
+    oidc: Option<web::Data<OidcClient>>,
+    query: web::Query<OidcLoginQuery>,
+) -> Result<HttpResponse> {
+    let Some(oidc) = oidc else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "message": "Single sign-on er ikke slået til"
+        })));
+    };
+
+    let now = now_secs();
+    let login_state = OidcLoginState {
+        id: None,
+        state: generate_token(),
+        nonce: generate_token(),
+        code_verifier: generate_token(),
+        guest_token: query.guest_token.clone(),
+        created_at: now,
+        expires_at: now + OIDC_LOGIN_TTL_SECS,
+    };
+
+    let authorization_url = oidc
+        .authorization_url(&login_state.state, &login_state.nonce, &login_state.code_verifier)
+        .await
+        .map_err(|e| {
+            println!("{}", e);
+            ErrorInternalServerError("Kunne ikke kontakte identitetsudbyderen")
+        })?;
+
+    db.collection::<OidcLoginState>("oidc_login_states")
+        .insert_one(&login_state, None)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((actix_web::http::header::LOCATION, authorization_url))
+        .finish())
+}
+
+// Udbyderen sender browseren hertil. Svaret er altid en redirect tilbage til klienten,
+// med sessionens tokens i URL-fragmentet så de ikke havner i serverlogs.
+#[get("/auth/oidc/callback")]
+pub async fn oidc_callback(
+    req: HttpRequest,
+    db: web::Data<Database>,
+    oidc: Option<web::Data<OidcClient>>,
+    query: web::Query<OidcCallbackQuery>,
+) -> Result<HttpResponse> {
+    let Some(oidc) = oidc else {
+        return Ok(sso_error_redirect("not_enabled"));
+    };
+    if let Some(error) = &query.error {
+        println!("SSO login afvist af udbyderen: {}", error);
+        return Ok(sso_error_redirect("denied"));
+    }
+    let (Some(code), Some(state)) = (&query.code, &query.state) else {
+        return Ok(sso_error_redirect("invalid_request"));
+    };
+
+    // Staten kan kun bruges én gang
+    let login_state = db.collection::<OidcLoginState>("oidc_login_states")
+        .find_one_and_delete(doc! { "state": state, "expires_at": { "$gt": now_secs() } }, None)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let Some(login_state) = login_state else {
+        return Ok(sso_error_redirect("expired"));
+    };
+
+    let claims = match oidc.exchange_code(code, &login_state.code_verifier, &login_state.nonce).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            println!("{}", e);
+            return Ok(sso_error_redirect("invalid_token"));
+        }
+    };
+
+    let user = match find_or_link_user(&db, &oidc, &claims).await {
+        Ok(user) => user,
+        Err(reason) => return Ok(sso_error_redirect(reason)),
+    };
+    let user_id = user.id.unwrap().to_string();
+
+    // Et udløbet gæstetoken skal ikke forhindre login; gæsten er så bare ikke flettet ind
+    if let Ok(Some(guest_id)) = guest_id_from_token(login_state.guest_token.as_deref()) {
+        merge_guest_into_user(&db, &guest_id, &user_id)
+            .await
+            .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    }
+
+    let tokens = create_session(&db, &user_id, &req).await?;
+    let location = format!(
+        "{}/auth/sso#token={}&refresh_token={}&expires_in={}",
+        app_base_url(),
+        tokens.token,
+        tokens.refresh_token,
+        tokens.expires_in
+    );
+    Ok(HttpResponse::Found()
+        .insert_header((actix_web::http::header::LOCATION, location))
+        .finish())
+}
+
+// To-faktor håndteres af identitetsudbyderen og kræves ikke igen her.
+// Finder brugeren ud fra udbyderens identitet. Første gang kobles identiteten til
+// en eksisterende konto med samme bekræftede email, eller der oprettes en ny konto.
+async fn find_or_link_user(
+    db: &Database,
+    oidc: &OidcClient,
+    claims: &IdentityClaims,
+) -> std::result::Result<User, &'static str> {
+    let collection = db.collection::<User>("users");
+    let identity = ExternalIdentity {
+        issuer: claims.iss.clone(),
+        subject: claims.sub.clone(),
+    };
+
+    let linked = collection
+        .find_one(
+            doc! { "identities": { "$elemMatch": { "issuer": &identity.issuer, "subject": &identity.subject } } },
+            None,
+        )
+        .await
+        .map_err(|e| {
+            println!("Fejl ved opslag af SSO bruger: {}", e);
+            "server_error"
+        })?;
+    if let Some(user) = linked {
+        let email = claims.email.as_deref().unwrap_or(&user.email);
+        if !oidc.email_domain_allowed(email) {
+            return Err("domain_not_allowed");
+        }
+        return Ok(user);
+    }
+
+    // Uden en bekræftet email kan identiteten ikke kobles sikkert til en konto
+    let Some(email) = claims.email.clone().filter(|_| claims.email_verified == Some(true)) else {
+        return Err("email_not_verified");
+    };
+    if !oidc.email_domain_allowed(&email) {
+        return Err("domain_not_allowed");
+    }
+
+    // Kun konti hvor ejeren har bekræftet emailen kobles automatisk. Ellers kunne en anden have
+    // oprettet kontoen med offerets email og beholde adgangen via sin adgangskode.
+    let existing = collection
+        .find_one_and_update(
+            doc! { "email": &email, "email_verified": true },
+            doc! {
+                "$addToSet": { "identities": { "issuer": &identity.issuer, "subject": &identity.subject } },
+                "$set": { "email_verified": true }
+            },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(|e| {
+            println!("Fejl ved kobling af SSO identitet: {}", e);
+            "server_error"
+        })?;
+    if let Some(user) = existing {
+        println!("SSO identitet koblet til eksisterende bruger {}", user.id.unwrap());
+        return Ok(user);
+    }
+
+    let unverified = collection
+        .find_one(doc! { "email": &email }, None)
+        .await
+        .map_err(|e| {
+            println!("Fejl ved opslag af SSO bruger: {}", e);
+            "server_error"
+        })?;
+    if unverified.is_some() {
+        println!("SSO login afvist: lokal konto med samme email er ikke bekræftet");
+        return Err("account_not_verified");
+    }
+
+    // SSO-brugere får en tilfældig adgangskode som ingen kender; de kan nulstille den senere
+    let password_hash = hash(generate_token().as_bytes(), DEFAULT_COST).map_err(|_| "server_error")?;
+    let username = claims.name.clone()
+        .or_else(|| claims.preferred_username.clone())
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+
+    let mut new_user = User {
+        id: None,
+        email,
+        username,
+        password_hash,
+        profile_image: None,
+        email_verified: true,
+        identities: vec![identity],
+        two_factor: None,
+        is_admin: false,
+    };
+    let result = collection
+        .insert_one(&new_user, None)
+        .await
+        .map_err(|e| {
+            println!("Fejl ved oprettelse af SSO bruger: {}", e);
+            "server_error"
+        })?;
+    new_user.id = result.inserted_id.as_object_id();
+    println!("Ny bruger oprettet via SSO: {}", new_user.username);
+    Ok(new_user)
+}
+
+fn sso_error_redirect(reason: &str) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header((actix_web::http::header::LOCATION, format!("{}/auth?sso_error={}", app_base_url(), reason)))
+        .finish()
+}
@@ -0,0 +1,323 @@
+use actix_web::{ get, post, delete, web, HttpResponse, Result, error::ErrorInternalServerError };
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::options::FindOptions;
+use mongodb::Database;
+use std::collections::{ BTreeMap, HashSet };
+use std::time::{ SystemTime, UNIX_EPOCH };
+use crate::middleware::auth::{ ApiUser, MaybeUser };
+use crate::models::api_token::TokenScope;
+use crate::models::game_room::{
+    GameRoom,
+    CompletedStory,
+    ReferenceStory,
+    Story,
+    PinReferenceStoryDto,
+    UNKNOWN_CARD,
+};
+use crate::models::similarity::{ most_similar, SimilarStory };
+
+// Antal afsluttede historier der sammenlignes med, nyeste først
+const SIMILARITY_CANDIDATES: i64 = 500;
+const MAX_SUGGESTIONS: usize = 3;
+const MIN_SIMILARITY: f64 = 0.2;
+const MAX_REFERENCES_PER_VALUE: usize = 3;
+
+async fn load_room(db: &Database, room_id: &str) -> Result<Option<GameRoom>, mongodb::error::Error> {
+    let Ok(object_id) = ObjectId::parse_str(room_id) else {
+        return Ok(None);
+    };
+    db.collection::<GameRoom>("game_rooms").find_one(doc! { "_id": object_id }, None).await
+}
+
+// Rummet selv og, for team-rum, de af teamets andre rum som alle rummets deltagere også
+// deltager i. Forslag og referencer vises for hele rummet, også gæster, så de må ikke
+// afsløre historier fra rum som nogen i rummet ikke har adgang til.
+async fn related_room_ids(db: &Database, room: &GameRoom) -> Result<Vec<String>, mongodb::error::Error> {
+    let mut room_ids: Vec<String> = room.id.iter().map(ObjectId::to_string).collect();
+    if let Some(team_id) = room.team_id.as_deref() {
+        let mut cursor = db
+            .collection::<GameRoom>("game_rooms")
+            .find(doc! { "team_id": team_id, "participants": { "$all": &room.participants } }, None).await?;
+        while let Some(team_room) = cursor.try_next().await? {
+            if let Some(id) = team_room.id.map(|id| id.to_string()) {
+                if !room_ids.contains(&id) {
+                    room_ids.push(id);
+                }
+            }
+        }
+    }
+    Ok(room_ids)
+}
+
+pub async fn suggest_similar_stories(
+    db: &Database,
+    room: &GameRoom,
+    story: &Story
+) -> Result<Vec<SimilarStory>, mongodb::error::Error> {
+    let room_ids = related_room_ids(db, room).await?;
+    let options = FindOptions::builder()
+        .sort(doc! { "completed_at": -1 })
+        .limit(SIMILARITY_CANDIDATES)
+        .build();
+    let mut cursor = db
+        .collection::<CompletedStory>("completed_stories")
+        .find(doc! { "room_id": { "$in": room_ids } }, options).await?;
+
+    // En historie der er stemt om flere gange optræder kun med sin seneste score
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    let mut candidates: Vec<CompletedStory> = Vec::new();
+    while let Some(candidate) = cursor.try_next().await? {
+        if seen.insert((candidate.room_id.clone(), candidate.title.to_lowercase())) {
+            candidates.push(candidate);
+        }
+    }
+
+    let documents: Vec<String> = candidates
+        .iter()
+        .map(|c| format!("{} {}", c.title, c.description.as_deref().unwrap_or("")))
+        .collect();
+    let query = format!("{} {}", story.title, story.description.as_deref().unwrap_or(""));
+
+    Ok(
+        most_similar(&query, &documents, MAX_SUGGESTIONS, MIN_SIMILARITY)
+            .into_iter()
+            .map(|(index, similarity)| {
+                let candidate = &candidates[index];
+                SimilarStory {
+                    story_id: candidate.story_id.clone(),
+                    room_id: candidate.room_id.clone(),
+                    title: candidate.title.clone(),
+                    final_score: candidate.final_score,
+                    completed_at: candidate.completed_at,
+                    similarity,
+                }
+            })
+            .collect()
+    )
+}
+
+// Indholdet af `new_story` beskeden: historien med lignende tidligere historier i `similar_stories`.
+// Fejler opslaget sendes historien uden forslag.
+pub async fn new_story_content(db: &Database, room_id: &str, story: &Story) -> serde_json::Value {
+    let mut content = serde_json::to_value(story).unwrap_or_default();
+    let suggestions = match load_room(db, room_id).await {
+        Ok(Some(room)) => suggest_similar_stories(db, &room, story).await,
+        Ok(None) => Ok(Vec::new()),
+        Err(e) => Err(e),
+    };
+    match suggestions {
+        Ok(suggestions) => {
+            content["similar_stories"] = serde_json::json!(suggestions);
+        }
+        Err(e) => println!("Kunne ikke finde lignende historier i rum {}: {:?}", room_id, e),
+    }
+    content
+}
+
+// Rummets referencehistorier grupperet efter kortværdi
+#[get("/rooms/{room_id}/reference-stories")]
+pub async fn list_reference_stories(
+    user: MaybeUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>
+) -> Result<HttpResponse> {
+    let user_id = user.id_in_room(room_id.as_str())?.to_string();
+
+    let room = match load_room(&db, &room_id).await.map_err(ErrorInternalServerError)? {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Spilrum ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+    if !room.participants.contains(&user_id) {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Du har ikke adgang til dette spilrum"
+                })
+            )
+        );
+    }
+
+    let mut by_value: BTreeMap<i32, Vec<ReferenceStory>> = BTreeMap::new();
+    for reference in room.reference_stories {
+        by_value.entry(reference.value).or_default().push(reference);
+    }
+
+    Ok(HttpResponse::Ok().json(by_value))
+}
+
+// Fastgør en afsluttet historie fra rummet eller teamets andre rum som eksempel på en kortværdi
+#[post("/rooms/{room_id}/reference-stories")]
+pub async fn pin_reference_story(
+    user: ApiUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>,
+    pin_data: web::Json<PinReferenceStoryDto>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::RoomsWrite)?.to_string();
+
+    let room = match load_room(&db, &room_id).await.map_err(ErrorInternalServerError)? {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Spilrum ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+    if room.admin_id != user_id {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Kun admin kan fastgøre referencehistorier"
+                })
+            )
+        );
+    }
+
+    let room_ids = related_room_ids(&db, &room).await.map_err(ErrorInternalServerError)?;
+    let story = match
+        db
+            .collection::<CompletedStory>("completed_stories")
+            .find_one(doc! { "story_id": &pin_data.story_id, "room_id": { "$in": room_ids } }, None).await
+            .map_err(ErrorInternalServerError)?
+    {
+        Some(story) => story,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Historie ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+
+    let value = pin_data.value.unwrap_or(story.final_score);
+    if value == UNKNOWN_CARD || !room.settings.deck.contains(&value) {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("{} er ikke et kort i rummets kortbunke", value)
+                })
+            )
+        );
+    }
+
+    // En historie kan kun være reference for én værdi ad gangen
+    let mut references: Vec<ReferenceStory> = room.reference_stories
+        .into_iter()
+        .filter(|r| r.story_id != story.story_id)
+        .collect();
+    if references.iter().filter(|r| r.value == value).count() >= MAX_REFERENCES_PER_VALUE {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!(
+                        "Der kan højst være {} referencehistorier pr. kort",
+                        MAX_REFERENCES_PER_VALUE
+                    )
+                })
+            )
+        );
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let reference = ReferenceStory {
+        value,
+        story_id: story.story_id,
+        room_id: story.room_id,
+        title: story.title,
+        description: story.description,
+        final_score: story.final_score,
+        pinned_by: user_id,
+        pinned_at: now,
+    };
+    references.push(reference.clone());
+
+    db.collection::<GameRoom>("game_rooms")
+        .update_one(
+            doc! { "_id": room.id },
+            doc! {
+                "$set": {
+                    "reference_stories": mongodb::bson::to_bson(&references).map_err(ErrorInternalServerError)?,
+                    "updated_at": now
+                }
+            },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(reference))
+}
+
+#[delete("/rooms/{room_id}/reference-stories/{story_id}")]
+pub async fn unpin_reference_story(
+    user: ApiUser,
+    db: web::Data<Database>,
+    path: web::Path<(String, String)>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::RoomsWrite)?.to_string();
+    let (room_id, story_id) = path.into_inner();
+
+    let room = match load_room(&db, &room_id).await.map_err(ErrorInternalServerError)? {
+        Some(room) => room,
+        None => {
+            return Ok(
+                HttpResponse::NotFound().json(
+                    serde_json::json!({
+                        "message": "Spilrum ikke fundet"
+                    })
+                )
+            );
+        }
+    };
+    if room.admin_id != user_id {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Kun admin kan fjerne referencehistorier"
+                })
+            )
+        );
+    }
+
+    let result = db
+        .collection::<GameRoom>("game_rooms")
+        .update_one(
+            doc! { "_id": room.id, "reference_stories.story_id": &story_id },
+            doc! { "$pull": { "reference_stories": { "story_id": &story_id } } },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Referencehistorie ikke fundet"
+                })
+            )
+        );
+    }
+
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "message": "Referencehistorien er fjernet"
+            })
+        )
+    )
+}
@@ -0,0 +1,285 @@
+use actix::Addr;
+use actix_web::{ get, post, web, HttpResponse, Result, error::ErrorInternalServerError };
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::Database;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use crate::middleware::auth::{ ApiUser, MaybeUser };
+use crate::models::api_token::TokenScope;
+use crate::models::game_room::{ GameRoom, CompletedStory, RoomSession, SessionSummary, StartSessionDto };
+use crate::websocket::{ GameServer, WebSocketMessage };
+
+const MAX_SESSION_NAME_LENGTH: usize = 100;
+
+async fn load_room(db: &Database, room_id: &str) -> Result<Option<GameRoom>> {
+    let Ok(object_id) = ObjectId::parse_str(room_id) else {
+        return Ok(None);
+    };
+    db.collection::<GameRoom>("game_rooms")
+        .find_one(doc! { "_id": object_id }, None).await
+        .map_err(ErrorInternalServerError)
+}
+
+fn room_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(
+        serde_json::json!({
+            "message": "Spilrum ikke fundet"
+        })
+    )
+}
+
+fn room_admin_required() -> HttpResponse {
+    HttpResponse::Forbidden().json(
+        serde_json::json!({
+            "message": "Kun admin kan styre rummets sessioner"
+        })
+    )
+}
+
+pub async fn session_summary(
+    db: &Database,
+    room_id: &str,
+    session: &RoomSession,
+    ended_at: i64
+) -> Result<SessionSummary, mongodb::error::Error> {
+    let mut cursor = db
+        .collection::<CompletedStory>("completed_stories")
+        .find(doc! { "room_id": room_id, "session_id": &session.id }, None).await?;
+    let mut stories = Vec::new();
+    while let Some(story) = cursor.try_next().await? {
+        stories.push(story);
+    }
+    Ok(SessionSummary::from_stories(&stories, session.started_at, ended_at))
+}
+
+// Afslutter rummets aktive session og gemmer dens opsummering
+async fn end_active_session(
+    db: &Database,
+    room: &GameRoom,
+    now: i64
+) -> Result<Option<RoomSession>, mongodb::error::Error> {
+    let Some(active_id) = room.active_session_id.as_deref() else {
+        return Ok(None);
+    };
+    let Some(mut session) = room.sessions
+        .iter()
+        .find(|s| s.id == active_id)
+        .cloned() else {
+        return Ok(None);
+    };
+    let room_id = room.id.map(|id| id.to_string()).unwrap_or_default();
+
+    session.ended_at = Some(now);
+    session.summary = Some(session_summary(db, &room_id, &session, now).await?);
+
+    db.collection::<GameRoom>("game_rooms").update_one(
+        doc! { "_id": room.id, "sessions.id": &session.id },
+        doc! {
+            "$set": {
+                "sessions.$": mongodb::bson::to_bson(&session)?,
+                "active_session_id": null,
+                "updated_at": now
+            }
+        },
+        None
+    ).await?;
+    Ok(Some(session))
+}
+
+// Starter en ny session. Den forrige afsluttes, og rummets estimerede historier ryddes
+// så sessionen begynder forfra, mens de bevares i historikken. Historier der endnu ikke
+// er estimeret følger med over i den nye session.
+#[post("/rooms/{room_id}/sessions")]
+pub async fn start_session(
+    user: ApiUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>,
+    session_data: web::Json<StartSessionDto>,
+    srv: web::Data<Addr<GameServer>>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::RoomsWrite)?.to_string();
+
+    let room = match load_room(&db, &room_id).await? {
+        Some(room) => room,
+        None => {
+            return Ok(room_not_found());
+        }
+    };
+    if room.admin_id != user_id {
+        return Ok(room_admin_required());
+    }
+
+    let name = match session_data.name.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => format!("Session {}", room.sessions.len() + 1),
+    };
+    if name.chars().count() > MAX_SESSION_NAME_LENGTH {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("Navnet må højst være {} tegn", MAX_SESSION_NAME_LENGTH)
+                })
+            )
+        );
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    if let Some(ended) = end_active_session(&db, &room, now).await.map_err(ErrorInternalServerError)? {
+        srv.do_send(WebSocketMessage {
+            message_type: "session_ended".to_string(),
+            content: serde_json::json!(ended),
+            room_id: room_id.to_string(),
+            user_id: "system".to_string(),
+        });
+    }
+
+    let session = RoomSession {
+        id: ObjectId::new().to_string(),
+        name,
+        started_at: now,
+        ended_at: None,
+        summary: None,
+    };
+    let session_bson = mongodb::bson::to_bson(&session).map_err(ErrorInternalServerError)?;
+
+    // Rummet ryddes i én opdatering på databasens egen udgave af rummet, så historier
+    // og stemmer der kommer til imens ikke går tabt. Historier der hverken har en score
+    // eller er estimeret i den forrige session følger med.
+    let estimated = doc! { "$ifNull": ["$completed_stories.id", []] };
+    let is_open = |story: &str| {
+        doc! {
+            "$and": [
+                { "$eq": [{ "$ifNull": [format!("{}.final_score", story), null] }, null] },
+                { "$not": [{ "$in": [format!("{}.id", story), estimated.clone()] }] }
+            ]
+        }
+    };
+    let update = vec![
+        doc! {
+            "$set": {
+                "sessions": { "$concatArrays": [{ "$ifNull": ["$sessions", []] }, [{ "$literal": session_bson }]] },
+                "active_session_id": &session.id,
+                "current_story": { "$cond": [is_open("$current_story"), "$current_story", null] },
+                "stories": {
+                    "$filter": { "input": { "$ifNull": ["$stories", []] }, "as": "story", "cond": is_open("$$story") }
+                },
+                "completed_stories": { "$literal": [] },
+                "dot_votes": { "$literal": {} },
+                "updated_at": now
+            }
+        }
+    ];
+    // Starter en anden samtidig en session, er rummet ikke længere uden aktiv session
+    let result = db
+        .collection::<GameRoom>("game_rooms")
+        .update_one(doc! { "_id": room.id, "active_session_id": null }, update, None).await
+        .map_err(ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(
+            HttpResponse::Conflict().json(
+                serde_json::json!({
+                    "message": "Rummet er blevet ændret imens. Prøv igen."
+                })
+            )
+        );
+    }
+    println!("Session '{}' startet i rum {}", session.name, room_id);
+
+    srv.do_send(WebSocketMessage {
+        message_type: "session_started".to_string(),
+        content: serde_json::json!(session),
+        room_id: room_id.to_string(),
+        user_id: "system".to_string(),
+    });
+
+    Ok(HttpResponse::Created().json(session))
+}
+
+#[post("/rooms/{room_id}/sessions/{session_id}/end")]
+pub async fn end_session(
+    user: ApiUser,
+    db: web::Data<Database>,
+    path: web::Path<(String, String)>,
+    srv: web::Data<Addr<GameServer>>
+) -> Result<HttpResponse> {
+    let user_id = user.require(TokenScope::RoomsWrite)?.to_string();
+    let (room_id, session_id) = path.into_inner();
+
+    let room = match load_room(&db, &room_id).await? {
+        Some(room) => room,
+        None => {
+            return Ok(room_not_found());
+        }
+    };
+    if room.admin_id != user_id {
+        return Ok(room_admin_required());
+    }
+    if room.active_session_id.as_deref() != Some(session_id.as_str()) {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Sessionen er ikke aktiv"
+                })
+            )
+        );
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let Some(session) = end_active_session(&db, &room, now).await.map_err(ErrorInternalServerError)? else {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Session ikke fundet"
+                })
+            )
+        );
+    };
+    println!("Session '{}' afsluttet i rum {}", session.name, room_id);
+
+    srv.do_send(WebSocketMessage {
+        message_type: "session_ended".to_string(),
+        content: serde_json::json!(session),
+        room_id,
+        user_id: "system".to_string(),
+    });
+
+    Ok(HttpResponse::Ok().json(session))
+}
+
+// Rummets sessioner, nyeste først. Den aktive sessions opsummering er foreløbig.
+#[get("/rooms/{room_id}/sessions")]
+pub async fn list_sessions(
+    user: MaybeUser,
+    db: web::Data<Database>,
+    room_id: web::Path<String>
+) -> Result<HttpResponse> {
+    let user_id = user.id_in_room(room_id.as_str())?.to_string();
+
+    let room = match load_room(&db, &room_id).await? {
+        Some(room) => room,
+        None => {
+            return Ok(room_not_found());
+        }
+    };
+    if !room.participants.contains(&user_id) {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Du har ikke adgang til dette spilrum"
+                })
+            )
+        );
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let mut sessions = room.sessions;
+    for session in sessions.iter_mut().filter(|s| s.ended_at.is_none()) {
+        session.summary = Some(
+            session_summary(&db, &room_id, session, now).await.map_err(ErrorInternalServerError)?
+        );
+    }
+    sessions.reverse();
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
@@ -0,0 +1,866 @@
+use actix_web::{ get, post, put, delete, web, HttpResponse, Result, error::ErrorInternalServerError };
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::{ doc, Document };
+use mongodb::bson::oid::ObjectId;
+use mongodb::options::{ FindOptions, FindOneAndUpdateOptions, ReturnDocument, UpdateOptions };
+use mongodb::Database;
+use crate::handlers::auth::now_secs;
+use crate::mailer::{ app_base_url, Mail, Mailer };
+use crate::middleware::auth::AuthUser;
+use crate::models::game_room::{ GameRoom, validate_deck, validate_timer_secs };
+use crate::models::team::{
+    Team,
+    TeamMember,
+    TeamRole,
+    TeamDefaults,
+    TeamResponse,
+    TeamMemberResponse,
+    TeamRoomSummary,
+    CreateTeamDto,
+    UpdateTeamDto,
+    TeamInvitation,
+    TeamInvitationResponse,
+    InviteTeamMemberDto,
+    UpdateTeamMemberDto,
+};
+use crate::models::user::User;
+
+const MAX_TEAM_NAME_LENGTH: usize = 100;
+const MAX_TEAM_MEMBERS: usize = 100;
+const MAX_PENDING_INVITATIONS: usize = 50;
+const TEAM_INVITATION_TTL_SECS: i64 = 7 * 24 * 3600;
+
+fn team_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(
+        serde_json::json!({
+            "message": "Team ikke fundet"
+        })
+    )
+}
+
+fn team_admin_required() -> HttpResponse {
+    HttpResponse::Forbidden().json(
+        serde_json::json!({
+            "message": "Kun teamets administratorer kan gøre dette"
+        })
+    )
+}
+
+// Brugerens email, hvis den er bekræftet. Invitationer gælder kun for bekræftede adresser,
+// så ingen kan overtage en invitation ved at oprette en konto med en andens email.
+async fn verified_email(db: &Database, user_id: &str) -> Result<Option<String>> {
+    let Ok(object_id) = ObjectId::parse_str(user_id) else {
+        return Ok(None);
+    };
+    Ok(
+        db
+            .collection::<User>("users")
+            .find_one(doc! { "_id": object_id }, None).await
+            .map_err(ErrorInternalServerError)?
+            .filter(|user| user.email_verified)
+            .map(|user| user.email)
+    )
+}
+
+// Teamet blev ændret af et andet kald mellem opslag og opdatering
+fn team_changed() -> HttpResponse {
+    HttpResponse::Conflict().json(
+        serde_json::json!({
+            "message": "Teamet er blevet ændret imens. Hent det igen og prøv på ny."
+        })
+    )
+}
+
+fn valid_team_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().count() <= MAX_TEAM_NAME_LENGTH
+}
+
+pub async fn load_team(db: &Database, team_id: &str) -> Result<Option<Team>, Box<dyn std::error::Error>> {
+    let Ok(object_id) = ObjectId::parse_str(team_id) else {
+        return Ok(None);
+    };
+    Ok(db.collection::<Team>("teams").find_one(doc! { "_id": object_id }, None).await?)
+}
+
+// Nye medlemmer bliver deltagere i alle teamets rum
+async fn add_to_team_rooms(db: &Database, team_id: &str, user_id: &str) -> Result<(), mongodb::error::Error> {
+    db.collection::<GameRoom>("game_rooms").update_many(
+        doc! { "team_id": team_id },
+        doc! { "$addToSet": { "participants": user_id }, "$set": { "updated_at": now_secs() } },
+        None
+    ).await?;
+    Ok(())
+}
+
+// Rum personen selv er admin for beholder dem som deltager
+async fn remove_from_team_rooms(db: &Database, team_id: &str, user_id: &str) -> Result<(), mongodb::error::Error> {
+    db.collection::<GameRoom>("game_rooms").update_many(
+        doc! { "team_id": team_id, "admin_id": { "$ne": user_id } },
+        doc! { "$pull": { "participants": user_id }, "$set": { "updated_at": now_secs() } },
+        None
+    ).await?;
+    Ok(())
+}
+
+// Filterbetingelse der kun matcher hvis teamet stadig har en anden administrator end `user_id`.
+// Bruges ved ændringer der kan fjerne den sidste administrator, så to samtidige kald ikke begge lykkes.
+fn other_admin_remains(user_id: &str) -> Document {
+    doc! {
+        "$expr": {
+            "$gt": [
+                {
+                    "$size": {
+                        "$filter": {
+                            "input": "$members",
+                            "cond": {
+                                "$and": [
+                                    { "$eq": ["$$this.role", "admin"] },
+                                    { "$ne": ["$$this.user_id", user_id] }
+                                ]
+                            }
+                        }
+                    }
+                },
+                0
+            ]
+        }
+    }
+}
+
+async fn team_response(db: &Database, team: Team) -> Result<TeamResponse, Box<dyn std::error::Error>> {
+    let users_collection = db.collection::<User>("users");
+    let mut members = Vec::new();
+    for member in team.members {
+        let Ok(object_id) = ObjectId::parse_str(&member.user_id) else {
+            continue;
+        };
+        if let Some(user) = users_collection.find_one(doc! { "_id": object_id }, None).await? {
+            members.push(TeamMemberResponse {
+                user_id: member.user_id,
+                username: user.username,
+                profile_image: user.profile_image,
+                role: member.role,
+                joined_at: member.joined_at,
+            });
+        }
+    }
+
+    Ok(TeamResponse {
+        id: team.id.map(|id| id.to_string()).unwrap_or_default(),
+        name: team.name,
+        members,
+        defaults: team.defaults,
+        created_at: team.created_at,
+        updated_at: team.updated_at,
+    })
+}
+
+// Fjerner en slettet bruger fra sine teams. Tomme teams slettes og
+// teams uden administratorer får det ældste medlem som ny administrator.
+pub async fn remove_user_from_teams(db: &Database, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let teams_collection = db.collection::<Team>("teams");
+    let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+
+    loop {
+        let Some(team) = teams_collection
+            .find_one_and_update(
+                doc! { "members.user_id": user_id },
+                doc! {
+                    "$pull": { "members": { "user_id": user_id } },
+                    "$set": { "updated_at": now_secs() }
+                },
+                options.clone()
+            ).await? else {
+            break;
+        };
+
+        if team.members.is_empty() {
+            let team_id = team.id.map(|id| id.to_string()).unwrap_or_default();
+            let deleted = teams_collection.delete_one(doc! { "_id": team.id, "members": { "$size": 0 } }, None).await?;
+            if deleted.deleted_count > 0 {
+                db.collection::<GameRoom>("game_rooms").update_many(
+                    doc! { "team_id": &team_id },
+                    doc! { "$set": { "team_id": null } },
+                    None
+                ).await?;
+                println!("Team {} slettet da det ikke har flere medlemmer", team_id);
+            }
+            continue;
+        }
+        if team.admin_count() == 0 {
+            if let Some(oldest) = team.members.iter().min_by_key(|m| m.joined_at) {
+                teams_collection.update_one(
+                    doc! {
+                        "_id": team.id,
+                        "members.role": { "$ne": "admin" },
+                        "members.user_id": &oldest.user_id
+                    },
+                    doc! { "$set": { "members.$[member].role": "admin" } },
+                    UpdateOptions::builder()
+                        .array_filters(vec![doc! { "member.user_id": &oldest.user_id }])
+                        .build()
+                ).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[post("/teams")]
+pub async fn create_team(
+    user: AuthUser,
+    db: web::Data<Database>,
+    team_data: web::Json<CreateTeamDto>
+) -> Result<HttpResponse> {
+    let name = team_data.name.trim();
+    if !valid_team_name(name) {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("Navnet skal være mellem 1 og {} tegn", MAX_TEAM_NAME_LENGTH)
+                })
+            )
+        );
+    }
+
+    let now = now_secs();
+    let mut team = Team {
+        id: None,
+        name: name.to_string(),
+        members: vec![TeamMember {
+            user_id: user.id.clone(),
+            role: TeamRole::Admin,
+            joined_at: now,
+        }],
+        invitations: Vec::new(),
+        defaults: TeamDefaults::default(),
+        created_at: now,
+        updated_at: now,
+    };
+    let result = db
+        .collection::<Team>("teams")
+        .insert_one(&team, None).await
+        .map_err(ErrorInternalServerError)?;
+    team.id = result.inserted_id.as_object_id();
+    println!("Team '{}' oprettet af {}", team.name, user.id);
+
+    let response = team_response(&db, team).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Created().json(response))
+}
+
+#[get("/teams")]
+pub async fn list_teams(user: AuthUser, db: web::Data<Database>) -> Result<HttpResponse> {
+    let options = FindOptions::builder()
+        .sort(doc! { "name": 1 })
+        .build();
+    let mut cursor = db
+        .collection::<Team>("teams")
+        .find(doc! { "members.user_id": &user.id }, options).await
+        .map_err(ErrorInternalServerError)?;
+
+    let mut teams = Vec::new();
+    while let Some(team) = cursor.try_next().await.map_err(ErrorInternalServerError)? {
+        teams.push(team_response(&db, team).await.map_err(|e| ErrorInternalServerError(e.to_string()))?);
+    }
+
+    Ok(HttpResponse::Ok().json(teams))
+}
+
+#[get("/teams/{team_id}")]
+pub async fn get_team(
+    user: AuthUser,
+    db: web::Data<Database>,
+    team_id: web::Path<String>
+) -> Result<HttpResponse> {
+    let team = match load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+        Some(team) if team.role_of(&user.id).is_some() => team,
+        _ => {
+            return Ok(team_not_found());
+        }
+    };
+
+    let response = team_response(&db, team).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Ændrer navn og standarder. Standarderne gælder for nye rum, eksisterende rum beholder deres indstillinger.
+#[put("/teams/{team_id}")]
+pub async fn update_team(
+    user: AuthUser,
+    db: web::Data<Database>,
+    team_id: web::Path<String>,
+    team_data: web::Json<UpdateTeamDto>
+) -> Result<HttpResponse> {
+    let mut team = match load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+        Some(team) => team,
+        None => {
+            return Ok(team_not_found());
+        }
+    };
+    match team.role_of(&user.id) {
+        Some(TeamRole::Admin) => {}
+        Some(TeamRole::Member) => {
+            return Ok(team_admin_required());
+        }
+        None => {
+            return Ok(team_not_found());
+        }
+    }
+
+    if let Some(name) = team_data.name.as_deref() {
+        let name = name.trim();
+        if !valid_team_name(name) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": format!("Navnet skal være mellem 1 og {} tegn", MAX_TEAM_NAME_LENGTH)
+                    })
+                )
+            );
+        }
+        team.name = name.to_string();
+    }
+    if let Some(deck) = team_data.deck.clone() {
+        if let Err(e) = validate_deck(&deck) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": e
+                    })
+                )
+            );
+        }
+        team.defaults.deck = deck;
+    }
+    if let Some(anonymous_votes) = team_data.anonymous_votes {
+        team.defaults.anonymous_votes = anonymous_votes;
+    }
+    if let Some(timer_secs) = team_data.timer_secs {
+        if timer_secs == 0 {
+            team.defaults.timer_secs = None;
+        } else if let Err(e) = validate_timer_secs(timer_secs) {
+            return Ok(
+                HttpResponse::BadRequest().json(
+                    serde_json::json!({
+                        "message": e
+                    })
+                )
+            );
+        } else {
+            team.defaults.timer_secs = Some(timer_secs);
+        }
+    }
+
+    team.updated_at = now_secs();
+    db.collection::<Team>("teams")
+        .update_one(
+            doc! { "_id": team.id },
+            doc! {
+                "$set": {
+                    "name": &team.name,
+                    "defaults": mongodb::bson::to_bson(&team.defaults).map_err(ErrorInternalServerError)?,
+                    "updated_at": team.updated_at
+                }
+            },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+
+    let response = team_response(&db, team).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Teamets rum bevares men er derefter almindelige rum med deres nuværende deltagere
+#[delete("/teams/{team_id}")]
+pub async fn delete_team(
+    user: AuthUser,
+    db: web::Data<Database>,
+    team_id: web::Path<String>
+) -> Result<HttpResponse> {
+    let team = match load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+        Some(team) => team,
+        None => {
+            return Ok(team_not_found());
+        }
+    };
+    match team.role_of(&user.id) {
+        Some(TeamRole::Admin) => {}
+        Some(TeamRole::Member) => {
+            return Ok(team_admin_required());
+        }
+        None => {
+            return Ok(team_not_found());
+        }
+    }
+
+    db.collection::<Team>("teams")
+        .delete_one(doc! { "_id": team.id }, None).await
+        .map_err(ErrorInternalServerError)?;
+    db.collection::<GameRoom>("game_rooms")
+        .update_many(doc! { "team_id": team_id.as_str() }, doc! { "$set": { "team_id": null } }, None).await
+        .map_err(ErrorInternalServerError)?;
+    println!("Team {} slettet af {}", team_id, user.id);
+
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "message": "Teamet er slettet"
+            })
+        )
+    )
+}
+
+// Svarer altid ens, så endpointet ikke afslører hvilke emails der har en konto.
+// Personen får en mail hvis kontoen findes, og bliver først medlem når de accepterer.
+#[post("/teams/{team_id}/invitations")]
+pub async fn invite_team_member(
+    user: AuthUser,
+    db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+    team_id: web::Path<String>,
+    invite_data: web::Json<InviteTeamMemberDto>
+) -> Result<HttpResponse> {
+    let team = match load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+        Some(team) => team,
+        None => {
+            return Ok(team_not_found());
+        }
+    };
+    match team.role_of(&user.id) {
+        Some(TeamRole::Admin) => {}
+        Some(TeamRole::Member) => {
+            return Ok(team_admin_required());
+        }
+        None => {
+            return Ok(team_not_found());
+        }
+    }
+
+    let email = invite_data.email.trim().to_string();
+    if email.is_empty() || !email.contains('@') {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Ugyldig email"
+                })
+            )
+        );
+    }
+    let pending = team.invitations
+        .iter()
+        .filter(|invitation| invitation.email != email)
+        .count();
+    if team.members.len() + pending >= MAX_TEAM_MEMBERS || pending >= MAX_PENDING_INVITATIONS {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("Et team kan højst have {} medlemmer", MAX_TEAM_MEMBERS)
+                })
+            )
+        );
+    }
+
+    // En ny invitation til samme email erstatter den forrige
+    let now = now_secs();
+    let invitation = TeamInvitation {
+        id: ObjectId::new().to_string(),
+        email: email.clone(),
+        role: invite_data.role,
+        invited_by: user.id.clone(),
+        created_at: now,
+        expires_at: now + TEAM_INVITATION_TTL_SECS,
+    };
+    let teams_collection = db.collection::<Team>("teams");
+    teams_collection
+        .update_one(doc! { "_id": team.id }, doc! { "$pull": { "invitations": { "email": &email } } }, None).await
+        .map_err(ErrorInternalServerError)?;
+    teams_collection
+        .update_one(
+            doc! { "_id": team.id },
+            doc! {
+                "$push": { "invitations": mongodb::bson::to_bson(&invitation).map_err(ErrorInternalServerError)? },
+                "$set": { "updated_at": now }
+            },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+
+    let invitee = db
+        .collection::<User>("users")
+        .find_one(doc! { "email": &email }, None).await
+        .map_err(ErrorInternalServerError)?;
+    let already_member = invitee
+        .as_ref()
+        .and_then(|invitee| invitee.id)
+        .is_some_and(|id| team.role_of(&id.to_string()).is_some());
+    if let Some(invitee) = invitee.filter(|_| !already_member) {
+        let mail = Mail {
+            to: invitee.email,
+            subject: format!("Invitation til teamet {}", team.name),
+            body: format!(
+                "Hej {}\n\nDu er inviteret til teamet {} på Planning Poker. Log ind for at acceptere eller afvise invitationen. Den udløber om 7 dage.\n\n{}/teams\n\nHvis du ikke kender teamet, kan du se bort fra denne mail.",
+                invitee.username,
+                team.name,
+                app_base_url()
+            ),
+        };
+        if let Err(e) = mailer.send(mail).await {
+            println!("Kunne ikke sende team-invitation: {}", e);
+        }
+    }
+    println!("Invitation {} til team {} oprettet af {}", invitation.id, team_id, user.id);
+
+    Ok(
+        HttpResponse::Accepted().json(
+            serde_json::json!({
+                "id": invitation.id,
+                "message": "Hvis emailen har en konto, har vi sendt en invitation"
+            })
+        )
+    )
+}
+
+// Afventende invitationer til den indloggede brugers bekræftede email
+#[get("/users/me/team-invitations")]
+pub async fn list_my_team_invitations(user: AuthUser, db: web::Data<Database>) -> Result<HttpResponse> {
+    let Some(email) = verified_email(&db, &user.id).await? else {
+        return Ok(HttpResponse::Ok().json(Vec::<TeamInvitationResponse>::new()));
+    };
+
+    let now = now_secs();
+    let mut cursor = db
+        .collection::<Team>("teams")
+        .find(
+            doc! { "invitations": { "$elemMatch": { "email": &email, "expires_at": { "$gt": now } } } },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+    let users_collection = db.collection::<User>("users");
+    let mut invitations = Vec::new();
+    while let Some(team) = cursor.try_next().await.map_err(ErrorInternalServerError)? {
+        let team_id = team.id.map(|id| id.to_string()).unwrap_or_default();
+        for invitation in team.invitations.into_iter().filter(|i| i.email == email && i.expires_at > now) {
+            let invited_by = match ObjectId::parse_str(&invitation.invited_by) {
+                Ok(object_id) =>
+                    users_collection
+                        .find_one(doc! { "_id": object_id }, None).await
+                        .map_err(ErrorInternalServerError)?
+                        .map(|inviter| inviter.username)
+                        .unwrap_or_default(),
+                Err(_) => String::new(),
+            };
+            invitations.push(TeamInvitationResponse {
+                id: invitation.id,
+                team_id: team_id.clone(),
+                team_name: team.name.clone(),
+                role: invitation.role,
+                invited_by,
+                expires_at: invitation.expires_at,
+            });
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+#[post("/teams/{team_id}/invitations/{invitation_id}/accept")]
+pub async fn accept_team_invitation(
+    user: AuthUser,
+    db: web::Data<Database>,
+    path: web::Path<(String, String)>
+) -> Result<HttpResponse> {
+    let (team_id, invitation_id) = path.into_inner();
+    let invitation_not_found = || {
+        HttpResponse::NotFound().json(
+            serde_json::json!({
+                "message": "Invitationen findes ikke eller er udløbet"
+            })
+        )
+    };
+
+    let Some(email) = verified_email(&db, &user.id).await? else {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Bekræft din email før du accepterer invitationer"
+                })
+            )
+        );
+    };
+    let Some(team) = load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? else {
+        return Ok(invitation_not_found());
+    };
+    let now = now_secs();
+    let Some(invitation) = team.invitations
+        .iter()
+        .find(|i| i.id == invitation_id && i.email == email && i.expires_at > now)
+        .cloned() else {
+        return Ok(invitation_not_found());
+    };
+
+    let member = TeamMember {
+        user_id: user.id.clone(),
+        role: invitation.role,
+        joined_at: now,
+    };
+    let teams_collection = db.collection::<Team>("teams");
+    let result = teams_collection
+        .update_one(
+            doc! {
+                "_id": team.id,
+                "invitations.id": &invitation.id,
+                "members.user_id": { "$ne": &user.id },
+                format!("members.{}", MAX_TEAM_MEMBERS - 1): { "$exists": false }
+            },
+            doc! {
+                "$push": { "members": mongodb::bson::to_bson(&member).map_err(ErrorInternalServerError)? },
+                "$pull": { "invitations": { "id": &invitation.id } },
+                "$set": { "updated_at": now }
+            },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        // Allerede medlem eller teamet er fyldt; invitationen kan ikke bruges
+        teams_collection
+            .update_one(doc! { "_id": team.id }, doc! { "$pull": { "invitations": { "id": &invitation.id } } }, None).await
+            .map_err(ErrorInternalServerError)?;
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Du er allerede medlem, eller teamet er fyldt"
+                })
+            )
+        );
+    }
+    add_to_team_rooms(&db, &team_id, &user.id).await.map_err(ErrorInternalServerError)?;
+    println!("{} har accepteret invitationen til team {}", user.id, team_id);
+
+    let team = load_team(&db, &team_id).await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?
+        .ok_or_else(|| ErrorInternalServerError("Team ikke fundet"))?;
+    let response = team_response(&db, team).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Den inviterede kan afvise, og teamets administratorer kan trække invitationen tilbage
+#[delete("/teams/{team_id}/invitations/{invitation_id}")]
+pub async fn delete_team_invitation(
+    user: AuthUser,
+    db: web::Data<Database>,
+    path: web::Path<(String, String)>
+) -> Result<HttpResponse> {
+    let (team_id, invitation_id) = path.into_inner();
+    let Some(team) = load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? else {
+        return Ok(team_not_found());
+    };
+    let email = verified_email(&db, &user.id).await?;
+    let allowed = team.invitations
+        .iter()
+        .find(|i| i.id == invitation_id)
+        .is_some_and(|i| team.role_of(&user.id) == Some(TeamRole::Admin) || email.as_deref() == Some(i.email.as_str()));
+    if !allowed {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Invitationen findes ikke"
+                })
+            )
+        );
+    }
+
+    db.collection::<Team>("teams")
+        .update_one(
+            doc! { "_id": team.id },
+            doc! { "$pull": { "invitations": { "id": &invitation_id } }, "$set": { "updated_at": now_secs() } },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "message": "Invitationen er fjernet"
+            })
+        )
+    )
+}
+
+#[put("/teams/{team_id}/members/{user_id}")]
+pub async fn update_team_member(
+    user: AuthUser,
+    db: web::Data<Database>,
+    path: web::Path<(String, String)>,
+    member_data: web::Json<UpdateTeamMemberDto>
+) -> Result<HttpResponse> {
+    let (team_id, member_id) = path.into_inner();
+    let mut team = match load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+        Some(team) => team,
+        None => {
+            return Ok(team_not_found());
+        }
+    };
+    match team.role_of(&user.id) {
+        Some(TeamRole::Admin) => {}
+        Some(TeamRole::Member) => {
+            return Ok(team_admin_required());
+        }
+        None => {
+            return Ok(team_not_found());
+        }
+    }
+
+    let Some(current_role) = team.role_of(&member_id) else {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Medlem ikke fundet"
+                })
+            )
+        );
+    };
+    if current_role == TeamRole::Admin && member_data.role != TeamRole::Admin && team.admin_count() == 1 {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Teamet skal have mindst én administrator"
+                })
+            )
+        );
+    }
+
+    let mut filter = doc! { "_id": team.id, "members.user_id": &member_id };
+    if member_data.role != TeamRole::Admin {
+        filter.extend(other_admin_remains(&member_id));
+    }
+    let result = db
+        .collection::<Team>("teams")
+        .update_one(
+            filter,
+            doc! {
+                "$set": {
+                    "members.$.role": mongodb::bson::to_bson(&member_data.role).map_err(ErrorInternalServerError)?,
+                    "updated_at": now_secs()
+                }
+            },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(team_changed());
+    }
+    if let Some(member) = team.members.iter_mut().find(|m| m.user_id == member_id) {
+        member.role = member_data.role;
+    }
+
+    let response = team_response(&db, team).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Administratorer kan fjerne alle medlemmer, og alle kan forlade teamet selv
+#[delete("/teams/{team_id}/members/{user_id}")]
+pub async fn remove_team_member(
+    user: AuthUser,
+    db: web::Data<Database>,
+    path: web::Path<(String, String)>
+) -> Result<HttpResponse> {
+    let (team_id, member_id) = path.into_inner();
+    let team = match load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+        Some(team) => team,
+        None => {
+            return Ok(team_not_found());
+        }
+    };
+    match team.role_of(&user.id) {
+        Some(TeamRole::Admin) => {}
+        Some(TeamRole::Member) if member_id == user.id => {}
+        Some(TeamRole::Member) => {
+            return Ok(team_admin_required());
+        }
+        None => {
+            return Ok(team_not_found());
+        }
+    }
+
+    let Some(role) = team.role_of(&member_id) else {
+        return Ok(
+            HttpResponse::NotFound().json(
+                serde_json::json!({
+                    "message": "Medlem ikke fundet"
+                })
+            )
+        );
+    };
+    if role == TeamRole::Admin && team.admin_count() == 1 {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Teamet skal have mindst én administrator. Udnævn en anden eller slet teamet."
+                })
+            )
+        );
+    }
+
+    let mut filter = doc! { "_id": team.id, "members.user_id": &member_id };
+    filter.extend(other_admin_remains(&member_id));
+    let result = db
+        .collection::<Team>("teams")
+        .update_one(
+            filter,
+            doc! {
+                "$pull": { "members": { "user_id": &member_id } },
+                "$set": { "updated_at": now_secs() }
+            },
+            None
+        ).await
+        .map_err(ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(team_changed());
+    }
+    remove_from_team_rooms(&db, &team_id, &member_id).await.map_err(ErrorInternalServerError)?;
+    println!("{} fjernet fra team {}", member_id, team_id);
+
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "message": "Medlemmet er fjernet fra teamet"
+            })
+        )
+    )
+}
+
+#[get("/teams/{team_id}/rooms")]
+pub async fn list_team_rooms(
+    user: AuthUser,
+    db: web::Data<Database>,
+    team_id: web::Path<String>
+) -> Result<HttpResponse> {
+    match load_team(&db, &team_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))? {
+        Some(team) if team.role_of(&user.id).is_some() => {}
+        _ => {
+            return Ok(team_not_found());
+        }
+    }
+
+    let options = FindOptions::builder()
+        .sort(doc! { "updated_at": -1 })
+        .build();
+    let mut cursor = db
+        .collection::<GameRoom>("game_rooms")
+        .find(doc! { "team_id": team_id.as_str() }, options).await
+        .map_err(ErrorInternalServerError)?;
+
+    let mut rooms = Vec::new();
+    while let Some(room) = cursor.try_next().await.map_err(ErrorInternalServerError)? {
+        rooms.push(TeamRoomSummary {
+            id: room.id.map(|id| id.to_string()).unwrap_or_default(),
+            name: room.name,
+            invite_code: room.invite_code,
+            admin_id: room.admin_id,
+            mode: room.settings.mode,
+            updated_at: room.updated_at,
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(rooms))
+}
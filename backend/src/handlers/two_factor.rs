@@ -0,0 +1,404 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Result, error::ErrorInternalServerError};
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::Database;
+use rand::RngCore;
+use totp_rs::{Algorithm, Secret, TOTP};
+use crate::handlers::auth::{complete_login, generate_token, hash_token, now_secs};
+use crate::handlers::user::{reauthenticate, Reauthentication};
+use crate::mailer::Mailer;
+use crate::middleware::auth::AuthUser;
+use crate::models::user::{
+    User, TwoFactor, LoginChallenge, TwoFactorChallengeResponse, TwoFactorSetupResponse, RecoveryCodesResponse,
+    TwoFactorCodeDto, TwoFactorLoginDto, DisableTwoFactorDto, TokenPurpose,
+};
+use crate::rate_limit::{client_ip, RateLimiter};
+
+// Tid brugeren har til at indtaste koden efter adgangskoden
+const LOGIN_CHALLENGE_TTL_SECS: i64 = 300;
+// Forkerte koder før udfordringen kasseres og brugeren må starte forfra
+const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+const RECOVERY_CODE_COUNT: usize = 10;
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP_SECS: i64 = 30;
+
+// Starter en ny to-faktor opsætning. Hemmeligheden gælder først når `enable` har bekræftet en kode.
+#[post("/auth/2fa/setup")]
+pub async fn setup_two_factor(
+    user: AuthUser,
+    db: web::Data<Database>,
+) -> Result<HttpResponse> {
+    let account = load_user(&db, &user.id).await?;
+    if account.two_factor.as_ref().is_some_and(|two_factor| two_factor.enabled) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "message": "To-faktor login er allerede slået til"
+        })));
+    }
+
+    let Secret::Encoded(secret) = Secret::generate_secret().to_encoded() else {
+        return Err(ErrorInternalServerError("Kunne ikke oprette en to-faktor hemmelighed"));
+    };
+    let totp = totp_for(&secret, &account.email).map_err(ErrorInternalServerError)?;
+
+    let two_factor = TwoFactor {
+        secret: secret.clone(),
+        enabled: false,
+        recovery_code_hashes: Vec::new(),
+        last_used_step: None,
+    };
+    db.collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id },
+            doc! { "$set": { "two_factor": mongodb::bson::to_bson(&two_factor).map_err(ErrorInternalServerError)? } },
+            None,
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(TwoFactorSetupResponse {
+        secret,
+        otpauth_uri: totp.get_url(),
+    }))
+}
+
+// Bekræfter opsætningen med en kode fra appen og returnerer gendannelseskoderne én gang
+#[post("/auth/2fa/enable")]
+pub async fn enable_two_factor(
+    user: AuthUser,
+    db: web::Data<Database>,
+    code_data: web::Json<TwoFactorCodeDto>,
+) -> Result<HttpResponse> {
+    let account = load_user(&db, &user.id).await?;
+    let Some(two_factor) = account.two_factor.filter(|two_factor| !two_factor.enabled) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "message": "Start opsætningen af to-faktor login først"
+        })));
+    };
+
+    let Some(step) = matching_step(&two_factor, &account.email, &code_data.code) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "message": "Ugyldig kode"
+        })));
+    };
+
+    let recovery_codes = generate_recovery_codes();
+    let recovery_code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_token(&normalize_recovery_code(code)))
+        .collect();
+
+    db.collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id, "two_factor.secret": &two_factor.secret },
+            doc! { "$set": {
+                "two_factor.enabled": true,
+                "two_factor.recovery_code_hashes": recovery_code_hashes,
+                "two_factor.last_used_step": step
+            } },
+            None,
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+#[post("/auth/2fa/disable")]
+pub async fn disable_two_factor(
+    user: AuthUser,
+    db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+    disable_data: web::Json<DisableTwoFactorDto>,
+) -> Result<HttpResponse> {
+    let account = load_user(&db, &user.id).await?;
+    let reauthentication = reauthenticate(
+        &db,
+        mailer.get_ref(),
+        &account,
+        disable_data.password.as_deref(),
+        disable_data.confirmation_token.as_deref(),
+        TokenPurpose::ConfirmTwoFactorDisable,
+        None,
+    )
+    .await?;
+    if let Reauthentication::Pending(response) = reauthentication {
+        return Ok(response);
+    }
+
+    db.collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id },
+            doc! { "$unset": { "two_factor": "" } },
+            None,
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "To-faktor login er slået fra"
+    })))
+}
+
+// Andet trin af login: en kode fra appen eller en gendannelseskode
+#[post("/auth/login/2fa")]
+pub async fn verify_two_factor_login(
+    req: HttpRequest,
+    db: web::Data<Database>,
+    limiter: web::Data<RateLimiter>,
+    login_data: web::Json<TwoFactorLoginDto>,
+) -> Result<HttpResponse> {
+    let challenges = db.collection::<LoginChallenge>("login_challenges");
+    let token_hash = hash_token(&login_data.challenge_token);
+
+    let challenge = challenges
+        .find_one(doc! { "token_hash": &token_hash, "expires_at": { "$gt": now_secs() } }, None)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let Some(challenge) = challenge else {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "message": "Login er udløbet. Log ind igen."
+        })));
+    };
+
+    let account = load_user(&db, &challenge.user_id).await?;
+    let ip = client_ip(&req);
+    limiter.check_login(&ip, &account.email).await?;
+
+    let Some(two_factor) = account.two_factor.as_ref().filter(|two_factor| two_factor.enabled) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "message": "To-faktor login er ikke slået til"
+        })));
+    };
+
+    let accepted = match (&login_data.code, &login_data.recovery_code) {
+        (Some(code), _) => match matching_step(two_factor, &account.email, code) {
+            Some(step) => use_step(&db, &account, step).await.map_err(ErrorInternalServerError)?,
+            None => false,
+        },
+        (None, Some(recovery_code)) => use_recovery_code(&db, &account, recovery_code)
+            .await
+            .map_err(ErrorInternalServerError)?,
+        (None, None) => false,
+    };
+
+    if !accepted {
+        limiter.login_failed(&ip, &account.email).await;
+        let attempts = challenges
+            .find_one_and_update(
+                doc! { "_id": challenge.id },
+                doc! { "$inc": { "attempts": 1 } },
+                mongodb::options::FindOneAndUpdateOptions::builder()
+                    .return_document(mongodb::options::ReturnDocument::After)
+                    .build(),
+            )
+            .await
+            .map_err(ErrorInternalServerError)?
+            .map(|challenge| challenge.attempts)
+            .unwrap_or(MAX_CHALLENGE_ATTEMPTS);
+        if attempts >= MAX_CHALLENGE_ATTEMPTS {
+            challenges
+                .delete_one(doc! { "_id": challenge.id }, None)
+                .await
+                .map_err(ErrorInternalServerError)?;
+        }
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "message": "Ugyldig kode"
+        })));
+    }
+
+    // Udfordringen kan kun indløses én gang
+    let consumed = challenges
+        .find_one_and_delete(doc! { "_id": challenge.id }, None)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    if consumed.is_none() {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "message": "Login er udløbet. Log ind igen."
+        })));
+    }
+
+    limiter.login_succeeded(&account.email).await;
+    complete_login(&db, &req, account, challenge.guest_id).await
+}
+
+pub(crate) async fn start_login_challenge(
+    db: &Database,
+    user_id: &str,
+    guest_id: Option<String>,
+) -> mongodb::error::Result<TwoFactorChallengeResponse> {
+    let now = now_secs();
+    let challenge_token = generate_token();
+    db.collection::<LoginChallenge>("login_challenges")
+        .insert_one(
+            LoginChallenge {
+                id: None,
+                token_hash: hash_token(&challenge_token),
+                user_id: user_id.to_string(),
+                guest_id,
+                attempts: 0,
+                created_at: now,
+                expires_at: now + LOGIN_CHALLENGE_TTL_SECS,
+            },
+            None,
+        )
+        .await?;
+
+    Ok(TwoFactorChallengeResponse {
+        two_factor_required: true,
+        challenge_token,
+        expires_in: LOGIN_CHALLENGE_TTL_SECS,
+    })
+}
+
+async fn load_user(db: &Database, user_id: &str) -> Result<User> {
+    let object_id = ObjectId::parse_str(user_id)
+        .map_err(|_| ErrorInternalServerError("Ugyldigt bruger ID"))?;
+    db.collection::<User>("users")
+        .find_one(doc! { "_id": object_id }, None)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorInternalServerError("Bruger ikke fundet"))
+}
+
+fn totp_for(secret: &str, email: &str) -> std::result::Result<TOTP, Box<dyn std::error::Error>> {
+    let issuer = std::env::var("TOTP_ISSUER").unwrap_or_else(|_| "Estimer".to_string());
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().map_err(|e| format!("{:?}", e))?;
+    Ok(TOTP::new(Algorithm::SHA1, TOTP_DIGITS, 1, TOTP_STEP_SECS as u64, secret, Some(issuer), email.to_string())?)
+}
+
+// Tidsskridtet koden hører til. Et skridt før og efter accepteres pga. urforskydning.
+fn matching_step(two_factor: &TwoFactor, email: &str, code: &str) -> Option<i64> {
+    matching_step_at(two_factor, email, code, now_secs() / TOTP_STEP_SECS)
+}
+
+fn matching_step_at(two_factor: &TwoFactor, email: &str, code: &str, current: i64) -> Option<i64> {
+    let totp = totp_for(&two_factor.secret, email).ok()?;
+    let code = code.trim();
+    (current - 1..=current + 1)
+        .filter(|step| two_factor.last_used_step.is_none_or(|last| *step > last))
+        .find(|step| totp.generate((step * TOTP_STEP_SECS) as u64) == code)
+}
+
+// Gemmer skridtet atomisk, så to samtidige logins ikke kan bruge den samme kode
+async fn use_step(db: &Database, account: &User, step: i64) -> mongodb::error::Result<bool> {
+    let result = db.collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id, "two_factor.last_used_step": { "$not": { "$gte": step } } },
+            doc! { "$set": { "two_factor.last_used_step": step } },
+            None,
+        )
+        .await?;
+    Ok(result.modified_count == 1)
+}
+
+async fn use_recovery_code(db: &Database, account: &User, recovery_code: &str) -> mongodb::error::Result<bool> {
+    let code_hash = hash_token(&normalize_recovery_code(recovery_code));
+    let result = db.collection::<User>("users")
+        .update_one(
+            doc! { "_id": account.id, "two_factor.recovery_code_hashes": &code_hash },
+            doc! { "$pull": { "two_factor.recovery_code_hashes": &code_hash } },
+            None,
+        )
+        .await?;
+    Ok(result.modified_count == 1)
+}
+
+// Koder på formen `a1b2c-3d4e5`
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{}-{}", &hex[..5], &hex[5..])
+        })
+        .collect()
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL: &str = "a@example.com";
+    const CURRENT: i64 = 56_000_000;
+
+    fn two_factor(last_used_step: Option<i64>) -> TwoFactor {
+        let Secret::Encoded(secret) = Secret::generate_secret().to_encoded() else {
+            panic!("hemmeligheden skal kunne kodes");
+        };
+        TwoFactor { secret, enabled: true, recovery_code_hashes: Vec::new(), last_used_step }
+    }
+
+    fn code_at(two_factor: &TwoFactor, step: i64) -> String {
+        totp_for(&two_factor.secret, EMAIL).unwrap().generate((step * TOTP_STEP_SECS) as u64)
+    }
+
+    #[test]
+    fn accepts_one_step_of_clock_drift() {
+        let two_factor = two_factor(None);
+        for step in [CURRENT - 1, CURRENT, CURRENT + 1] {
+            let code = code_at(&two_factor, step);
+            assert_eq!(matching_step_at(&two_factor, EMAIL, &code, CURRENT), Some(step));
+        }
+    }
+
+    #[test]
+    fn rejects_codes_outside_the_window() {
+        let two_factor = two_factor(None);
+        for step in [CURRENT - 2, CURRENT + 2] {
+            let code = code_at(&two_factor, step);
+            // En kode kan tilfældigvis være den samme i et andet skridt
+            if (CURRENT - 1..=CURRENT + 1).all(|s| code_at(&two_factor, s) != code) {
+                assert_eq!(matching_step_at(&two_factor, EMAIL, &code, CURRENT), None);
+            }
+        }
+        assert_eq!(matching_step_at(&two_factor, EMAIL, "not-a-code", CURRENT), None);
+    }
+
+    #[test]
+    fn rejects_replay_of_used_step() {
+        let two_factor = two_factor(Some(CURRENT));
+        let used = code_at(&two_factor, CURRENT);
+        let earlier = code_at(&two_factor, CURRENT - 1);
+        let next = code_at(&two_factor, CURRENT + 1);
+        if used != next && earlier != next {
+            assert_eq!(matching_step_at(&two_factor, EMAIL, &used, CURRENT), None);
+            assert_eq!(matching_step_at(&two_factor, EMAIL, &earlier, CURRENT), None);
+        }
+        assert_eq!(matching_step_at(&two_factor, EMAIL, &next, CURRENT), Some(CURRENT + 1));
+    }
+
+    #[test]
+    fn trims_whitespace_around_code() {
+        let two_factor = two_factor(None);
+        let code = format!(" {} \n", code_at(&two_factor, CURRENT));
+        assert_eq!(matching_step_at(&two_factor, EMAIL, &code, CURRENT), Some(CURRENT));
+    }
+
+    #[test]
+    fn recovery_codes_normalize_case_and_separators() {
+        assert_eq!(normalize_recovery_code(" A1B2C-3D4E5 "), "a1b2c3d4e5");
+        assert_eq!(normalize_recovery_code("a1b2c 3d4e5"), normalize_recovery_code("A1B2C-3D4E5"));
+    }
+
+    #[test]
+    fn generated_recovery_codes_are_unique_and_formatted() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), 11);
+            assert_eq!(&code[5..6], "-");
+            assert_eq!(normalize_recovery_code(code).len(), 10);
+        }
+        let mut unique = codes.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), codes.len());
+    }
+}
@@ -1,43 +1,71 @@
-use actix_web::{ post, web, HttpResponse, Result, error::ResponseError, Error as ActixError };
+use actix::Addr;
+use actix_web::{
+    post,
+    put,
+    delete,
+    web,
+    HttpResponse,
+    Result,
+    error::{ ResponseError, ErrorInternalServerError },
+    Error as ActixError,
+};
+use bcrypt::{ hash, verify, DEFAULT_COST };
 use actix_multipart::{ Multipart, MultipartError };
 use futures_util::TryStreamExt;
 use uuid::Uuid;
 use std::io::Write;
-use image::ImageFormat;
 use mongodb::Database;
-use crate::models::user::User;
-use crate::middleware::auth::validate_token;
+use crate::models::user::{
+    User,
+    GuestUser,
+    UserResponse,
+    TokenPurpose,
+    UpdateProfileDto,
+    ChangeEmailDto,
+    ChangePasswordDto,
+    DeleteAccountDto,
+};
+use crate::models::game_room::GameRoom;
+use crate::handlers::auth::{ consume_user_token, issue_user_token, revoke_other_sessions, EMAIL_VERIFICATION_TTL_SECS };
+use crate::handlers::team::remove_user_from_teams;
+use crate::mailer::{ app_base_url, Mail, Mailer };
+use crate::websocket::{ GameServer, ProfileChanged, WebSocketMessage };
+use crate::middleware::auth::{ AuthUser, AuthGuest, legacy_guest_urls_enabled };
 use mongodb::bson::doc;
 use std::fmt;
 use actix_web::error::BlockingError;
 
 #[derive(Debug)]
 pub enum UserError {
-    ImageError(image::ImageError),
-    MongoError(mongodb::error::Error),
-    BsonError(mongodb::bson::oid::Error),
-    IoError(std::io::Error),
-    ActixError(ActixError),
-    MultipartError(MultipartError),
-    BlockingError,
+    Image(image::ImageError),
+    Mongo(mongodb::error::Error),
+    Bson(mongodb::bson::oid::Error),
+    Io(std::io::Error),
+    Actix(ActixError),
+    Multipart(MultipartError),
+    Blocking,
 }
 
 impl fmt::Display for UserError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            UserError::ImageError(e) => write!(f, "Billedfejl: {}", e),
-            UserError::MongoError(e) => write!(f, "Database fejl: {}", e),
-            UserError::BsonError(e) => write!(f, "BSON fejl: {}", e),
-            UserError::IoError(e) => write!(f, "IO fejl: {}", e),
-            UserError::ActixError(e) => write!(f, "Actix fejl: {}", e),
-            UserError::MultipartError(e) => write!(f, "Multipart fejl: {}", e),
-            UserError::BlockingError => write!(f, "Blocking operation fejl"),
+            UserError::Image(e) => write!(f, "Billedfejl: {}", e),
+            UserError::Mongo(e) => write!(f, "Database fejl: {}", e),
+            UserError::Bson(e) => write!(f, "BSON fejl: {}", e),
+            UserError::Io(e) => write!(f, "IO fejl: {}", e),
+            UserError::Actix(e) => write!(f, "Actix fejl: {}", e),
+            UserError::Multipart(e) => write!(f, "Multipart fejl: {}", e),
+            UserError::Blocking => write!(f, "Blocking operation fejl"),
         }
     }
 }
 
 impl ResponseError for UserError {
     fn error_response(&self) -> HttpResponse {
+        // Fx 401 fra token valideringen skal ikke blive til en 500
+        if let UserError::Actix(e) = self {
+            return e.error_response();
+        }
         HttpResponse::InternalServerError().json(
             serde_json::json!({
             "error": self.to_string()
@@ -48,60 +76,60 @@ impl ResponseError for UserError {
 
 impl From<image::ImageError> for UserError {
     fn from(err: image::ImageError) -> Self {
-        UserError::ImageError(err)
+        UserError::Image(err)
     }
 }
 
 impl From<mongodb::error::Error> for UserError {
     fn from(err: mongodb::error::Error) -> Self {
-        UserError::MongoError(err)
+        UserError::Mongo(err)
     }
 }
 
 impl From<mongodb::bson::oid::Error> for UserError {
     fn from(err: mongodb::bson::oid::Error) -> Self {
-        UserError::BsonError(err)
+        UserError::Bson(err)
     }
 }
 
 impl From<std::io::Error> for UserError {
     fn from(err: std::io::Error) -> Self {
-        UserError::IoError(err)
+        UserError::Io(err)
     }
 }
 
 impl From<ActixError> for UserError {
     fn from(err: ActixError) -> Self {
-        UserError::ActixError(err)
+        UserError::Actix(err)
     }
 }
 
 impl From<MultipartError> for UserError {
     fn from(err: MultipartError) -> Self {
-        UserError::MultipartError(err)
+        UserError::Multipart(err)
     }
 }
 
 impl From<BlockingError> for UserError {
     fn from(_: BlockingError) -> Self {
-        UserError::BlockingError
+        UserError::Blocking
     }
 }
 
 #[post("/users/profile-image")]
 pub async fn upload_profile_image(
-    req: actix_web::HttpRequest,
+    user: AuthUser,
     mut payload: Multipart,
     db: web::Data<Database>
 ) -> Result<HttpResponse, UserError> {
     // Valider bruger
-    let user_id = validate_token(req).await?;
+    let user_id = user.id;
 
     // Opret uploads mappe hvis den ikke findes
     std::fs::create_dir_all("uploads")?;
 
     // Håndter fil upload
-    while let Some(mut field) = payload.try_next().await? {
+    if let Some(mut field) = payload.try_next().await? {
         // Generer unikt filnavn
         let file_id = Uuid::new_v4();
         let file_path = format!("uploads/{}.jpg", file_id);
@@ -110,16 +138,16 @@ pub async fn upload_profile_image(
         let file_path_clone = file_path.clone();
         let mut f = web
             ::block(move || std::fs::File::create(&file_path_clone)).await
-            .map_err(|e: BlockingError| UserError::BlockingError)?
-            .map_err(UserError::IoError)?;
+            .map_err(|_: BlockingError| UserError::Blocking)?
+            .map_err(UserError::Io)?;
 
         // Skriv data til fil
         while let Some(chunk) = field.try_next().await? {
             let chunk_data = chunk.to_vec();
             f = web
                 ::block(move || f.write_all(&chunk_data).map(|_| f)).await
-                .map_err(|e: BlockingError| UserError::BlockingError)?
-                .map_err(UserError::IoError)?;
+                .map_err(|_: BlockingError| UserError::Blocking)?
+                .map_err(UserError::Io)?;
         }
 
         // Optimer billede
@@ -156,19 +184,422 @@ pub async fn upload_profile_image(
     )
 }
 
-#[post("/guest/profile-image/{guest_id}")]
+const MAX_USERNAME_LENGTH: usize = 50;
+// Navnet slettede brugere står med i historikken
+const DELETED_USERNAME: &str = "Slettet bruger";
+
+#[put("/users/me")]
+pub async fn update_profile(
+    user: AuthUser,
+    db: web::Data<Database>,
+    srv: web::Data<Addr<GameServer>>,
+    profile_data: web::Json<UpdateProfileDto>
+) -> Result<HttpResponse> {
+    let username = profile_data.username.trim();
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": format!("Brugernavnet skal være mellem 1 og {} tegn", MAX_USERNAME_LENGTH)
+                })
+            )
+        );
+    }
+
+    let account = load_account(&db, &user.id).await?;
+    db.collection::<User>("users")
+        .update_one(doc! { "_id": account.id }, doc! { "$set": { "username": username } }, None).await
+        .map_err(ErrorInternalServerError)?;
+
+    // Historikken viser det nye navn
+    rewrite_participant_history(&db, &user.id, &user.id, username, &account.profile_image).await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+
+    srv.do_send(ProfileChanged {
+        user_id: user.id.clone(),
+        username: username.to_string(),
+        profile_image: account.profile_image.clone(),
+    });
+
+    Ok(
+        HttpResponse::Ok().json(UserResponse {
+            id: user.id,
+            email: account.email,
+            username: username.to_string(),
+            profile_image: account.profile_image,
+            email_verified: account.email_verified,
+            two_factor_enabled: account.two_factor.is_some_and(|two_factor| two_factor.enabled),
+        })
+    )
+}
+
+// SSO-brugere kan ændre email, slette kontoen og slå to-faktor fra uden at kende en adgangskode
+const ACCOUNT_CONFIRMATION_TTL_SECS: i64 = 3600;
+
+pub(crate) enum Reauthentication {
+    Confirmed,
+    // Svaret der skal sendes, fx at en bekræftelsesmail er på vej
+    Pending(HttpResponse),
+}
+
+// Bekræfter at det er kontoens ejer der ændrer den. Med adgangskode som udgangspunkt.
+// Konti koblet til SSO har typisk en tilfældig adgangskode ingen kender, så de kan i stedet
+// bekræfte med en kode sendt til kontoens email. `subject` bindes til tokenet, fx den nye email.
+pub(crate) async fn reauthenticate(
+    db: &Database,
+    mailer: &dyn Mailer,
+    account: &User,
+    password: Option<&str>,
+    confirmation_token: Option<&str>,
+    purpose: TokenPurpose,
+    subject: Option<&str>
+) -> Result<Reauthentication> {
+    if let Some(password) = password {
+        if verify(password, &account.password_hash).map_err(ErrorInternalServerError)? {
+            return Ok(Reauthentication::Confirmed);
+        }
+    }
+    let wrong_password = || {
+        HttpResponse::Forbidden().json(
+            serde_json::json!({
+                "message": "Forkert adgangskode"
+            })
+        )
+    };
+    if account.identities.is_empty() || password.is_some() {
+        return Ok(Reauthentication::Pending(wrong_password()));
+    }
+    let user_id = account.id.map(|id| id.to_string()).unwrap_or_default();
+
+    if let Some(token) = confirmation_token {
+        let user_token = consume_user_token(db, token, purpose).await.map_err(ErrorInternalServerError)?;
+        let valid = user_token.is_some_and(|t| t.user_id == user_id && t.email.as_deref() == subject);
+        if valid {
+            return Ok(Reauthentication::Confirmed);
+        }
+        return Ok(
+            Reauthentication::Pending(
+                HttpResponse::Forbidden().json(
+                    serde_json::json!({
+                        "message": "Bekræftelsen er ugyldig eller udløbet"
+                    })
+                )
+            )
+        );
+    }
+
+    let action = match purpose {
+        TokenPurpose::ConfirmAccountDeletion => "slette din konto",
+        TokenPurpose::ConfirmTwoFactorDisable => "slå to-faktor login fra",
+        _ => "skifte email",
+    };
+    let token = issue_user_token(db, &user_id, purpose, ACCOUNT_CONFIRMATION_TTL_SECS, subject).await.map_err(
+        ErrorInternalServerError
+    )?;
+    let mail = Mail {
+        to: account.email.clone(),
+        subject: format!("Bekræft at du vil {}", action),
+        body: format!(
+            "Hej {}\n\nSend koden herunder sammen med din anmodning for at {}. Koden udløber om en time.\n\n{}\n\nHvis det ikke var dig, kan du se bort fra denne mail.",
+            account.username,
+            action,
+            token
+        ),
+    };
+    mailer.send(mail).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+
+    Ok(
+        Reauthentication::Pending(
+            HttpResponse::Accepted().json(
+                serde_json::json!({
+                    "message": "Vi har sendt en bekræftelseskode til din email. Send den som confirmation_token.",
+                    "confirmation_required": true
+                })
+            )
+        )
+    )
+}
+
+// Den nye email træder først i kraft når linket i bekræftelsesmailen er åbnet
+#[put("/users/me/email")]
+pub async fn change_email(
+    user: AuthUser,
+    db: web::Data<Database>,
+    mailer: web::Data<dyn Mailer>,
+    email_data: web::Json<ChangeEmailDto>
+) -> Result<HttpResponse> {
+    let account = load_account(&db, &user.id).await?;
+    let new_email = email_data.new_email.trim();
+    if !new_email.contains('@') || new_email == account.email {
+        return Ok(
+            HttpResponse::BadRequest().json(
+                serde_json::json!({
+                    "message": "Angiv en ny gyldig email"
+                })
+            )
+        );
+    }
+    let reauthentication = reauthenticate(
+        &db,
+        mailer.get_ref(),
+        &account,
+        email_data.password.as_deref(),
+        email_data.confirmation_token.as_deref(),
+        TokenPurpose::ConfirmEmailChange,
+        Some(new_email)
+    ).await?;
+    if let Reauthentication::Pending(response) = reauthentication {
+        return Ok(response);
+    }
+    let taken = db
+        .collection::<User>("users")
+        .find_one(doc! { "email": new_email }, None).await
+        .map_err(ErrorInternalServerError)?
+        .is_some();
+    if taken {
+        return Ok(
+            HttpResponse::Conflict().json(
+                serde_json::json!({
+                    "message": "Emailen bruges allerede af en anden konto"
+                })
+            )
+        );
+    }
+
+    let token = issue_user_token(
+        &db,
+        &user.id,
+        TokenPurpose::EmailVerification,
+        EMAIL_VERIFICATION_TTL_SECS,
+        Some(new_email)
+    ).await
+        .map_err(ErrorInternalServerError)?;
+    let mail = Mail {
+        to: new_email.to_string(),
+        subject: "Bekræft din nye email".to_string(),
+        body: format!(
+            "Hej {}\n\nÅbn linket herunder for at bekræfte din nye email. Linket udløber om 48 timer.\n\n{}/verify-email?token={}",
+            account.username,
+            app_base_url(),
+            token
+        ),
+    };
+    mailer.send(mail).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+
+    Ok(
+        HttpResponse::Accepted().json(
+            serde_json::json!({
+                "message": "Vi har sendt et link til den nye email. Emailen skiftes når linket er åbnet."
+            })
+        )
+    )
+}
+
+#[put("/users/me/password")]
+pub async fn change_password(
+    user: AuthUser,
+    db: web::Data<Database>,
+    password_data: web::Json<ChangePasswordDto>
+) -> Result<HttpResponse> {
+    let account = load_account(&db, &user.id).await?;
+    if !verify(&password_data.current_password, &account.password_hash).map_err(ErrorInternalServerError)? {
+        return Ok(
+            HttpResponse::Forbidden().json(
+                serde_json::json!({
+                    "message": "Forkert adgangskode"
+                })
+            )
+        );
+    }
+
+    let password_hash = hash(password_data.new_password.as_bytes(), DEFAULT_COST).map_err(
+        ErrorInternalServerError
+    )?;
+    db.collection::<User>("users")
+        .update_one(doc! { "_id": account.id }, doc! { "$set": { "password_hash": password_hash } }, None).await
+        .map_err(ErrorInternalServerError)?;
+
+    // Den nuværende session fortsætter; alle andre enheder skal logge ind igen
+    revoke_other_sessions(&db, &user.id, &user.session_id).await.map_err(ErrorInternalServerError)?;
+
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "message": "Din adgangskode er ændret"
+            })
+        )
+    )
+}
+
+// Sletter kontoen. Stemmer og beskeder bevares anonymt, så rummenes historik stadig giver mening.
+// Rum brugeren er admin for overdrages til en anden deltager eller lukkes hvis der ikke er andre.
+#[delete("/users/me")]
+pub async fn delete_account(
+    user: AuthUser,
+    db: web::Data<Database>,
+    srv: web::Data<Addr<GameServer>>,
+    mailer: web::Data<dyn Mailer>,
+    delete_data: web::Json<DeleteAccountDto>
+) -> Result<HttpResponse> {
+    let account = load_account(&db, &user.id).await?;
+    let reauthentication = reauthenticate(
+        &db,
+        mailer.get_ref(),
+        &account,
+        delete_data.password.as_deref(),
+        delete_data.confirmation_token.as_deref(),
+        TokenPurpose::ConfirmAccountDeletion,
+        None
+    ).await?;
+    if let Reauthentication::Pending(response) = reauthentication {
+        return Ok(response);
+    }
+    let user_id = user.id;
+
+    transfer_or_close_rooms(&db, &user_id, &srv).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+
+    // Hver slettet konto får sit eget anonyme id, så stemmer stadig kan skelnes fra hinanden
+    let anonymous_id = format!("deleted-{}", Uuid::new_v4());
+    rewrite_participant_history(&db, &user_id, &anonymous_id, DELETED_USERNAME, &None).await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+
+    db.collection::<GameRoom>("game_rooms")
+        .update_many(doc! { "participants": &user_id }, doc! { "$pull": { "participants": &user_id } }, None).await
+        .map_err(ErrorInternalServerError)?;
+    remove_user_from_teams(&db, &user_id).await.map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    for collection in ["sessions", "user_tokens", "login_challenges", "notifications", "api_tokens"] {
+        db.collection::<mongodb::bson::Document>(collection)
+            .delete_many(doc! { "user_id": &user_id }, None).await
+            .map_err(ErrorInternalServerError)?;
+    }
+    db.collection::<User>("users")
+        .delete_one(doc! { "_id": account.id }, None).await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(path) = account.profile_image.as_deref().and_then(|image| image.strip_prefix('/')) {
+        if path.starts_with("uploads/") {
+            if let Err(e) = std::fs::remove_file(path) {
+                println!("Kunne ikke slette profilbillede {}: {:?}", path, e);
+            }
+        }
+    }
+
+    println!("Bruger {} har slettet sin konto", user_id);
+    Ok(
+        HttpResponse::Ok().json(
+            serde_json::json!({
+                "message": "Din konto er slettet"
+            })
+        )
+    )
+}
+
+async fn transfer_or_close_rooms(
+    db: &Database,
+    user_id: &str,
+    srv: &Addr<GameServer>
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rooms_collection = db.collection::<GameRoom>("game_rooms");
+    let mut cursor = rooms_collection.find(doc! { "admin_id": user_id }, None).await?;
+    let mut rooms = Vec::new();
+    while let Some(room) = cursor.try_next().await? {
+        rooms.push(room);
+    }
+
+    for room in rooms {
+        let Some(room_object_id) = room.id else {
+            continue;
+        };
+        let room_id = room_object_id.to_string();
+
+        match room.participants.iter().find(|participant| participant.as_str() != user_id) {
+            Some(new_admin) => {
+                rooms_collection.update_one(
+                    doc! { "_id": room_object_id },
+                    doc! { "$set": { "admin_id": new_admin } },
+                    None
+                ).await?;
+                println!("Rum {} overdraget til {}", room_id, new_admin);
+                srv.do_send(WebSocketMessage {
+                    message_type: "admin_changed".to_string(),
+                    content: serde_json::json!({ "admin_id": new_admin }),
+                    room_id: room_id.clone(),
+                    user_id: "system".to_string(),
+                });
+            }
+            None => {
+                // Ingen andre har deltaget, så rummets data tilhører kun brugeren
+                rooms_collection.delete_one(doc! { "_id": room_object_id }, None).await?;
+                for collection in ["completed_stories", "room_messages"] {
+                    db.collection::<mongodb::bson::Document>(collection)
+                        .delete_many(doc! { "room_id": &room_id }, None).await?;
+                }
+                println!("Rum {} lukket", room_id);
+                srv.do_send(WebSocketMessage {
+                    message_type: "room_closed".to_string(),
+                    content: serde_json::json!({}),
+                    room_id,
+                    user_id: "system".to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn load_account(db: &Database, user_id: &str) -> Result<User> {
+    let object_id = mongodb::bson::oid::ObjectId
+        ::parse_str(user_id)
+        .map_err(|_| ErrorInternalServerError("Ugyldigt bruger ID"))?;
+    db.collection::<User>("users")
+        .find_one(doc! { "_id": object_id }, None).await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorInternalServerError("Bruger ikke fundet"))
+}
+
+// Gæsten identificeres ud fra sit gæstetoken i Authorization headeren
+#[post("/guest/profile-image")]
 pub async fn upload_guest_profile_image(
+    guest: AuthGuest,
+    payload: Multipart,
+    db: web::Data<Database>
+) -> Result<HttpResponse, UserError> {
+    save_guest_profile_image(guest.id, payload, db).await
+}
+
+// Den gamle URL uden token. Slås kun til med ALLOW_LEGACY_GUEST_URLS mens
+// ældre klienter udfases, og fjernes derefter.
+#[post("/guest/profile-image/{guest_id}")]
+pub async fn legacy_upload_guest_profile_image(
     path: web::Path<String>,
-    mut payload: Multipart,
+    payload: Multipart,
     db: web::Data<Database>
 ) -> Result<HttpResponse, UserError> {
+    if !legacy_guest_urls_enabled() {
+        return Ok(
+            HttpResponse::Gone().json(
+                serde_json::json!({
+            "message": "Denne URL er udfaset. Brug /guest/profile-image med gæstetoken"
+        })
+            )
+        );
+    }
     let guest_id = path.into_inner();
+    println!("ADVARSEL: Gæst {} bruger den udfasede profilbillede URL", guest_id);
+    save_guest_profile_image(guest_id, payload, db).await
+}
+
+async fn save_guest_profile_image(
+    guest_id: String,
+    mut payload: Multipart,
+    db: web::Data<Database>
+) -> Result<HttpResponse, UserError> {
 
     // Opret uploads mappe hvis den ikke findes
     std::fs::create_dir_all("uploads")?;
 
     // Håndter fil upload
-    while let Some(mut field) = payload.try_next().await? {
+    if let Some(mut field) = payload.try_next().await? {
         // Generer unikt filnavn
         let file_id = Uuid::new_v4();
         let file_path = format!("uploads/{}.jpg", file_id);
@@ -177,16 +608,16 @@ pub async fn upload_guest_profile_image(
         let file_path_clone = file_path.clone();
         let mut f = web
             ::block(move || std::fs::File::create(&file_path_clone)).await
-            .map_err(|e: BlockingError| UserError::BlockingError)?
-            .map_err(UserError::IoError)?;
+            .map_err(|_: BlockingError| UserError::Blocking)?
+            .map_err(UserError::Io)?;
 
         // Skriv data til fil
         while let Some(chunk) = field.try_next().await? {
             let chunk_data = chunk.to_vec();
             f = web
                 ::block(move || f.write_all(&chunk_data).map(|_| f)).await
-                .map_err(|e: BlockingError| UserError::BlockingError)?
-                .map_err(UserError::IoError)?;
+                .map_err(|_: BlockingError| UserError::Blocking)?
+                .map_err(UserError::Io)?;
         }
 
         // Optimer billede
@@ -222,3 +653,136 @@ pub async fn upload_guest_profile_image(
         )
     )
 }
+
+// Flytter en gæsts historik over på en registreret konto: rum, stemmer,
+// tillidsstemmer, dot votes, chatbeskeder, notifikationer og profilbillede.
+// Gæsten slettes bagefter. Returnerer kontoens profilbillede efter sammenlægningen.
+pub async fn merge_guest_into_user(
+    db: &Database,
+    guest_id: &str,
+    user_id: &str
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let guests_collection = db.collection::<GuestUser>("guest_users");
+    let users_collection = db.collection::<User>("users");
+    let rooms_collection = db.collection::<mongodb::bson::Document>("game_rooms");
+
+    let guest_object_id = mongodb::bson::oid::ObjectId::parse_str(guest_id)?;
+    let user_object_id = mongodb::bson::oid::ObjectId::parse_str(user_id)?;
+
+    let guest = guests_collection
+        .find_one(doc! { "_id": guest_object_id }, None).await?
+        .ok_or("Gæsten findes ikke")?;
+    let user = users_collection
+        .find_one(doc! { "_id": user_object_id }, None).await?
+        .ok_or("Bruger ikke fundet")?;
+
+    // Brugerens eget profilbillede vinder over gæstens
+    let profile_image = user.profile_image.or(guest.profile_image);
+    if let Some(profile_image) = &profile_image {
+        users_collection.update_one(
+            doc! { "_id": user_object_id },
+            doc! { "$set": { "profile_image": profile_image } },
+            None
+        ).await?;
+    }
+
+    // Medlemskaber og admin-rettigheder
+    rooms_collection.update_many(
+        doc! { "participants": guest_id },
+        doc! { "$addToSet": { "participants": user_id } },
+        None
+    ).await?;
+    rooms_collection.update_many(
+        doc! { "participants": guest_id },
+        doc! { "$pull": { "participants": guest_id } },
+        None
+    ).await?;
+    rooms_collection.update_many(
+        doc! { "admin_id": guest_id },
+        doc! { "$set": { "admin_id": user_id } },
+        None
+    ).await?;
+
+    rewrite_participant_history(db, guest_id, user_id, &user.username, &profile_image).await?;
+
+    db.collection::<mongodb::bson::Document>("notifications").update_many(
+        doc! { "user_id": guest_id },
+        doc! { "$set": { "user_id": user_id } },
+        None
+    ).await?;
+
+    guests_collection.delete_one(doc! { "_id": guest_object_id }, None).await?;
+    println!("Gæst {} er flettet ind i bruger {}", guest_id, user_id);
+    Ok(profile_image)
+}
+
+// Skriver en deltagers stemmer, tillidsstemmer, dot votes og chatbeskeder over på
+// `new_id` med det givne navn og billede. Bruges når en gæst flettes ind i en konto,
+// når en bruger skifter navn, og når en slettet konto anonymiseres.
+async fn rewrite_participant_history(
+    db: &Database,
+    old_id: &str,
+    new_id: &str,
+    username: &str,
+    profile_image: &Option<String>
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rooms_collection = db.collection::<mongodb::bson::Document>("game_rooms");
+    let completed_stories_collection = db.collection::<mongodb::bson::Document>("completed_stories");
+
+    // Stemmer i rummenes historier. Vote serialiseres i camelCase.
+    let vote_filter = vec![doc! { "vote.userId": old_id }];
+    for (array_path, filter_path) in [
+        ("current_story.votes", "current_story.votes"),
+        ("stories.$[].votes", "stories.votes"),
+        ("completed_stories.$[].votes", "completed_stories.votes"),
+    ] {
+        rooms_collection.update_many(
+            doc! { format!("{}.userId", filter_path): old_id },
+            doc! {
+                "$set": {
+                    format!("{}.$[vote].userId", array_path): new_id,
+                    format!("{}.$[vote].username", array_path): username,
+                    format!("{}.$[vote].profileImage", array_path): profile_image
+                }
+            },
+            mongodb::options::UpdateOptions::builder().array_filters(vote_filter.clone()).build()
+        ).await?;
+    }
+
+    // Dot voting fordelinger ligger under deltagerens id
+    if old_id != new_id {
+        rooms_collection.update_many(
+            doc! { format!("dot_votes.{}", old_id): { "$exists": true } },
+            doc! { "$rename": { format!("dot_votes.{}", old_id): format!("dot_votes.{}", new_id) } },
+            None
+        ).await?;
+    }
+
+    // Afsluttede historier og deres tillidsrunder
+    completed_stories_collection.update_many(
+        doc! { "votes.userId": old_id },
+        doc! {
+            "$set": {
+                "votes.$[vote].userId": new_id,
+                "votes.$[vote].username": username,
+                "votes.$[vote].profileImage": profile_image
+            }
+        },
+        mongodb::options::UpdateOptions::builder().array_filters(vote_filter).build()
+    ).await?;
+    completed_stories_collection.update_many(
+        doc! { "confidence.votes.user_id": old_id },
+        doc! { "$set": { "confidence.votes.$[vote].user_id": new_id } },
+        mongodb::options::UpdateOptions
+            ::builder()
+            .array_filters(vec![doc! { "vote.user_id": old_id }])
+            .build()
+    ).await?;
+
+    db.collection::<mongodb::bson::Document>("room_messages").update_many(
+        doc! { "user_id": old_id },
+        doc! { "$set": { "user_id": new_id, "username": username, "profile_image": profile_image } },
+        None
+    ).await?;
+    Ok(())
+}
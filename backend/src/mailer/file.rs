@@ -0,0 +1,43 @@
+use futures_util::future::BoxFuture;
+use std::path::PathBuf;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use super::{ Mail, MailError, Mailer };
+
+// Skriver hver mail som en tekstfil i en mappe i stedet for at sende den
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileMailer { dir: dir.into() }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, Result<(), MailError>> {
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.dir).await.map_err(|e| MailError(e.to_string()))?;
+
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+            let path = self.dir.join(format!("{}-{}.txt", timestamp, uuid::Uuid::new_v4()));
+            let content = format!("To: {}\nSubject: {}\n\n{}\n", mail.to, mail.subject, mail.body);
+
+            tokio::fs::write(&path, content).await.map_err(|e| MailError(e.to_string()))?;
+            println!("Mail til {} gemt i {:?}", mail.to, path);
+            Ok(())
+        })
+    }
+}
+
+// Udskriver mails til loggen. Kun til lokal udvikling, da links i mails er hemmelige.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, Result<(), MailError>> {
+        Box::pin(async move {
+            println!("=== MAIL til {} ===\nEmne: {}\n{}\n=== SLUT PÅ MAIL ===", mail.to, mail.subject, mail.body);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,50 @@
+use futures_util::future::BoxFuture;
+use std::fmt;
+use std::sync::Arc;
+
+mod file;
+mod smtp;
+
+pub use file::{ FileMailer, LogMailer };
+pub use smtp::SmtpMailer;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct MailError(pub String);
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mail fejl: {}", self.0)
+    }
+}
+
+impl std::error::Error for MailError {}
+
+// Afsender af systemets mails. Vælges i `from_env` ud fra MAIL_TRANSPORT.
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, Result<(), MailError>>;
+}
+
+// MAIL_TRANSPORT=smtp sender rigtige mails. `file` skriver dem til MAIL_DIR og
+// `log` (standard) udskriver dem, til lokal udvikling og tests.
+pub fn from_env() -> Arc<dyn Mailer> {
+    match std::env::var("MAIL_TRANSPORT").unwrap_or_default().as_str() {
+        "smtp" => Arc::new(SmtpMailer::from_env().expect("SMTP er ikke konfigureret korrekt")),
+        "file" => {
+            let dir = std::env::var("MAIL_DIR").unwrap_or_else(|_| "mail_outbox".to_string());
+            Arc::new(FileMailer::new(dir))
+        }
+        _ => Arc::new(LogMailer),
+    }
+}
+
+// Basis URL til links i mails, fx https://estimer.dk
+pub fn app_base_url() -> String {
+    std::env::var("APP_BASE_URL").unwrap_or_else(|_| "http://localhost:5173".to_string())
+}
@@ -0,0 +1,51 @@
+use futures_util::future::BoxFuture;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{ AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor };
+use super::{ Mail, MailError, Mailer };
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    // Læser SMTP_HOST, SMTP_PORT, SMTP_USERNAME, SMTP_PASSWORD og MAIL_FROM
+    pub fn from_env() -> Result<Self, MailError> {
+        let env = |key: &str| std::env::var(key).map_err(|_| MailError(format!("{} skal være sat", key)));
+
+        let host = env("SMTP_HOST")?;
+        let from = env("MAIL_FROM")?
+            .parse::<Mailbox>()
+            .map_err(|e| MailError(e.to_string()))?;
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>
+            ::starttls_relay(&host)
+            .map_err(|e| MailError(e.to_string()))?;
+        if let Ok(port) = std::env::var("SMTP_PORT") {
+            builder = builder.port(port.parse().map_err(|_| MailError("Ugyldig SMTP_PORT".to_string()))?);
+        }
+        if let (Ok(username), Ok(password)) = (std::env::var("SMTP_USERNAME"), std::env::var("SMTP_PASSWORD")) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpMailer { transport: builder.build(), from })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, Result<(), MailError>> {
+        Box::pin(async move {
+            let to = mail.to.parse::<Mailbox>().map_err(|e| MailError(e.to_string()))?;
+            let message = Message::builder()
+                .from(self.from.clone())
+                .to(to)
+                .subject(mail.subject)
+                .body(mail.body)
+                .map_err(|e| MailError(e.to_string()))?;
+
+            self.transport.send(message).await.map_err(|e| MailError(e.to_string()))?;
+            Ok(())
+        })
+    }
+}
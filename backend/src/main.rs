@@ -3,13 +3,13 @@ mod models;
 mod handlers;
 mod middleware;
 mod websocket;
+mod mailer;
+mod rate_limit;
+mod oidc;
 
 use actix::Actor;
 use actix_cors::Cors;
 use actix_web::{ web, App, HttpServer };
-use dotenv::dotenv;
-use handlers::auth::{ login, register, get_me };
-use handlers::game_room::{ create_room, join_room, join_room_by_id, get_room, room_ws };
 use websocket::GameServer;
 use mongodb::Client;
 use crate::handlers::{ auth, game_room };
@@ -20,16 +20,14 @@ async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
     env_logger::init();
 
-    let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET skal være sat");
+    let _ = config::get_jwt_secret();
     let mongodb_uri = std::env::var("MONGODB_URI").expect("MONGODB_URI skal være sat");
 
     println!("Attempting to connect to MongoDB...");
-    println!("MongoDB URI (masked): {}", mongodb_uri.chars().take(20).collect::<String>() + "...");
 
     let client = Client::with_uri_str(&mongodb_uri).await
         .map_err(|e| {
             eprintln!("MongoDB connection error: {:?}", e);
-            eprintln!("Full MongoDB URI for debugging: {}", mongodb_uri);
             e
         })
         .expect("Kunne ikke oprette forbindelse til MongoDB");
@@ -67,33 +65,61 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
-    let game_server = GameServer::new();
+    let mailer = mailer::from_env();
+    let oidc_client = oidc::OidcConfig::from_env().map(|config| web::Data::new(oidc::OidcClient::new(config)));
+    let rate_limiter = rate_limit::RateLimiter::new(rate_limit::from_env());
+    let analytics_cache = web::Data::new(handlers::analytics::AnalyticsCache::from_env());
+
+    let game_server = GameServer::new(db.clone());
     let game_server_addr = game_server.clone().start();
     *GAME_SERVER.lock().unwrap() = Some(game_server_addr.clone());
 
     HttpServer::new(move || {
-        App::new()
+        let mut app = App::new();
+        // Uden OIDC_ISSUER er SSO slået fra og endpoints svarer derefter
+        if let Some(oidc_client) = &oidc_client {
+            app = app.app_data(oidc_client.clone());
+        }
+        app
             .wrap(
                 Cors::default()
                     .allowed_origin("https://www.estimer.dk")
                     .allowed_origin("https://estimer.dk")
                     .allowed_origin("http://localhost:5173")
                     .allowed_origin("http://127.0.0.1:5173")
-                    .allowed_methods(vec!["GET", "POST", "PUT", "OPTIONS"])
+                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
                     .allowed_headers(
                         vec![
                             actix_web::http::header::AUTHORIZATION,
                             actix_web::http::header::CONTENT_TYPE
                         ]
                     )
+                    .expose_headers(vec![actix_web::http::header::RETRY_AFTER])
                     .supports_credentials()
                     .max_age(3600)
             )
             .app_data(web::Data::new(db.clone()))
             .app_data(web::Data::new(game_server_addr.clone()))
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(web::Data::new(rate_limiter.clone()))
+            .app_data(analytics_cache.clone())
             .service(auth::register)
             .service(auth::login)
             .service(auth::get_me)
+            .service(auth::refresh)
+            .service(auth::logout)
+            .service(auth::logout_all)
+            .service(auth::request_password_reset)
+            .service(auth::confirm_password_reset)
+            .service(auth::verify_email)
+            .service(auth::resend_verification_email)
+            .service(handlers::two_factor::setup_two_factor)
+            .service(handlers::two_factor::enable_two_factor)
+            .service(handlers::two_factor::disable_two_factor)
+            .service(handlers::two_factor::verify_two_factor_login)
+            .service(handlers::oidc::oidc_config)
+            .service(handlers::oidc::oidc_login)
+            .service(handlers::oidc::oidc_callback)
             .service(game_room::create_room)
             .service(game_room::guest_join_room)
             .service(game_room::guest_create_room)
@@ -102,10 +128,52 @@ async fn main() -> std::io::Result<()> {
             .service(game_room::get_room)
             .service(game_room::get_room_info)
             .service(game_room::get_completed_stories)
+            .service(game_room::update_room_settings)
+            .service(game_room::get_room_ranking)
+            .service(game_room::submit_vote)
+            .service(game_room::create_story)
+            .service(handlers::chat::get_room_messages)
+            .service(handlers::notification::get_notifications)
+            .service(handlers::notification::mark_notification_read)
             .service(game_room::room_ws)
             .service(game_room::guest_room_ws)
+            .service(game_room::legacy_guest_room_ws)
             .service(handlers::user::upload_profile_image)
+            .service(handlers::user::update_profile)
+            .service(handlers::user::change_email)
+            .service(handlers::user::change_password)
+            .service(handlers::user::delete_account)
+            .service(handlers::export::export_my_data)
+            .service(handlers::api_token::create_api_token)
+            .service(handlers::api_token::list_api_tokens)
+            .service(handlers::api_token::revoke_api_token)
+            .service(handlers::team::create_team)
+            .service(handlers::team::list_teams)
+            .service(handlers::team::get_team)
+            .service(handlers::team::update_team)
+            .service(handlers::team::delete_team)
+            .service(handlers::team::invite_team_member)
+            .service(handlers::team::accept_team_invitation)
+            .service(handlers::team::delete_team_invitation)
+            .service(handlers::team::list_my_team_invitations)
+            .service(handlers::team::update_team_member)
+            .service(handlers::team::remove_team_member)
+            .service(handlers::team::list_team_rooms)
+            .service(handlers::room_session::start_session)
+            .service(handlers::room_session::end_session)
+            .service(handlers::room_session::list_sessions)
+            .service(handlers::analytics::room_analytics)
+            .service(handlers::analytics::team_analytics)
+            .service(handlers::analytics::my_analytics)
+            .service(handlers::analytics::room_accuracy)
+            .service(handlers::analytics::team_accuracy)
+            .service(game_room::record_actual_effort)
+            .service(handlers::reference::list_reference_stories)
+            .service(handlers::reference::pin_reference_story)
+            .service(handlers::reference::unpin_reference_story)
+            .service(handlers::export::export_user_data)
             .service(handlers::user::upload_guest_profile_image)
+            .service(handlers::user::legacy_upload_guest_profile_image)
             .service(actix_files::Files::new("/uploads", "uploads").show_files_listing())
     })
         .bind("0.0.0.0:8080")?
@@ -1,68 +1,466 @@
-use actix_web::{ error::ErrorUnauthorized, http::header::AUTHORIZATION, Error, HttpRequest };
-use jsonwebtoken::{ decode, DecodingKey, Validation, Algorithm };
+use actix_web::{
+    dev::Payload,
+    error::ResponseError,
+    http::{ header::AUTHORIZATION, StatusCode },
+    web,
+    FromRequest,
+    HttpRequest,
+    HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use jsonwebtoken::{ decode, encode, DecodingKey, EncodingKey, Header, Validation, Algorithm };
+use mongodb::bson::doc;
+use mongodb::Database;
 use serde::{ Deserialize, Serialize };
+use std::fmt;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use crate::config::get_jwt_secret;
+use crate::handlers::auth::hash_token;
+use crate::models::api_token::{ ApiToken, TokenScope };
+use crate::models::session::Session;
+use crate::models::user::User;
 
+// Access token for en registreret bruger
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    // Sessionen tokenet er udstedt til
+    pub sid: String,
 }
 
-pub async fn validate_token(req: HttpRequest) -> Result<String, Error> {
-    println!("Validerer token...");
-    println!("Headers: {:?}", req.headers());
+// Gæstetokens er bundet til ét rum og gælder lige så længe som en gæstesession i klienten
+const GUEST_TOKEN_TTL_SECS: u64 = 24 * 3600;
+const GUEST_TOKEN_TYPE: &str = "guest";
 
-    let token = req
-        .headers()
-        .get(AUTHORIZATION)
-        .and_then(|auth_header| {
-            println!("Auth header fundet: {:?}", auth_header);
-            auth_header.to_str().ok()
-        })
-        .and_then(|auth_str| {
-            println!("Auth string: {}", auth_str);
-            auth_str.strip_prefix("Bearer ")
-        })
-        .ok_or_else(|| {
-            println!("Ingen token fundet i header");
-            ErrorUnauthorized("Ingen token fundet")
-        })?;
+// Personlige API tokens kendes på præfikset og er ikke JWT'er
+pub const API_TOKEN_PREFIX: &str = "pp_";
+// Sidst brugt opdateres højst én gang i minuttet pr. token
+const API_TOKEN_TOUCH_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GuestClaims {
+    pub sub: String,
+    pub room_id: String,
+    pub exp: usize,
+    // Adskiller gæstetokens fra brugeres access tokens
+    pub typ: String,
+}
+
+// Et token er enten en gæsts eller en brugers. Gæstetokens har flest felter og prøves først.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyClaims {
+    Guest(GuestClaims),
+    User(Claims),
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+    SessionExpired,
+    AccountRequired,
+    WrongRoom,
+    AdminRequired,
+    // Endpointet kræver en rigtig login-session
+    ApiTokenNotAllowed,
+    MissingScope(TokenScope),
+    Internal,
+}
 
-    println!("Token efter strip_prefix: {}", token);
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "Ingen token fundet"),
+            AuthError::InvalidToken => write!(f, "Ugyldig token"),
+            AuthError::SessionExpired => write!(f, "Sessionen er udløbet eller logget ud"),
+            AuthError::AccountRequired => write!(f, "Dette kræver en brugerkonto"),
+            AuthError::WrongRoom => write!(f, "Du har ikke adgang til dette spilrum"),
+            AuthError::AdminRequired => write!(f, "Dette kræver administratorrettigheder"),
+            AuthError::ApiTokenNotAllowed => write!(f, "API tokens kan ikke bruges til dette endpoint"),
+            AuthError::MissingScope(scope) => write!(f, "Tokenet mangler adgangen {}", scope),
+            AuthError::Internal => write!(f, "Intern fejl ved validering af token"),
+        }
+    }
+}
 
-    let jwt_secret = std::env::var("JWT_SECRET").map_err(|e| {
-        println!("JWT_SECRET fejl: {:?}", e);
-        ErrorUnauthorized("JWT_SECRET er ikke konfigureret")
-    })?;
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::AccountRequired |
+            AuthError::WrongRoom |
+            AuthError::AdminRequired |
+            AuthError::ApiTokenNotAllowed |
+            AuthError::MissingScope(_) => StatusCode::FORBIDDEN,
+            AuthError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
 
-    println!("JWT_SECRET hentet fra env: {}", jwt_secret);
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(
+            serde_json::json!({
+                "message": self.to_string()
+            })
+        )
+    }
+}
+
+// En registreret bruger med en aktiv session
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: String,
+    pub session_id: String,
+}
 
+// En bruger der kalder med et personligt API token
+#[derive(Debug, Clone)]
+pub struct TokenUser {
+    pub id: String,
+    pub scopes: Vec<TokenScope>,
+}
+
+// Endpoints som scripts også må kalde: en bruger med en session eller et API token
+#[derive(Debug, Clone)]
+pub enum ApiUser {
+    Session(AuthUser),
+    Token(TokenUser),
+}
+
+impl ApiUser {
+    // Brugerens id, hvis kaldet må det `scope` dækker. Sessioner må alt.
+    pub fn require(&self, scope: TokenScope) -> Result<&str, AuthError> {
+        match self {
+            ApiUser::Session(user) => Ok(&user.id),
+            ApiUser::Token(token) if token.scopes.contains(&scope) => Ok(&token.id),
+            ApiUser::Token(_) => Err(AuthError::MissingScope(scope)),
+        }
+    }
+}
+
+// En registreret bruger med `is_admin` sat
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub id: String,
+}
+
+// En gæst med et gyldigt gæstetoken til `room_id`
+#[derive(Debug, Clone)]
+pub struct AuthGuest {
+    pub id: String,
+    pub room_id: String,
+}
+
+// Endpoints der er åbne for både brugere, gæster og anonyme.
+// Et token der er sendt med men er ugyldigt afvises stadig.
+#[derive(Debug, Clone)]
+pub enum MaybeUser {
+    User(AuthUser),
+    Token(TokenUser),
+    Guest(AuthGuest),
+    Anonymous,
+}
+
+impl MaybeUser {
+    // Id'et på den der kalder, hvis de må tilgå `room_id`.
+    // En gæst har kun adgang til det rum tokenet er udstedt til.
+    pub fn id_in_room(&self, room_id: &str) -> Result<&str, AuthError> {
+        match self {
+            MaybeUser::User(user) => Ok(&user.id),
+            MaybeUser::Token(token) if token.scopes.contains(&TokenScope::RoomsRead) => Ok(&token.id),
+            MaybeUser::Token(_) => Err(AuthError::MissingScope(TokenScope::RoomsRead)),
+            MaybeUser::Guest(guest) if guest.room_id == room_id => Ok(&guest.id),
+            MaybeUser::Guest(_) => Err(AuthError::WrongRoom),
+            MaybeUser::Anonymous => Err(AuthError::MissingToken),
+        }
+    }
+
+    fn into_session_user(self) -> Result<AuthUser, AuthError> {
+        match self {
+            MaybeUser::User(user) => Ok(user),
+            MaybeUser::Token(_) => Err(AuthError::ApiTokenNotAllowed),
+            MaybeUser::Guest(_) => Err(AuthError::AccountRequired),
+            MaybeUser::Anonymous => Err(AuthError::MissingToken),
+        }
+    }
+
+    fn into_guest(self) -> Result<AuthGuest, AuthError> {
+        match self {
+            MaybeUser::Guest(guest) => Ok(guest),
+            MaybeUser::User(_) | MaybeUser::Token(_) => Err(AuthError::InvalidToken),
+            MaybeUser::Anonymous => Err(AuthError::MissingToken),
+        }
+    }
+}
+
+// Tokenet fra Authorization headeren
+fn request_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|auth_header| auth_header.to_str().ok())
+        .and_then(|auth_str| auth_str.strip_prefix("Bearer "))
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_string())
+}
+
+// Browsere kan ikke sætte headers på WebSocket forbindelser, så her må tokenet også sendes som `?token=`.
+// Kun WebSocket endpoints bruger dette, så tokens ikke havner i adgangslogs for almindelige kald.
+fn ws_request_token(req: &HttpRequest) -> Option<String> {
+    request_token(req).or_else(|| {
+        req.query_string()
+            .split('&')
+            .find_map(|s| s.strip_prefix("token="))
+            .filter(|token| !token.is_empty())
+            .map(|token| token.to_string())
+    })
+}
+
+fn decode_claims(token: &str) -> Result<AnyClaims, AuthError> {
     let mut validation = Validation::new(Algorithm::HS256);
     validation.validate_exp = true;
 
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &validation
-    ).map_err(|e| {
-        println!("Token validering fejlede: {:?}", e);
-        ErrorUnauthorized("Ugyldig token")
-    })?;
+    decode::<AnyClaims>(token, &DecodingKey::from_secret(get_jwt_secret().as_bytes()), &validation)
+        .map(|token_data| token_data.claims)
+        .map_err(|_| AuthError::InvalidToken)
+}
+
+pub fn decode_guest_token(token: &str) -> Result<GuestClaims, AuthError> {
+    match decode_claims(token)? {
+        AnyClaims::Guest(claims) if claims.typ == GUEST_TOKEN_TYPE => Ok(claims),
+        _ => Err(AuthError::InvalidToken),
+    }
+}
+
+pub fn create_guest_token(guest_id: &str, room_id: &str) -> Result<String, AuthError> {
+    let expiration = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + GUEST_TOKEN_TTL_SECS;
+    let claims = GuestClaims {
+        sub: guest_id.to_string(),
+        room_id: room_id.to_string(),
+        exp: expiration as usize,
+        typ: GUEST_TOKEN_TYPE.to_string(),
+    };
+
+    encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(get_jwt_secret().as_bytes())
+    ).map_err(|_| AuthError::Internal)
+}
+
+// Et access token er kun gyldigt så længe dets session ikke er logget ud eller udløbet
+async fn ensure_session_active(db: &Database, claims: &Claims) -> Result<(), AuthError> {
+    let session_id = mongodb::bson::oid::ObjectId
+        ::parse_str(&claims.sid)
+        .map_err(|_| AuthError::InvalidToken)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    let session = db
+        .collection::<Session>("sessions")
+        .find_one(
+            doc! {
+                "_id": session_id,
+                "user_id": &claims.sub,
+                "revoked_at": null,
+                "expires_at": { "$gt": now }
+            },
+            None
+        ).await
+        .map_err(|e| {
+            println!("Fejl ved opslag af session: {:?}", e);
+            AuthError::Internal
+        })?;
+
+    if session.is_none() {
+        return Err(AuthError::SessionExpired);
+    }
+    Ok(())
+}
+
+async fn authenticate_api_token(db: &Database, token: &str) -> Result<TokenUser, AuthError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let collection = db.collection::<ApiToken>("api_tokens");
+    let token_hash = hash_token(token);
+
+    let api_token = collection
+        .find_one(
+            doc! {
+                "token_hash": &token_hash,
+                "revoked_at": null,
+                "$or": [{ "expires_at": null }, { "expires_at": { "$gt": now } }]
+            },
+            None
+        ).await
+        .map_err(|e| {
+            println!("Fejl ved opslag af API token: {:?}", e);
+            AuthError::Internal
+        })?
+        .ok_or(AuthError::InvalidToken)?;
+
+    let touch = collection.update_one(
+        doc! {
+            "_id": api_token.id,
+            "$or": [
+                { "last_used_at": null },
+                { "last_used_at": { "$lt": now - API_TOKEN_TOUCH_INTERVAL_SECS } }
+            ]
+        },
+        doc! { "$set": { "last_used_at": now } },
+        None
+    ).await;
+    if let Err(e) = touch {
+        println!("Kunne ikke opdatere API tokenets sidste brug: {:?}", e);
+    }
+
+    Ok(TokenUser { id: api_token.user_id, scopes: api_token.scopes })
+}
 
-    println!("Token valideret succesfuldt. User ID: {}", token_data.claims.sub);
-    Ok(token_data.claims.sub)
+async fn authenticate(req: HttpRequest) -> Result<MaybeUser, AuthError> {
+    let token = request_token(&req);
+    authenticate_token(req, token).await
 }
 
-// Optional token validation for guest users
-pub async fn validate_optional_token(req: HttpRequest) -> Result<Option<String>, Error> {
-    let auth_header = req.headers().get(AUTHORIZATION);
+async fn authenticate_token(req: HttpRequest, token: Option<String>) -> Result<MaybeUser, AuthError> {
+    let Some(token) = token else {
+        return Ok(MaybeUser::Anonymous);
+    };
 
-    if auth_header.is_none() {
-        return Ok(None); // No token present, allow guest access
+    if token.starts_with(API_TOKEN_PREFIX) {
+        let db = req.app_data::<web::Data<Database>>().ok_or(AuthError::Internal)?;
+        return Ok(MaybeUser::Token(authenticate_api_token(db, &token).await?));
     }
 
-    match validate_token(req).await {
-        Ok(user_id) => Ok(Some(user_id)),
-        Err(_) => Ok(None), // Invalid token, treat as guest
+    match decode_claims(&token)? {
+        AnyClaims::Guest(claims) => {
+            if claims.typ != GUEST_TOKEN_TYPE {
+                return Err(AuthError::InvalidToken);
+            }
+            Ok(MaybeUser::Guest(AuthGuest { id: claims.sub, room_id: claims.room_id }))
+        }
+        AnyClaims::User(claims) => {
+            let db = req.app_data::<web::Data<Database>>().ok_or(AuthError::Internal)?;
+            ensure_session_active(db, &claims).await?;
+            Ok(MaybeUser::User(AuthUser { id: claims.sub, session_id: claims.sid }))
+        }
+    }
+}
+
+impl FromRequest for MaybeUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        Box::pin(authenticate(req.clone()))
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { authenticate(req).await?.into_session_user() })
+    }
+}
+
+impl FromRequest for ApiUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            match authenticate(req).await? {
+                MaybeUser::User(user) => Ok(ApiUser::Session(user)),
+                MaybeUser::Token(token) => Ok(ApiUser::Token(token)),
+                MaybeUser::Guest(_) => Err(AuthError::AccountRequired),
+                MaybeUser::Anonymous => Err(AuthError::MissingToken),
+            }
+        })
     }
 }
+
+impl FromRequest for AdminUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let user = match authenticate(req.clone()).await? {
+                MaybeUser::User(user) => user,
+                MaybeUser::Token(_) => return Err(AuthError::ApiTokenNotAllowed),
+                MaybeUser::Guest(_) => return Err(AuthError::AccountRequired),
+                MaybeUser::Anonymous => return Err(AuthError::MissingToken),
+            };
+
+            let db = req.app_data::<web::Data<Database>>().ok_or(AuthError::Internal)?;
+            let object_id = mongodb::bson::oid::ObjectId
+                ::parse_str(&user.id)
+                .map_err(|_| AuthError::InvalidToken)?;
+            let is_admin = db
+                .collection::<User>("users")
+                .find_one(doc! { "_id": object_id }, None).await
+                .map_err(|e| {
+                    println!("Fejl ved opslag af administrator: {:?}", e);
+                    AuthError::Internal
+                })?
+                .is_some_and(|user| user.is_admin);
+
+            if !is_admin {
+                return Err(AuthError::AdminRequired);
+            }
+            Ok(AdminUser { id: user.id })
+        })
+    }
+}
+
+impl FromRequest for AuthGuest {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { authenticate(req).await?.into_guest() })
+    }
+}
+
+// Som `AuthUser`, men tokenet må også komme fra `?token=`. Kun til WebSocket forbindelser.
+#[derive(Debug, Clone)]
+pub struct WsUser(pub AuthUser);
+
+impl FromRequest for WsUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let token = ws_request_token(&req);
+            authenticate_token(req, token).await?.into_session_user().map(WsUser)
+        })
+    }
+}
+
+// Som `AuthGuest`, men tokenet må også komme fra `?token=`. Kun til WebSocket forbindelser.
+#[derive(Debug, Clone)]
+pub struct WsGuest(pub AuthGuest);
+
+impl FromRequest for WsGuest {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let token = ws_request_token(&req);
+            authenticate_token(req, token).await?.into_guest().map(WsGuest)
+        })
+    }
+}
+
+// De gamle gæste-URL'er uden token er kun slået til mens klienterne migrerer
+pub fn legacy_guest_urls_enabled() -> bool {
+    std::env::var("ALLOW_LEGACY_GUEST_URLS").map(|v| v == "true").unwrap_or(false)
+}
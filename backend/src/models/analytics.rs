@@ -0,0 +1,163 @@
+use serde::{ Deserialize, Serialize };
+use crate::models::game_room::EffortUnit;
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQuery {
+    // Unix sekunder. Uden grænser bruges hele historikken.
+    #[serde(default)]
+    pub from: Option<i64>,
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
+// Felterne matcher output fra aggregeringen i handlers::analytics
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AnalyticsTotals {
+    #[serde(default)]
+    pub story_count: u64,
+    #[serde(default)]
+    pub total_points: i64,
+    #[serde(default)]
+    pub average_score: Option<f64>,
+    #[serde(default)]
+    pub average_rounds: Option<f64>,
+    // Gennemsnitlig tid fra historien blev lagt op til den fik sin endelige score
+    #[serde(default)]
+    pub average_story_secs: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionPoints {
+    pub room_id: String,
+    // Historier estimeret før rummet brugte sessioner har ingen session
+    pub session_id: Option<String>,
+    pub story_count: u64,
+    pub total_points: i64,
+    pub first_completed_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScoreCount {
+    pub final_score: i32,
+    pub count: u64,
+}
+
+// Forskellen mellem højeste og laveste stemme, i gennemsnit pr. uge
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpreadPoint {
+    pub week_start: i64,
+    pub story_count: u64,
+    pub average_spread: f64,
+}
+
+// Hvor meget en deltager i gennemsnit stemmer over (positiv) eller under den endelige score
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserBias {
+    pub user_id: String,
+    pub username: String,
+    pub vote_count: u64,
+    pub average_bias: f64,
+    pub average_abs_bias: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnalyticsReport {
+    pub generated_at: i64,
+    #[serde(flatten)]
+    pub totals: AnalyticsTotals,
+    pub points_per_session: Vec<SessionPoints>,
+    pub score_distribution: Vec<ScoreCount>,
+    pub vote_spread: Vec<SpreadPoint>,
+    pub user_bias: Vec<UserBias>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Assessment {
+    // Den faktiske indsats var større end estimatet
+    UnderEstimated,
+    OverEstimated,
+    Accurate,
+    // For få historier til en vurdering
+    Insufficient,
+}
+
+// Hvor meget en time eller dag svarer til ét point i den valgte periode
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EffortBaseline {
+    pub unit: EffortUnit,
+    pub per_point: f64,
+    pub story_count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActualAverage {
+    pub unit: EffortUnit,
+    pub average: f64,
+    pub story_count: u64,
+}
+
+// Forholdet er faktisk indsats divideret med estimatet, normaliseret med periodens baseline.
+// 1.0 betyder at estimatet ramte plet, over 1.0 at opgaven var større end estimeret.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScoreBucketAccuracy {
+    pub final_score: i32,
+    pub story_count: u64,
+    pub average_ratio: f64,
+    pub actuals: Vec<ActualAverage>,
+    #[serde(default = "insufficient")]
+    pub assessment: Assessment,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PersonAccuracy {
+    pub user_id: String,
+    pub username: String,
+    pub story_count: u64,
+    pub average_ratio: f64,
+    #[serde(default = "insufficient")]
+    pub assessment: Assessment,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagAccuracy {
+    pub tag: String,
+    pub story_count: u64,
+    pub average_ratio: f64,
+    #[serde(default = "insufficient")]
+    pub assessment: Assessment,
+}
+
+fn insufficient() -> Assessment {
+    Assessment::Insufficient
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AccuracyTotals {
+    #[serde(default)]
+    pub story_count: u64,
+    #[serde(default)]
+    pub average_ratio: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FlaggedGroup {
+    // "score", "tag" eller "person"
+    pub kind: &'static str,
+    pub key: String,
+    pub assessment: Assessment,
+    pub average_ratio: f64,
+    pub story_count: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AccuracyReport {
+    pub generated_at: i64,
+    #[serde(flatten)]
+    pub totals: AccuracyTotals,
+    pub baselines: Vec<EffortBaseline>,
+    pub by_score: Vec<ScoreBucketAccuracy>,
+    pub by_person: Vec<PersonAccuracy>,
+    pub by_tag: Vec<TagAccuracy>,
+    pub flagged: Vec<FlaggedGroup>,
+}
@@ -0,0 +1,92 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{ Deserialize, Serialize };
+use std::fmt;
+
+// Hvad et personligt API token må bruges til
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TokenScope {
+    #[serde(rename = "rooms:read")]
+    RoomsRead,
+    #[serde(rename = "rooms:write")]
+    RoomsWrite,
+    #[serde(rename = "stories:write")]
+    StoriesWrite,
+    #[serde(rename = "export")]
+    Export,
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TokenScope::RoomsRead => "rooms:read",
+            TokenScope::RoomsWrite => "rooms:write",
+            TokenScope::StoriesWrite => "stories:write",
+            TokenScope::Export => "export",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Personligt API token til scripts og CI. Kun en hash af tokenet gemmes.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub name: String,
+    pub token_hash: String,
+    // Begyndelsen af tokenet, så brugeren kan genkende det i oversigten
+    pub token_prefix: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: i64,
+    #[serde(default)]
+    pub last_used_at: Option<i64>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    #[serde(default)]
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenDto {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    // Uden udløb gælder tokenet indtil det tilbagekaldes
+    #[serde(default)]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiTokenResponse {
+    pub id: String,
+    pub name: String,
+    pub token_prefix: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+}
+
+impl From<ApiToken> for ApiTokenResponse {
+    fn from(token: ApiToken) -> Self {
+        ApiTokenResponse {
+            id: token.id.map(|id| id.to_string()).unwrap_or_default(),
+            name: token.name,
+            token_prefix: token.token_prefix,
+            scopes: token.scopes,
+            created_at: token.created_at,
+            last_used_at: token.last_used_at,
+            expires_at: token.expires_at,
+            revoked_at: token.revoked_at,
+        }
+    }
+}
+
+// Svaret ved oprettelse. Selve tokenet vises kun denne ene gang.
+#[derive(Debug, Serialize)]
+pub struct CreatedApiTokenResponse {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ApiTokenResponse,
+}
@@ -0,0 +1,55 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{ Deserialize, Serialize };
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatMessage {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub room_id: String,
+    // Den historie der blev estimeret da beskeden blev skrevet
+    pub story_id: Option<String>,
+    pub user_id: String,
+    pub username: String,
+    pub profile_image: Option<String>,
+    pub text: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ChatMessageResponse {
+    pub id: String,
+    pub room_id: String,
+    pub story_id: Option<String>,
+    pub user_id: String,
+    pub username: String,
+    pub profile_image: Option<String>,
+    pub text: String,
+    pub created_at: i64,
+}
+
+impl From<ChatMessage> for ChatMessageResponse {
+    fn from(message: ChatMessage) -> Self {
+        ChatMessageResponse {
+            id: message.id.map(|id| id.to_string()).unwrap_or_default(),
+            room_id: message.room_id,
+            story_id: message.story_id,
+            user_id: message.user_id,
+            username: message.username,
+            profile_image: message.profile_image,
+            text: message.text,
+            created_at: message.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatHistoryResponse {
+    pub messages: Vec<ChatMessageResponse>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatHistoryQuery {
+    pub before: Option<String>,
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,316 @@
+use serde::{ Deserialize, Serialize };
+use std::collections::BTreeMap;
+use crate::models::game_room::Vote;
+
+// Maksimalt antal dimensioner et rum kan estimere på
+pub const MAX_DIMENSIONS: usize = 6;
+
+// En navngiven akse der estimeres på, fx "effort" eller "risk", med sit eget kortsæt
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EstimationDimension {
+    pub key: String,
+    pub name: String,
+    pub deck: Vec<i32>,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+// En række i opslagsmatricen: én værdi pr. dimension giver en samlet score
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LookupRow {
+    pub values: BTreeMap<String, i32>,
+    pub score: i32,
+}
+
+// Hvordan dimensionernes konsensus kombineres til den endelige score
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScoreCombination {
+    #[default]
+    WeightedSum,
+    Lookup {
+        rows: Vec<LookupRow>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DimensionStats {
+    pub key: String,
+    pub vote_count: usize,
+    pub min: i32,
+    pub max: i32,
+    pub mean: f64,
+    pub median: f64,
+    // Medianen rundet til nærmeste kort i dimensionens kortsæt
+    pub consensus: i32,
+}
+
+// Nærmeste kort i kortsættet. Ved lige afstand vælges det højeste kort.
+fn snap_to_deck(value: f64, deck: &[i32]) -> i32 {
+    deck.iter()
+        .copied()
+        .min_by(|a, b| {
+            let da = (value - *a as f64).abs();
+            let db = (value - *b as f64).abs();
+            da.partial_cmp(&db).unwrap_or(std::cmp::Ordering::Equal).then(b.cmp(a))
+        })
+        .unwrap_or(value.round() as i32)
+}
+
+fn median(sorted: &[i32]) -> f64 {
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        ((sorted[mid - 1] as f64) + (sorted[mid] as f64)) / 2.0
+    } else {
+        sorted[mid] as f64
+    }
+}
+
+pub fn dimension_stats(dimensions: &[EstimationDimension], votes: &[Vote]) -> Vec<DimensionStats> {
+    dimensions
+        .iter()
+        .filter_map(|dimension| {
+            let mut values: Vec<i32> = votes
+                .iter()
+                .filter_map(|vote| vote.dimension_values.as_ref()?.get(&dimension.key).copied())
+                .collect();
+            if values.is_empty() {
+                return None;
+            }
+            values.sort();
+
+            let median = median(&values);
+            Some(DimensionStats {
+                key: dimension.key.clone(),
+                vote_count: values.len(),
+                min: values[0],
+                max: values[values.len() - 1],
+                mean: (values.iter().map(|v| *v as f64).sum::<f64>()) / (values.len() as f64),
+                median,
+                consensus: snap_to_deck(median, &dimension.deck),
+            })
+        })
+        .collect()
+}
+
+// Kombinerer én værdi pr. dimension til en samlet score.
+// Et opslag uden matchende række falder tilbage til den vægtede sum.
+pub fn combine_scores(
+    dimensions: &[EstimationDimension],
+    combination: &ScoreCombination,
+    values: &BTreeMap<String, i32>
+) -> i32 {
+    if let ScoreCombination::Lookup { rows } = combination {
+        if let Some(row) = rows.iter().find(|row| &row.values == values) {
+            return row.score;
+        }
+    }
+
+    dimensions
+        .iter()
+        .filter_map(|dimension| {
+            values.get(&dimension.key).map(|value| (*value as f64) * dimension.weight)
+        })
+        .sum::<f64>()
+        .round() as i32
+}
+
+// Tjekker at en stemme har præcis én gyldig værdi pr. dimension
+pub fn validate_dimension_values(
+    dimensions: &[EstimationDimension],
+    values: &BTreeMap<String, i32>
+) -> Result<(), String> {
+    if values.len() != dimensions.len() {
+        return Err("Der skal stemmes på alle dimensioner".to_string());
+    }
+    for dimension in dimensions {
+        match values.get(&dimension.key) {
+            Some(value) if dimension.deck.contains(value) => {}
+            Some(value) => {
+                return Err(format!("{} er ikke et gyldigt kort for {}", value, dimension.name));
+            }
+            None => {
+                return Err(format!("Manglende værdi for {}", dimension.name));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Validerer et rums dimensionsopsætning før den gemmes
+pub fn validate_dimensions(
+    dimensions: &[EstimationDimension],
+    combination: &ScoreCombination
+) -> Result<(), String> {
+    if dimensions.len() > MAX_DIMENSIONS {
+        return Err(format!("Der kan højst være {} dimensioner", MAX_DIMENSIONS));
+    }
+    for (i, dimension) in dimensions.iter().enumerate() {
+        if dimension.key.trim().is_empty() || dimension.name.trim().is_empty() {
+            return Err("Alle dimensioner skal have en nøgle og et navn".to_string());
+        }
+        if dimensions[..i].iter().any(|d| d.key == dimension.key) {
+            return Err(format!("Dimensionen {} findes allerede", dimension.key));
+        }
+        if dimension.deck.is_empty() {
+            return Err(format!("Dimensionen {} mangler et kortsæt", dimension.key));
+        }
+        if !dimension.weight.is_finite() || dimension.weight < 0.0 {
+            return Err(format!("Ugyldig vægt for {}", dimension.key));
+        }
+    }
+    if let ScoreCombination::Lookup { rows } = combination {
+        if rows.iter().any(|row| validate_dimension_values(dimensions, &row.values).is_err()) {
+            return Err("Opslagsmatricen passer ikke til dimensionerne".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Dimensionerne i WSJF (Weighted Shortest Job First)
+pub const WSJF_BUSINESS_VALUE: &str = "business_value";
+pub const WSJF_TIME_CRITICALITY: &str = "time_criticality";
+pub const WSJF_RISK_REDUCTION: &str = "risk_reduction";
+pub const WSJF_JOB_SIZE: &str = "job_size";
+const WSJF_DECK: &[i32] = &[1, 2, 3, 5, 8, 13, 20];
+
+pub fn wsjf_dimensions() -> Vec<EstimationDimension> {
+    [
+        (WSJF_BUSINESS_VALUE, "Business value"),
+        (WSJF_TIME_CRITICALITY, "Time criticality"),
+        (WSJF_RISK_REDUCTION, "Risk reduction / opportunity enablement"),
+        (WSJF_JOB_SIZE, "Job size"),
+    ]
+        .iter()
+        .map(|(key, name)| EstimationDimension {
+            key: key.to_string(),
+            name: name.to_string(),
+            deck: WSJF_DECK.to_vec(),
+            weight: 1.0,
+        })
+        .collect()
+}
+
+// WSJF = (business value + time criticality + risk reduction) / job size
+pub fn wsjf_score(values: &BTreeMap<String, i32>) -> Option<f64> {
+    let job_size = *values.get(WSJF_JOB_SIZE)?;
+    if job_size <= 0 {
+        return None;
+    }
+    let cost_of_delay =
+        values.get(WSJF_BUSINESS_VALUE)? +
+        values.get(WSJF_TIME_CRITICALITY)? +
+        values.get(WSJF_RISK_REDUCTION)?;
+    Some((cost_of_delay as f64) / (job_size as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dimension(key: &str, weight: f64) -> EstimationDimension {
+        EstimationDimension {
+            key: key.to_string(),
+            name: key.to_string(),
+            deck: vec![1, 2, 3, 5, 8],
+            weight,
+        }
+    }
+
+    fn values(entries: &[(&str, i32)]) -> BTreeMap<String, i32> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), *value))
+            .collect()
+    }
+
+    fn vote(entries: &[(&str, i32)]) -> Vote {
+        Vote {
+            user_id: "u".to_string(),
+            username: "u".to_string(),
+            profile_image: None,
+            value: 0,
+            timestamp: 0,
+            dimension_values: Some(values(entries)),
+        }
+    }
+
+    #[test]
+    fn weighted_sum_rounds_to_nearest() {
+        let dimensions = [dimension("effort", 1.0), dimension("risk", 0.5)];
+        let score = combine_scores(&dimensions, &ScoreCombination::WeightedSum, &values(&[("effort", 5), ("risk", 3)]));
+        assert_eq!(score, 7);
+    }
+
+    #[test]
+    fn weighted_sum_ignores_values_without_dimension() {
+        let dimensions = [dimension("effort", 2.0)];
+        let score = combine_scores(&dimensions, &ScoreCombination::WeightedSum, &values(&[("effort", 3), ("other", 8)]));
+        assert_eq!(score, 6);
+    }
+
+    #[test]
+    fn lookup_uses_matching_row() {
+        let dimensions = [dimension("effort", 1.0), dimension("risk", 1.0)];
+        let combination = ScoreCombination::Lookup {
+            rows: vec![LookupRow { values: values(&[("effort", 3), ("risk", 5)]), score: 13 }],
+        };
+        assert_eq!(combine_scores(&dimensions, &combination, &values(&[("effort", 3), ("risk", 5)])), 13);
+    }
+
+    #[test]
+    fn lookup_without_match_falls_back_to_weighted_sum() {
+        let dimensions = [dimension("effort", 1.0), dimension("risk", 1.0)];
+        let combination = ScoreCombination::Lookup {
+            rows: vec![LookupRow { values: values(&[("effort", 3), ("risk", 5)]), score: 13 }],
+        };
+        assert_eq!(combine_scores(&dimensions, &combination, &values(&[("effort", 2), ("risk", 1)])), 3);
+    }
+
+    #[test]
+    fn consensus_tie_picks_higher_card() {
+        let dimensions = [dimension("effort", 1.0)];
+        // Medianen 4 ligger lige mellem 3 og 5
+        let stats = dimension_stats(&dimensions, &[vote(&[("effort", 3)]), vote(&[("effort", 5)])]);
+        assert_eq!(stats[0].median, 4.0);
+        assert_eq!(stats[0].consensus, 5);
+    }
+
+    #[test]
+    fn wsjf_divides_cost_of_delay_by_job_size() {
+        let score = wsjf_score(
+            &values(&[
+                (WSJF_BUSINESS_VALUE, 8),
+                (WSJF_TIME_CRITICALITY, 5),
+                (WSJF_RISK_REDUCTION, 2),
+                (WSJF_JOB_SIZE, 3),
+            ])
+        );
+        assert_eq!(score, Some(5.0));
+    }
+
+    #[test]
+    fn wsjf_without_job_size_has_no_score() {
+        let mut wsjf = values(&[
+            (WSJF_BUSINESS_VALUE, 8),
+            (WSJF_TIME_CRITICALITY, 5),
+            (WSJF_RISK_REDUCTION, 2),
+            (WSJF_JOB_SIZE, 0),
+        ]);
+        assert_eq!(wsjf_score(&wsjf), None);
+        wsjf.insert(WSJF_JOB_SIZE.to_string(), -1);
+        assert_eq!(wsjf_score(&wsjf), None);
+        wsjf.remove(WSJF_JOB_SIZE);
+        assert_eq!(wsjf_score(&wsjf), None);
+    }
+
+    #[test]
+    fn wsjf_missing_cost_of_delay_value_has_no_score() {
+        let wsjf = values(&[(WSJF_BUSINESS_VALUE, 8), (WSJF_TIME_CRITICALITY, 5), (WSJF_JOB_SIZE, 3)]);
+        assert_eq!(wsjf_score(&wsjf), None);
+    }
+}
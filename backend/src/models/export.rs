@@ -0,0 +1,99 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::models::api_token::ApiTokenResponse;
+use crate::models::chat::ChatMessageResponse;
+use crate::models::notification::NotificationResponse;
+use crate::models::team::TeamRole;
+use crate::models::user::ExternalIdentity;
+
+// Alt vi har gemt om en bruger, til indsigtsanmodninger efter GDPR.
+// Hemmeligheder som adgangskode- og token-hashes udelades.
+#[derive(Debug, Serialize)]
+pub struct UserExport {
+    pub exported_at: i64,
+    pub account: AccountExport,
+    pub rooms: Vec<RoomMembershipExport>,
+    pub teams: Vec<TeamMembershipExport>,
+    pub votes: Vec<VoteExport>,
+    pub confidence_votes: Vec<ConfidenceVoteExport>,
+    pub dot_votes: Vec<DotVoteExport>,
+    pub chat_messages: Vec<ChatMessageResponse>,
+    pub notifications: Vec<NotificationResponse>,
+    pub sessions: Vec<SessionExport>,
+    pub api_tokens: Vec<ApiTokenResponse>,
+    // Stier til uploadede billeder i ZIP-filen
+    pub images: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub id: String,
+    pub email: String,
+    pub username: String,
+    pub profile_image: Option<String>,
+    pub email_verified: bool,
+    pub two_factor_enabled: bool,
+    pub identities: Vec<ExternalIdentity>,
+    pub is_admin: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomMembershipExport {
+    pub room_id: String,
+    pub name: String,
+    pub is_admin: bool,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamMembershipExport {
+    pub team_id: String,
+    pub name: String,
+    pub role: TeamRole,
+    pub joined_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VoteExport {
+    // "game_rooms" eller "completed_stories"
+    pub source: &'static str,
+    pub room_id: String,
+    pub story_id: String,
+    pub title: String,
+    pub value: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimension_values: Option<BTreeMap<String, i32>>,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfidenceVoteExport {
+    pub room_id: String,
+    pub story_id: String,
+    pub title: String,
+    pub value: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DotVoteExport {
+    pub room_id: String,
+    pub story_id: String,
+    pub dots: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionExport {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub expires_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ExportQuery {
+    // "json" giver kun data.json; standard er en ZIP med billeder
+    #[serde(default)]
+    pub format: Option<String>,
+}
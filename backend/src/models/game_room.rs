@@ -1,14 +1,14 @@
 use mongodb::bson::oid::ObjectId;
 use serde::{ Deserialize, Serialize };
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct Participant {
-    pub id: String,
-    pub username: String,
-    pub profile_image: Option<String>,
-    pub is_guest: bool,
-}
+use std::collections::{ BTreeMap, HashMap };
+use crate::models::estimation::{
+    EstimationDimension,
+    ScoreCombination,
+    DimensionStats,
+    combine_scores,
+    wsjf_dimensions,
+    wsjf_score,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -19,6 +19,10 @@ pub struct Vote {
     pub profile_image: Option<String>,
     pub value: i32,
     pub timestamp: i64,
+    // Én værdi pr. dimension i rum med flere estimeringsdimensioner.
+    // `value` er da stemmens kombinerede score.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimension_values: Option<BTreeMap<String, i32>>,
 }
 
 impl From<Vote> for mongodb::bson::Bson {
@@ -27,6 +31,51 @@ impl From<Vote> for mongodb::bson::Bson {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VoteCount {
+    pub value: i32,
+    pub count: usize,
+}
+
+// Antal stemmer pr. værdi, sorteret efter værdi
+pub fn vote_distribution(votes: &[Vote]) -> Vec<VoteCount> {
+    let mut distribution: Vec<VoteCount> = Vec::new();
+    for vote in votes {
+        match distribution.iter_mut().find(|c| c.value == vote.value) {
+            Some(entry) => {
+                entry.count += 1;
+            }
+            None => distribution.push(VoteCount { value: vote.value, count: 1 }),
+        }
+    }
+    distribution.sort_by_key(|c| c.value);
+    distribution
+}
+
+// Fjerner identiteten fra alle stemmer undtagen `viewer_id`'s egen.
+// Stemmerne sorteres efter værdi så rækkefølgen ikke afslører hvem der stemte hvornår.
+pub fn anonymize_votes(votes: Vec<Vote>, viewer_id: Option<&str>) -> Vec<Vote> {
+    let mut votes: Vec<Vote> = votes
+        .into_iter()
+        .map(|vote| {
+            if viewer_id == Some(vote.user_id.as_str()) {
+                vote
+            } else {
+                Vote {
+                    user_id: String::new(),
+                    username: String::new(),
+                    profile_image: None,
+                    value: vote.value,
+                    timestamp: 0,
+                    dimension_values: vote.dimension_values,
+                }
+            }
+        })
+        .collect();
+    votes.sort_by_key(|vote| vote.value);
+    votes
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Story {
@@ -39,6 +88,74 @@ pub struct Story {
     pub votes: Vec<Vote>,
     #[serde(default)]
     pub final_score: Option<i32>,
+    // Asynkrone rum: tidspunkt (unix sekunder) hvor stemmerne afsløres automatisk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voting_deadline: Option<i64>,
+    // Tidspunkt hvor historien blev oprettet i rummet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<i64>,
+    // Antal gange stemmerne på historien er afsløret
+    #[serde(default)]
+    pub rounds: u32,
+    // Stemmerne blev afgivet i anonym tilstand og forbliver anonyme
+    #[serde(default)]
+    pub anonymous: bool,
+}
+
+// Mindste og største antal fingre i en "fist of five" runde
+pub const MIN_CONFIDENCE: i32 = 1;
+pub const MAX_CONFIDENCE: i32 = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfidenceVote {
+    pub user_id: String,
+    pub value: i32,
+}
+
+// Resultatet af en tillidsrunde efter at historien har fået sin endelige score
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfidenceResult {
+    pub votes: Vec<ConfidenceVote>,
+    pub average: f64,
+    pub distribution: Vec<VoteCount>,
+    pub low_confidence: bool,
+    pub completed_at: i64,
+}
+
+impl ConfidenceResult {
+    pub fn new(votes: Vec<ConfidenceVote>, threshold: f64, completed_at: i64) -> Self {
+        let average = if votes.is_empty() {
+            0.0
+        } else {
+            (votes.iter().map(|v| v.value as f64).sum::<f64>()) / (votes.len() as f64)
+        };
+        let mut distribution: Vec<VoteCount> = (MIN_CONFIDENCE..=MAX_CONFIDENCE)
+            .map(|value| VoteCount { value, count: 0 })
+            .collect();
+        for vote in &votes {
+            if let Some(entry) = distribution.iter_mut().find(|c| c.value == vote.value) {
+                entry.count += 1;
+            }
+        }
+        ConfidenceResult {
+            low_confidence: !votes.is_empty() && average < threshold,
+            votes,
+            average,
+            distribution,
+            completed_at,
+        }
+    }
+
+    // Skjul hvem der gav hvilken tillid, undtagen `viewer_id`'s egen
+    pub fn anonymized_for(mut self, viewer_id: Option<&str>) -> Self {
+        for vote in self.votes.iter_mut() {
+            if viewer_id != Some(vote.user_id.as_str()) {
+                vote.user_id = String::new();
+            }
+        }
+        self.votes.sort_by_key(|vote| vote.value);
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -52,6 +169,277 @@ pub struct CompletedStory {
     pub votes: Vec<Vote>,
     pub final_score: i32,
     pub completed_at: i64,
+    // Historien blev estimeret i anonym tilstand
+    #[serde(default)]
+    pub anonymous: bool,
+    #[serde(default)]
+    pub dimension_stats: Vec<DimensionStats>,
+    #[serde(default)]
+    pub confidence: Option<ConfidenceResult>,
+    // Beregnet WSJF for historier prioriteret i et WSJF-rum
+    #[serde(default)]
+    pub wsjf: Option<f64>,
+    // Sessionen historien blev estimeret i, hvis rummet bruger sessioner
+    #[serde(default)]
+    pub session_id: Option<String>,
+    // Antal gange historien blev stemt om før den fik sin endelige score
+    #[serde(default = "default_rounds")]
+    pub rounds: u32,
+    // Hvornår historien første gang blev lagt op til estimering
+    #[serde(default)]
+    pub started_at: Option<i64>,
+    // Den faktiske indsats, registreret når historien er leveret
+    #[serde(default)]
+    pub actual: Option<ActualEffort>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+fn default_rounds() -> u32 {
+    1
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EffortUnit {
+    Hours,
+    Days,
+    Points,
+}
+
+const MAX_TAGS: usize = 10;
+const MAX_TAG_LENGTH: usize = 30;
+
+// Tags gemmes med små bogstaver og uden dubletter, så de kan grupperes i rapporter
+pub fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || normalized.contains(&tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(format!("Tags må højst være {} tegn", MAX_TAG_LENGTH));
+        }
+        normalized.push(tag);
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(format!("En historie kan højst have {} tags", MAX_TAGS));
+    }
+    Ok(normalized)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActualEffort {
+    pub value: f64,
+    pub unit: EffortUnit,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub recorded_by: String,
+    pub recorded_at: i64,
+}
+
+// Et dateret møde i et fast rum, fx refinement i en sprint.
+// Rummet og dets invitationskode er de samme på tværs af sessioner.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoomSession {
+    pub id: String,
+    pub name: String,
+    pub started_at: i64,
+    #[serde(default)]
+    pub ended_at: Option<i64>,
+    // Gemmes når sessionen afsluttes. For den aktive session beregnes den ved opslag.
+    #[serde(default)]
+    pub summary: Option<SessionSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SessionSummary {
+    pub story_count: usize,
+    pub total_points: i64,
+    pub average_score: Option<f64>,
+    // Antal forskellige deltagere der har stemt
+    pub voter_count: usize,
+    pub low_confidence_count: usize,
+    pub duration_secs: i64,
+}
+
+impl SessionSummary {
+    pub fn from_stories(stories: &[CompletedStory], started_at: i64, ended_at: i64) -> Self {
+        let total_points: i64 = stories
+            .iter()
+            .map(|s| s.final_score as i64)
+            .sum();
+        let mut voters: Vec<&str> = stories
+            .iter()
+            .flat_map(|s| s.votes.iter().map(|v| v.user_id.as_str()))
+            .filter(|id| !id.is_empty())
+            .collect();
+        voters.sort();
+        voters.dedup();
+        SessionSummary {
+            story_count: stories.len(),
+            total_points,
+            average_score: if stories.is_empty() {
+                None
+            } else {
+                Some((total_points as f64) / (stories.len() as f64))
+            },
+            voter_count: voters.len(),
+            low_confidence_count: stories
+                .iter()
+                .filter(|s| s.confidence.as_ref().is_some_and(|c| c.low_confidence))
+                .count(),
+            duration_secs: (ended_at - started_at).max(0),
+        }
+    }
+}
+
+// Emojis der må bruges som reaktioner hvis rummet ikke har sin egen liste
+pub const DEFAULT_ALLOWED_EMOJIS: &[&str] = &["👍", "👎", "🎉", "🤔", "😂", "❤️", "🔥", "☕"];
+
+fn default_allowed_emojis() -> Vec<String> {
+    DEFAULT_ALLOWED_EMOJIS.iter()
+        .map(|e| e.to_string())
+        .collect()
+}
+
+// Kortbunken rummet bruger hvis intet andet er valgt. -1 er "?" kortet.
+pub const DEFAULT_DECK: &[i32] = &[0, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, -1];
+pub const UNKNOWN_CARD: i32 = -1;
+const MAX_DECK_SIZE: usize = 20;
+
+// Minimum antal sekunder en nedtælling kan startes eller forlænges med
+pub const MIN_TIMER_SECS: u64 = 5;
+// Maksimal længde på en nedtælling (1 time)
+pub const MAX_TIMER_SECS: u64 = 3600;
+
+fn default_deck() -> Vec<i32> {
+    DEFAULT_DECK.to_vec()
+}
+
+pub fn validate_deck(deck: &[i32]) -> Result<(), String> {
+    if deck.is_empty() || deck.len() > MAX_DECK_SIZE {
+        return Err(format!("Kortbunken skal have mellem 1 og {} kort", MAX_DECK_SIZE));
+    }
+    if deck.iter().any(|card| *card < UNKNOWN_CARD) {
+        return Err("Kortene må ikke være negative".to_string());
+    }
+    for (index, card) in deck.iter().enumerate() {
+        if deck[..index].contains(card) {
+            return Err(format!("Kortet {} findes flere gange", card));
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_timer_secs(secs: u32) -> Result<(), String> {
+    if !(MIN_TIMER_SECS..=MAX_TIMER_SECS).contains(&(secs as u64)) {
+        return Err(
+            format!("Nedtællingen skal være mellem {} og {} sekunder", MIN_TIMER_SECS, MAX_TIMER_SECS)
+        );
+    }
+    Ok(())
+}
+
+// Hvad rummet bruges til
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomMode {
+    // Klassisk planning poker
+    #[default]
+    Estimation,
+    // Prioritering efter Weighted Shortest Job First
+    Wsjf,
+    // Hver deltager fordeler et antal prikker på backloggen
+    DotVoting,
+    // Historier står åbne i en periode og stemmerne afsløres når fristen udløber
+    // eller alle har stemt. Ingen behøver at være forbundet samtidig.
+    Async,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoomSettings {
+    #[serde(default)]
+    pub mode: RoomMode,
+    #[serde(default = "default_allowed_emojis")]
+    pub allowed_emojis: Vec<String>,
+    // Afslørede stemmer vises kun som fordeling, uden navne og avatarer
+    #[serde(default)]
+    pub anonymous_votes: bool,
+    // Tom liste betyder klassisk estimering med én værdi
+    #[serde(default)]
+    pub dimensions: Vec<EstimationDimension>,
+    #[serde(default)]
+    pub combination: ScoreCombination,
+    // Start automatisk en tillidsrunde når en historie har fået sin endelige score
+    #[serde(default)]
+    pub confidence_check: bool,
+    // Gennemsnit under denne grænse markerer historien som usikker
+    #[serde(default = "default_low_confidence_threshold")]
+    pub low_confidence_threshold: f64,
+    // Antal prikker hver deltager har i dot voting
+    #[serde(default = "default_dot_budget")]
+    pub dot_budget: u32,
+    // Hvor længe en historie står åben for stemmer i et asynkront rum
+    #[serde(default = "default_async_voting_hours")]
+    pub async_voting_hours: u32,
+    #[serde(default = "default_deck")]
+    pub deck: Vec<i32>,
+    // Varighed der bruges når nedtællingen startes uden en længde
+    #[serde(default)]
+    pub timer_secs: Option<u32>,
+}
+
+fn default_low_confidence_threshold() -> f64 {
+    3.0
+}
+
+fn default_dot_budget() -> u32 {
+    5
+}
+
+fn default_async_voting_hours() -> u32 {
+    24
+}
+
+impl RoomSettings {
+    // Dimensionerne der stemmes på i rummets aktuelle tilstand
+    pub fn active_dimensions(&self) -> Vec<EstimationDimension> {
+        match self.mode {
+            RoomMode::Wsjf => wsjf_dimensions(),
+            _ => self.dimensions.clone(),
+        }
+    }
+
+    // Samlet score for én værdi pr. dimension
+    pub fn combined_score(&self, values: &BTreeMap<String, i32>) -> i32 {
+        match self.mode {
+            RoomMode::Wsjf =>
+                wsjf_score(values)
+                    .map(|score| score.round() as i32)
+                    .unwrap_or(0),
+            _ => combine_scores(&self.dimensions, &self.combination, values),
+        }
+    }
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            mode: RoomMode::default(),
+            allowed_emojis: default_allowed_emojis(),
+            anonymous_votes: false,
+            dimensions: Vec::new(),
+            combination: ScoreCombination::default(),
+            confidence_check: false,
+            low_confidence_threshold: default_low_confidence_threshold(),
+            dot_budget: default_dot_budget(),
+            async_voting_hours: default_async_voting_hours(),
+            deck: default_deck(),
+            timer_secs: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -65,6 +453,20 @@ pub struct GameRoom {
     pub current_story: Option<Story>,
     pub completed_stories: Vec<Story>,
     pub stories: Vec<Story>, // Alle historier (både aktive og afsluttede)
+    // Teamets medlemmer er automatisk deltagere i rummet
+    #[serde(default)]
+    pub team_id: Option<String>,
+    #[serde(default)]
+    pub sessions: Vec<RoomSession>,
+    #[serde(default)]
+    pub active_session_id: Option<String>,
+    #[serde(default)]
+    pub reference_stories: Vec<ReferenceStory>,
+    #[serde(default)]
+    pub settings: RoomSettings,
+    // Dot voting: user_id -> (story_id -> antal prikker)
+    #[serde(default)]
+    pub dot_votes: HashMap<String, BTreeMap<String, u32>>,
     pub created_at: i64,
     pub updated_at: i64,
 }
@@ -72,6 +474,10 @@ pub struct GameRoom {
 #[derive(Debug, Deserialize)]
 pub struct CreateRoomDto {
     pub name: String,
+    #[serde(default)]
+    pub mode: RoomMode,
+    #[serde(default)]
+    pub team_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -83,18 +489,92 @@ pub struct JoinRoomDto {
 pub struct GuestCreateRoomDto {
     pub room_name: String,
     pub username: String,
+    #[serde(default)]
+    pub mode: RoomMode,
 }
 
-#[derive(Debug, Serialize)]
-pub struct GameRoomResponse {
-    pub id: String,
-    pub name: String,
-    pub invite_code: String,
-    pub admin_id: String,
-    pub participants: Vec<String>,
-    pub current_story: Option<Story>,
-    pub completed_stories: Vec<Story>,
-    pub stories: Vec<Story>, // Alle historier (både aktive og afsluttede)
-    pub created_at: i64,
-    pub updated_at: i64,
+#[derive(Debug, Deserialize)]
+pub struct UpdateRoomSettingsDto {
+    pub allowed_emojis: Option<Vec<String>>,
+    pub anonymous_votes: Option<bool>,
+    pub dimensions: Option<Vec<EstimationDimension>>,
+    pub combination: Option<ScoreCombination>,
+    pub confidence_check: Option<bool>,
+    pub low_confidence_threshold: Option<f64>,
+    pub mode: Option<RoomMode>,
+    pub dot_budget: Option<u32>,
+    pub async_voting_hours: Option<u32>,
+    pub deck: Option<Vec<i32>>,
+    // 0 fjerner standardlængden
+    pub timer_secs: Option<u32>,
+}
+
+// Stemme afgivet via REST, fx fra en kollega i en anden tidszone
+#[derive(Debug, Deserialize)]
+pub struct SubmitVoteDto {
+    pub story_id: String,
+    pub value: Option<i32>,
+    pub values: Option<BTreeMap<String, i32>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecordActualDto {
+    pub value: f64,
+    pub unit: EffortUnit,
+    #[serde(default)]
+    pub note: Option<String>,
+    // Erstatter historiens tags hvis angivet
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+}
+
+// En afsluttet historie admin har fastgjort som eksempel på en kortværdi
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReferenceStory {
+    pub value: i32,
+    pub story_id: String,
+    pub room_id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub final_score: i32,
+    pub pinned_by: String,
+    pub pinned_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinReferenceStoryDto {
+    pub story_id: String,
+    // Standard er historiens endelige score
+    #[serde(default)]
+    pub value: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartSessionDto {
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompletedStoriesQuery {
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+// Ny historie oprettet via REST, fx af et CI-job
+#[derive(Debug, Deserialize)]
+pub struct CreateStoryDto {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+// En historie i rummets prioriterede rækkefølge
+#[derive(Debug, Serialize, Clone)]
+pub struct RankedStory {
+    pub rank: usize,
+    pub story_id: String,
+    pub title: String,
+    pub score: f64,
 }
@@ -1,2 +1,12 @@
 pub mod user;
-pub mod game_room; 
\ No newline at end of file
+pub mod game_room;
+pub mod chat;
+pub mod estimation;
+pub mod notification;
+pub mod session;
+pub mod oidc;
+pub mod export;
+pub mod api_token;
+pub mod team;
+pub mod analytics;
+pub mod similarity;
\ No newline at end of file
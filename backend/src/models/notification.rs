@@ -0,0 +1,57 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{ Deserialize, Serialize };
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    // Stemmerne på en asynkron historie er afsløret
+    ResultsReady,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notification {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub kind: NotificationKind,
+    pub room_id: String,
+    pub story_id: String,
+    pub title: String,
+    pub final_score: Option<i32>,
+    #[serde(default)]
+    pub read: bool,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct NotificationResponse {
+    pub id: String,
+    pub kind: NotificationKind,
+    pub room_id: String,
+    pub story_id: String,
+    pub title: String,
+    pub final_score: Option<i32>,
+    pub read: bool,
+    pub created_at: i64,
+}
+
+impl From<Notification> for NotificationResponse {
+    fn from(notification: Notification) -> Self {
+        NotificationResponse {
+            id: notification.id.map(|id| id.to_string()).unwrap_or_default(),
+            kind: notification.kind,
+            room_id: notification.room_id,
+            story_id: notification.story_id,
+            title: notification.title,
+            final_score: notification.final_score,
+            read: notification.read,
+            created_at: notification.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationQuery {
+    #[serde(default)]
+    pub unread_only: bool,
+}
@@ -0,0 +1,34 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{ Deserialize, Serialize };
+
+// Et påbegyndt SSO login. Slettes når udbyderen sender brugeren tilbage.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcLoginState {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    // Gæstetoken fra før login, så gæstens historik kan flettes ind i kontoen
+    #[serde(default)]
+    pub guest_token: Option<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcLoginQuery {
+    #[serde(default)]
+    pub guest_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    // Sat af udbyderen hvis brugeren afviste eller noget gik galt
+    #[serde(default)]
+    pub error: Option<String>,
+}
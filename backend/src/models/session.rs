@@ -0,0 +1,28 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{ Deserialize, Serialize };
+
+// En login-session. Access tokens peger på sessionen via `sid`, så en
+// tilbagekaldt session også ugyldiggør de access tokens der er udstedt til den.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Session {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    // SHA-256 af det nuværende refresh token. Selve tokenet gemmes aldrig.
+    pub refresh_token_hash: String,
+    // Hash af det forrige refresh token, så genbrug af et roteret token kan opdages
+    #[serde(default)]
+    pub previous_refresh_token_hash: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub expires_at: i64,
+    #[serde(default)]
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenDto {
+    pub refresh_token: String,
+}
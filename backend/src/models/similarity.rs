@@ -0,0 +1,164 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Almindelige danske og engelske ord der ikke siger noget om historiens indhold
+const STOPWORDS: &[&str] = &[
+    "af", "at", "de", "den", "der", "det", "du", "en", "er", "et", "for", "fra", "har", "hvis", "i",
+    "ikke", "jeg", "kan", "med", "men", "og", "om", "på", "skal", "som", "til", "ved", "vi", "være",
+    "a", "an", "and", "as", "be", "by", "can", "for", "from", "in", "is", "it", "of", "on", "or",
+    "should", "so", "that", "the", "to", "we", "when", "with",
+];
+
+// En tidligere historie der ligner den nye, sendt med i `new_story`
+#[derive(Debug, Serialize, Clone)]
+pub struct SimilarStory {
+    pub story_id: String,
+    pub room_id: String,
+    pub title: String,
+    pub final_score: i32,
+    pub completed_at: i64,
+    pub similarity: f64,
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| token.chars().count() >= 2 && !STOPWORDS.contains(token))
+        .map(str::to_string)
+        .collect()
+}
+
+fn term_weights(tokens: &[String], idf: &HashMap<&str, f64>) -> HashMap<String, f64> {
+    let mut counts: HashMap<String, f64> = HashMap::new();
+    for token in tokens {
+        *counts.entry(token.clone()).or_insert(0.0) += 1.0;
+    }
+    counts
+        .into_iter()
+        .map(|(term, count)| {
+            let weight = (1.0 + count.ln()) * idf.get(term.as_str()).copied().unwrap_or(0.0);
+            (term, weight)
+        })
+        .collect()
+}
+
+fn cosine(a: &HashMap<String, f64>, b: &HashMap<String, f64>) -> f64 {
+    let dot: f64 = a
+        .iter()
+        .filter_map(|(term, weight)| b.get(term).map(|other| weight * other))
+        .sum();
+    let norm = |v: &HashMap<String, f64>| v.values().map(|w| w * w).sum::<f64>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 { 0.0 } else { dot / denominator }
+}
+
+// TF-IDF med cosinus-lighed. Returnerer (indeks i `documents`, lighed) for de
+// `limit` mest lignende dokumenter over `min_similarity`, bedste først.
+pub fn most_similar(query: &str, documents: &[String], limit: usize, min_similarity: f64) -> Vec<(usize, f64)> {
+    let query_tokens = tokenize(query);
+    if query_tokens.is_empty() || documents.is_empty() {
+        return Vec::new();
+    }
+    let document_tokens: Vec<Vec<String>> = documents
+        .iter()
+        .map(|document| tokenize(document))
+        .collect();
+
+    // Forespørgslen tæller med i korpus, så ord der kun findes i den ikke giver division med nul
+    let corpus_size = (documents.len() + 1) as f64;
+    let mut document_frequency: HashMap<&str, f64> = HashMap::new();
+    for tokens in document_tokens.iter().chain(std::iter::once(&query_tokens)) {
+        let mut seen: Vec<&str> = tokens
+            .iter()
+            .map(String::as_str)
+            .collect();
+        seen.sort();
+        seen.dedup();
+        for term in seen {
+            *document_frequency.entry(term).or_insert(0.0) += 1.0;
+        }
+    }
+    let idf: HashMap<&str, f64> = document_frequency
+        .into_iter()
+        .map(|(term, df)| (term, ((1.0 + corpus_size) / (1.0 + df)).ln() + 1.0))
+        .collect();
+
+    let query_weights = term_weights(&query_tokens, &idf);
+    let mut scored: Vec<(usize, f64)> = document_tokens
+        .iter()
+        .enumerate()
+        .map(|(index, tokens)| (index, cosine(&query_weights, &term_weights(tokens, &idf))))
+        .filter(|(_, similarity)| *similarity >= min_similarity)
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(limit);
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn documents(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[test]
+    fn tokenize_lowercases_and_drops_stopwords_and_short_tokens() {
+        assert_eq!(tokenize("Login på SIDEN, og a x-y 2FA"), vec!["login", "siden", "2fa"]);
+        assert_eq!(tokenize("Ændr bruger-profil"), vec!["ændr", "bruger", "profil"]);
+    }
+
+    #[test]
+    fn empty_query_has_no_matches() {
+        let docs = documents(&["Login side", "Eksport af data"]);
+        assert!(most_similar("", &docs, 3, 0.0).is_empty());
+        assert!(most_similar("   ", &docs, 3, 0.0).is_empty());
+    }
+
+    #[test]
+    fn stopword_only_query_has_no_matches() {
+        let docs = documents(&["Login side", "Det er en og i"]);
+        assert!(most_similar("det er en og i", &docs, 3, 0.0).is_empty());
+    }
+
+    #[test]
+    fn no_documents_gives_no_matches() {
+        assert!(most_similar("Login side", &[], 3, 0.0).is_empty());
+    }
+
+    #[test]
+    fn ranks_closest_document_first() {
+        let docs = documents(&["Eksport af data til CSV", "Login side med SSO", "Login side"]);
+        let result = most_similar("Ny login side", &docs, 3, 0.1);
+        let order: Vec<usize> = result.iter().map(|(index, _)| *index).collect();
+        assert_eq!(order, vec![2, 1]);
+        assert!(result[0].1 > result[1].1);
+    }
+
+    #[test]
+    fn identical_text_has_similarity_one() {
+        let docs = documents(&["Login side", "Eksport af data"]);
+        let result = most_similar("Login side", &docs, 1, 0.0);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, 0);
+        assert!((result[0].1 - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn min_similarity_cuts_off_weak_matches() {
+        let docs = documents(&["Login side", "Login side med SSO og to-faktor og gendannelse"]);
+        let all = most_similar("Login side", &docs, 3, 0.0);
+        assert_eq!(all.len(), 2);
+        let cutoff = (all[0].1 + all[1].1) / 2.0;
+        let strong = most_similar("Login side", &docs, 3, cutoff);
+        assert_eq!(strong.len(), 1);
+        assert_eq!(strong[0].0, 0);
+    }
+
+    #[test]
+    fn limit_caps_number_of_matches() {
+        let docs = documents(&["Login side", "Login knap", "Login fejl"]);
+        assert_eq!(most_similar("Login", &docs, 2, 0.0).len(), 2);
+    }
+}
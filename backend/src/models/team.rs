@@ -0,0 +1,175 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{ Deserialize, Serialize };
+use crate::models::game_room::{ RoomMode, RoomSettings, DEFAULT_DECK };
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamRole {
+    // Kan ændre teamet, dets standarder og medlemmer
+    Admin,
+    Member,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TeamMember {
+    pub user_id: String,
+    pub role: TeamRole,
+    pub joined_at: i64,
+}
+
+fn default_deck() -> Vec<i32> {
+    DEFAULT_DECK.to_vec()
+}
+
+// Indstillinger nye rum i teamet starter med
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TeamDefaults {
+    #[serde(default = "default_deck")]
+    pub deck: Vec<i32>,
+    #[serde(default)]
+    pub anonymous_votes: bool,
+    #[serde(default)]
+    pub timer_secs: Option<u32>,
+}
+
+impl Default for TeamDefaults {
+    fn default() -> Self {
+        TeamDefaults {
+            deck: default_deck(),
+            anonymous_votes: false,
+            timer_secs: None,
+        }
+    }
+}
+
+impl TeamDefaults {
+    pub fn room_settings(&self, mode: RoomMode) -> RoomSettings {
+        RoomSettings {
+            mode,
+            anonymous_votes: self.anonymous_votes,
+            deck: self.deck.clone(),
+            timer_secs: self.timer_secs,
+            ..RoomSettings::default()
+        }
+    }
+}
+
+// En afventende invitation. Personen bliver først medlem når de selv accepterer,
+// og kun hvis de er logget ind med en bekræftet konto med samme email.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TeamInvitation {
+    pub id: String,
+    pub email: String,
+    pub role: TeamRole,
+    pub invited_by: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Team {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub name: String,
+    pub members: Vec<TeamMember>,
+    #[serde(default)]
+    pub invitations: Vec<TeamInvitation>,
+    #[serde(default)]
+    pub defaults: TeamDefaults,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Team {
+    pub fn role_of(&self, user_id: &str) -> Option<TeamRole> {
+        self.members
+            .iter()
+            .find(|m| m.user_id == user_id)
+            .map(|m| m.role)
+    }
+
+    pub fn member_ids(&self) -> Vec<String> {
+        self.members
+            .iter()
+            .map(|m| m.user_id.clone())
+            .collect()
+    }
+
+    pub fn admin_count(&self) -> usize {
+        self.members
+            .iter()
+            .filter(|m| m.role == TeamRole::Admin)
+            .count()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTeamDto {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTeamDto {
+    pub name: Option<String>,
+    pub deck: Option<Vec<i32>>,
+    pub anonymous_votes: Option<bool>,
+    // 0 fjerner standardlængden
+    pub timer_secs: Option<u32>,
+}
+
+// Medlemmer inviteres med den email de er registreret med
+#[derive(Debug, Deserialize)]
+pub struct InviteTeamMemberDto {
+    pub email: String,
+    #[serde(default = "default_member_role")]
+    pub role: TeamRole,
+}
+
+fn default_member_role() -> TeamRole {
+    TeamRole::Member
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTeamMemberDto {
+    pub role: TeamRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamMemberResponse {
+    pub user_id: String,
+    pub username: String,
+    pub profile_image: Option<String>,
+    pub role: TeamRole,
+    pub joined_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamResponse {
+    pub id: String,
+    pub name: String,
+    pub members: Vec<TeamMemberResponse>,
+    pub defaults: TeamDefaults,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// En invitation set fra den inviteredes side
+#[derive(Debug, Serialize)]
+pub struct TeamInvitationResponse {
+    pub id: String,
+    pub team_id: String,
+    pub team_name: String,
+    pub role: TeamRole,
+    pub invited_by: String,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamRoomSummary {
+    pub id: String,
+    pub name: String,
+    pub invite_code: String,
+    pub admin_id: String,
+    pub mode: RoomMode,
+    pub updated_at: i64,
+}
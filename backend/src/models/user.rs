@@ -10,6 +10,36 @@ pub struct User {
     pub password_hash: String,
     #[serde(default)]
     pub profile_image: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    // Konti hos eksterne identitetsudbydere (SSO) der er koblet til brugeren
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub identities: Vec<ExternalIdentity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<TwoFactor>,
+    // Systemadministrator, fx til dataudtræk for andre brugere. Sættes direkte i databasen.
+    #[serde(default)]
+    pub is_admin: bool,
+}
+
+// TOTP to-faktor. `enabled` bliver først sat når brugeren har bekræftet en kode fra sin app.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TwoFactor {
+    // Base32-kodet hemmelighed, som i provisioning URI'en
+    pub secret: String,
+    pub enabled: bool,
+    // SHA-256 af de ubrugte gendannelseskoder
+    #[serde(default)]
+    pub recovery_code_hashes: Vec<String>,
+    // Sidst brugte tidsskridt, så den samme kode ikke kan bruges igen
+    #[serde(default)]
+    pub last_used_step: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExternalIdentity {
+    pub issuer: String,
+    pub subject: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -18,26 +48,88 @@ pub struct CreateUserDto {
     pub username: String,
     pub password: String,
     pub profile_image: Option<String>,
+    // Gæstetoken hvis brugeren har deltaget som gæst og vil beholde sin historik
+    #[serde(default)]
+    pub guest_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct LoginDto {
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub guest_token: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
+    // Access tokenets levetid i sekunder
+    pub expires_in: i64,
     pub user: UserResponse,
 }
 
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
 #[derive(Debug, Serialize)]
 pub struct UserResponse {
     pub id: String,
     pub email: String,
     pub username: String,
     pub profile_image: Option<String>,
+    pub email_verified: bool,
+    pub two_factor_enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+    // Bekræfter en kontoændring for SSO-brugere, der ikke kender deres adgangskode
+    ConfirmEmailChange,
+    ConfirmAccountDeletion,
+    ConfirmTwoFactorDisable,
+}
+
+// Engangstoken sendt på mail. Kun en hash af tokenet gemmes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub purpose: TokenPurpose,
+    pub token_hash: String,
+    // Den adresse en bekræftelse gælder for. Er den en anden end brugerens
+    // nuværende email, skiftes emailen når tokenet bruges.
+    #[serde(default)]
+    pub email: Option<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    #[serde(default)]
+    pub used_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequestDto {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetConfirmDto {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailDto {
+    pub token: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -54,3 +146,89 @@ pub struct GuestJoinDto {
     pub username: String,
     pub room_code: String,
 }
+
+// Andet trin af et login med to-faktor. Kun en hash af tokenet gemmes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginChallenge {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub token_hash: String,
+    pub user_id: String,
+    // Gæst der skal flettes ind i kontoen når login er gennemført
+    #[serde(default)]
+    pub guest_id: Option<String>,
+    pub attempts: i32,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorChallengeResponse {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    // otpauth:// URI som klienten viser som QR-kode
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCodeDto {
+    pub code: String,
+}
+
+// Enten en kode fra appen eller en gendannelseskode
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginDto {
+    pub challenge_token: String,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisableTwoFactorDto {
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub confirmation_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileDto {
+    pub username: String,
+}
+
+// SSO-brugere kan sende `confirmation_token` fra bekræftelsesmailen i stedet for en adgangskode
+#[derive(Debug, Deserialize)]
+pub struct ChangeEmailDto {
+    pub new_email: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub confirmation_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordDto {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountDto {
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub confirmation_token: Option<String>,
+}
@@ -0,0 +1,240 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{ decode, decode_header, Algorithm, DecodingKey, Validation };
+use serde::Deserialize;
+use sha2::{ Digest, Sha256 };
+use std::fmt;
+use tokio::sync::OnceCell;
+
+// Indstillinger for single sign-on. SSO er slået fra hvis OIDC_ISSUER ikke er sat.
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    // Offentlige klienter klarer sig med PKCE og har ingen hemmelighed
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    // Tom liste betyder at alle domæner er tilladt
+    pub allowed_domains: Vec<String>,
+    pub scopes: String,
+}
+
+impl OidcConfig {
+    pub fn from_env() -> Option<Self> {
+        let issuer = std::env::var("OIDC_ISSUER").ok().filter(|issuer| !issuer.is_empty())?;
+        let client_id = std::env::var("OIDC_CLIENT_ID").expect("OIDC_CLIENT_ID skal være sat når OIDC_ISSUER er sat");
+        let redirect_uri = std::env::var("OIDC_REDIRECT_URI").unwrap_or_else(|_| {
+            "http://localhost:8080/auth/oidc/callback".to_string()
+        });
+        let allowed_domains = std::env::var("OIDC_ALLOWED_DOMAINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|domain| domain.trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect();
+
+        Some(OidcConfig {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok().filter(|secret| !secret.is_empty()),
+            redirect_uri,
+            allowed_domains,
+            scopes: std::env::var("OIDC_SCOPES").unwrap_or_else(|_| "openid email profile".to_string()),
+        })
+    }
+}
+
+// Når SSO_REQUIRED=true kan man kun logge ind via SSO
+pub fn sso_required() -> bool {
+    std::env::var("SSO_REQUIRED").map(|v| v == "true").unwrap_or(false)
+}
+
+#[derive(Debug)]
+pub struct OidcError(pub String);
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OIDC fejl: {}", self.0)
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(e: reqwest::Error) -> Self {
+        OidcError(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenEndpointResponse {
+    id_token: Option<String>,
+}
+
+// De felter fra ID tokenet vi bruger
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdentityClaims {
+    pub iss: String,
+    pub sub: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub preferred_username: Option<String>,
+    #[serde(default)]
+    nonce: Option<String>,
+}
+
+// Klient til én identitetsudbyder. Discovery-dokumentet hentes første gang det skal bruges.
+pub struct OidcClient {
+    pub config: OidcConfig,
+    http: reqwest::Client,
+    discovery: OnceCell<Discovery>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        OidcClient {
+            config,
+            http: reqwest::Client::new(),
+            discovery: OnceCell::new(),
+        }
+    }
+
+    async fn discovery(&self) -> Result<&Discovery, OidcError> {
+        self.discovery.get_or_try_init(|| async {
+            let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+            let discovery: Discovery = self.http
+                .get(&url)
+                .send().await?
+                .error_for_status()?
+                .json().await?;
+
+            if discovery.issuer.trim_end_matches('/') != self.config.issuer {
+                return Err(OidcError(format!("Uventet issuer i discovery: {}", discovery.issuer)));
+            }
+            Ok(discovery)
+        }).await
+    }
+
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str
+    ) -> Result<String, OidcError> {
+        let discovery = self.discovery().await?;
+        let url = reqwest::Url::parse_with_params(
+            &discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("scope", self.config.scopes.as_str()),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", pkce_challenge(code_verifier).as_str()),
+                ("code_challenge_method", "S256"),
+            ]
+        ).map_err(|e| OidcError(e.to_string()))?;
+        Ok(url.to_string())
+    }
+
+    // Veksler koden til et ID token og validerer signatur, issuer, audience, udløb og nonce
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str
+    ) -> Result<IdentityClaims, OidcError> {
+        let discovery = self.discovery().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response: TokenEndpointResponse = self.http
+            .post(&discovery.token_endpoint)
+            .form(&form)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+        let id_token = response.id_token.ok_or_else(|| OidcError("Intet id_token i svaret".to_string()))?;
+
+        let claims = self.validate_id_token(discovery, &id_token).await?;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError("Nonce passer ikke".to_string()));
+        }
+        Ok(claims)
+    }
+
+    async fn validate_id_token(
+        &self,
+        discovery: &Discovery,
+        id_token: &str
+    ) -> Result<IdentityClaims, OidcError> {
+        let header = decode_header(id_token).map_err(|e| OidcError(e.to_string()))?;
+        // Kun asymmetriske nøgler; en HMAC-signatur ville kunne laves af alle med klientens id
+        if !matches!(
+            header.alg,
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::ES256 | Algorithm::ES384 |
+            Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 | Algorithm::EdDSA
+        ) {
+            return Err(OidcError(format!("Algoritmen {:?} er ikke tilladt", header.alg)));
+        }
+
+        // Nøglerne hentes hver gang, så udbyderens nøgleskift slår igennem med det samme
+        let jwks: JwkSet = self.http
+            .get(&discovery.jwks_uri)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }.ok_or_else(|| OidcError("Ukendt signeringsnøgle".to_string()))?;
+        let key = DecodingKey::from_jwk(jwk).map_err(|e| OidcError(e.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&discovery.issuer]);
+
+        decode::<IdentityClaims>(id_token, &key, &validation)
+            .map(|token_data| token_data.claims)
+            .map_err(|e| OidcError(e.to_string()))
+    }
+
+    pub fn email_domain_allowed(&self, email: &str) -> bool {
+        if self.config.allowed_domains.is_empty() {
+            return true;
+        }
+        email
+            .rsplit_once('@')
+            .map(|(_, domain)| self.config.allowed_domains.contains(&domain.to_lowercase()))
+            .unwrap_or(false)
+    }
+}
+
+// S256 code challenge til PKCE (RFC 7636)
+fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
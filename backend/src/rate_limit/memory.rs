@@ -0,0 +1,113 @@
+use futures_util::future::{ ready, BoxFuture };
+use std::collections::HashMap;
+use std::sync::Mutex;
+use super::{ now_secs, Counter, LimiterError, LimiterStore };
+
+// Udløbne nøgler ryddes væk når lageret når denne størrelse
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// Tællere i processens hukommelse. Nulstilles ved genstart og deles ikke mellem instanser.
+pub struct MemoryStore {
+    counters: Mutex<HashMap<String, Counter>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore { counters: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LimiterStore for MemoryStore {
+    fn incr(&self, key: &str, ttl_secs: i64) -> BoxFuture<'_, Result<Counter, LimiterError>> {
+        let now = now_secs();
+        let mut counters = self.counters.lock().unwrap();
+        if counters.len() >= PRUNE_THRESHOLD {
+            counters.retain(|_, counter| counter.expires_at > now);
+        }
+
+        let counter = counters
+            .entry(key.to_string())
+            .and_modify(|counter| {
+                if counter.expires_at <= now {
+                    *counter = Counter { count: 0, expires_at: now + ttl_secs };
+                }
+            })
+            .or_insert(Counter { count: 0, expires_at: now + ttl_secs });
+        counter.count += 1;
+        Box::pin(ready(Ok(*counter)))
+    }
+
+    fn get(&self, key: &str) -> BoxFuture<'_, Result<Option<Counter>, LimiterError>> {
+        let now = now_secs();
+        let counter = self.counters
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|counter| counter.expires_at > now)
+            .copied();
+        Box::pin(ready(Ok(counter)))
+    }
+
+    fn set(&self, key: &str, count: u64, ttl_secs: i64) -> BoxFuture<'_, Result<(), LimiterError>> {
+        let counter = Counter { count, expires_at: now_secs() + ttl_secs };
+        self.counters.lock().unwrap().insert(key.to_string(), counter);
+        Box::pin(ready(Ok(())))
+    }
+
+    fn delete(&self, key: &str) -> BoxFuture<'_, Result<(), LimiterError>> {
+        self.counters.lock().unwrap().remove(key);
+        Box::pin(ready(Ok(())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn incr_counts_within_window() {
+        let store = MemoryStore::new();
+        assert_eq!(store.incr("key", 60).await.unwrap().count, 1);
+        assert_eq!(store.incr("key", 60).await.unwrap().count, 2);
+        assert_eq!(store.incr("other", 60).await.unwrap().count, 1);
+    }
+
+    #[tokio::test]
+    async fn incr_keeps_expiry_of_first_hit() {
+        let store = MemoryStore::new();
+        let first = store.incr("key", 60).await.unwrap();
+        let second = store.incr("key", 3600).await.unwrap();
+        assert_eq!(second.expires_at, first.expires_at);
+    }
+
+    #[tokio::test]
+    async fn expired_counter_starts_over() {
+        let store = MemoryStore::new();
+        store.incr("key", 0).await.unwrap();
+        assert_eq!(store.incr("key", 60).await.unwrap().count, 1);
+    }
+
+    #[tokio::test]
+    async fn get_ignores_expired_counters() {
+        let store = MemoryStore::new();
+        store.set("live", 3, 60).await.unwrap();
+        store.set("expired", 3, 0).await.unwrap();
+        assert_eq!(store.get("live").await.unwrap().map(|c| c.count), Some(3));
+        assert!(store.get("expired").await.unwrap().is_none());
+        assert!(store.get("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn delete_removes_counter() {
+        let store = MemoryStore::new();
+        store.incr("key", 60).await.unwrap();
+        store.delete("key").await.unwrap();
+        assert!(store.get("key").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,322 @@
+use actix_web::{ error::ResponseError, http::{ header, StatusCode }, HttpRequest, HttpResponse };
+use futures_util::future::BoxFuture;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+mod memory;
+
+pub use memory::MemoryStore;
+
+// En tæller i et tidsvindue. `expires_at` er unix-tid i sekunder.
+#[derive(Debug, Clone, Copy)]
+pub struct Counter {
+    pub count: u64,
+    pub expires_at: i64,
+}
+
+#[derive(Debug)]
+pub struct LimiterError(pub String);
+
+impl fmt::Display for LimiterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rate limit fejl: {}", self.0)
+    }
+}
+
+impl std::error::Error for LimiterError {}
+
+// Lager for tællere. Vælges i `from_env` ud fra RATE_LIMIT_STORE.
+// Et delt lager (fx Redis) er nødvendigt hvis backenden kører i flere instanser.
+pub trait LimiterStore: Send + Sync {
+    // Tæller nøglen op. En ny eller udløbet nøgle starter forfra og udløber om `ttl_secs`.
+    fn incr(&self, key: &str, ttl_secs: i64) -> BoxFuture<'_, Result<Counter, LimiterError>>;
+    fn get(&self, key: &str) -> BoxFuture<'_, Result<Option<Counter>, LimiterError>>;
+    fn set(&self, key: &str, count: u64, ttl_secs: i64) -> BoxFuture<'_, Result<(), LimiterError>>;
+    fn delete(&self, key: &str) -> BoxFuture<'_, Result<(), LimiterError>>;
+}
+
+pub fn from_env() -> Arc<dyn LimiterStore> {
+    match std::env::var("RATE_LIMIT_STORE").unwrap_or_default().as_str() {
+        "" | "memory" => Arc::new(MemoryStore::new()),
+        other => {
+            println!("Ukendt RATE_LIMIT_STORE '{}', bruger hukommelsen", other);
+            Arc::new(MemoryStore::new())
+        }
+    }
+}
+
+// Fejlede logins før en konto eller IP bliver låst
+const LOGIN_FAILURES_PER_ACCOUNT: u64 = 5;
+const LOGIN_FAILURES_PER_IP: u64 = 20;
+// Fejlede logins tælles i et fast vindue på et døgn fra den første fejl
+const LOGIN_FAILURE_WINDOW_SECS: i64 = 24 * 3600;
+// Låsen fordobles for hver fejl ud over grænsen, op til en time
+const LOCKOUT_BASE_SECS: i64 = 30;
+const LOCKOUT_MAX_SECS: i64 = 3600;
+// Samlet antal loginforsøg fra én IP, uanset konto
+const LOGIN_ATTEMPTS_PER_IP: u64 = 30;
+const LOGIN_ATTEMPT_WINDOW_SECS: i64 = 300;
+
+// Kvoter pr. IP for handlinger der opretter dokumenter
+#[derive(Debug, Clone, Copy)]
+pub enum Quota {
+    Register,
+    GuestCreate,
+    RoomCreate,
+    PasswordReset,
+}
+
+impl Quota {
+    fn key(&self) -> &'static str {
+        match self {
+            Quota::Register => "register",
+            Quota::GuestCreate => "guest",
+            Quota::RoomCreate => "room",
+            Quota::PasswordReset => "reset",
+        }
+    }
+
+    // (antal, vindue i sekunder)
+    fn limit(&self) -> (u64, i64) {
+        match self {
+            Quota::Register => (5, 3600),
+            Quota::GuestCreate => (30, 3600),
+            Quota::RoomCreate => (20, 3600),
+            Quota::PasswordReset => (5, 3600),
+        }
+    }
+}
+
+// Svaret når en grænse er nået. Klienten kan prøve igen om `retry_after` sekunder.
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_after: i64,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "For mange forsøg. Prøv igen om {} sekunder.", self.retry_after)
+    }
+}
+
+impl ResponseError for RateLimited {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, self.retry_after.to_string()))
+            .json(
+                serde_json::json!({
+                    "message": self.to_string(),
+                    "retry_after": self.retry_after
+                })
+            )
+    }
+}
+
+// Regler for login og oprettelser oven på et `LimiterStore`.
+// Fejl i lageret logges og lader forespørgslen gå igennem, så et nedbrud ikke lukker for login.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn LimiterStore>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn LimiterStore>) -> Self {
+        RateLimiter { store }
+    }
+
+    // Afviser login hvis IP'en eller kontoen er låst, eller IP'en har sendt for mange forsøg
+    pub async fn check_login(&self, ip: &str, email: &str) -> Result<(), RateLimited> {
+        for key in [lock_key("ip", ip), lock_key("account", &normalize_email(email))] {
+            if let Some(lock) = self.get(&key).await {
+                return Err(RateLimited { retry_after: seconds_until(lock.expires_at) });
+            }
+        }
+
+        let attempts_key = format!("login-attempts:{}", ip);
+        if let Some(attempts) = self.incr(&attempts_key, LOGIN_ATTEMPT_WINDOW_SECS).await {
+            if attempts.count > LOGIN_ATTEMPTS_PER_IP {
+                return Err(RateLimited { retry_after: seconds_until(attempts.expires_at) });
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn login_failed(&self, ip: &str, email: &str) {
+        self.record_failure("account", &normalize_email(email), LOGIN_FAILURES_PER_ACCOUNT).await;
+        self.record_failure("ip", ip, LOGIN_FAILURES_PER_IP).await;
+    }
+
+    // Et gyldigt login nulstiller kontoens fejl, men ikke IP'ens
+    pub async fn login_succeeded(&self, email: &str) {
+        let email = normalize_email(email);
+        for key in [failure_key("account", &email), lock_key("account", &email)] {
+            if let Err(e) = self.store.delete(&key).await {
+                println!("{}", e);
+            }
+        }
+    }
+
+    pub async fn check_quota(&self, quota: Quota, ip: &str) -> Result<(), RateLimited> {
+        let (limit, window_secs) = quota.limit();
+        let key = format!("quota:{}:{}", quota.key(), ip);
+        match self.incr(&key, window_secs).await {
+            Some(counter) if counter.count > limit => {
+                println!("Kvote '{}' overskredet for {}", quota.key(), ip);
+                Err(RateLimited { retry_after: seconds_until(counter.expires_at) })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn record_failure(&self, scope: &str, id: &str, threshold: u64) {
+        let Some(failures) = self.incr(&failure_key(scope, id), LOGIN_FAILURE_WINDOW_SECS).await else {
+            return;
+        };
+        if failures.count < threshold {
+            return;
+        }
+
+        let doublings = (failures.count - threshold).min(16) as u32;
+        let lock_secs = (LOCKOUT_BASE_SECS << doublings).min(LOCKOUT_MAX_SECS);
+        println!("Login låst for {} {} i {} sekunder", scope, id, lock_secs);
+        if let Err(e) = self.store.set(&lock_key(scope, id), failures.count, lock_secs).await {
+            println!("{}", e);
+        }
+    }
+
+    async fn incr(&self, key: &str, ttl_secs: i64) -> Option<Counter> {
+        self.store
+            .incr(key, ttl_secs).await
+            .map_err(|e| println!("{}", e))
+            .ok()
+    }
+
+    async fn get(&self, key: &str) -> Option<Counter> {
+        self.store
+            .get(key).await
+            .map_err(|e| println!("{}", e))
+            .ok()
+            .flatten()
+    }
+}
+
+fn failure_key(scope: &str, id: &str) -> String {
+    format!("login-failures:{}:{}", scope, id)
+}
+
+fn lock_key(scope: &str, id: &str) -> String {
+    format!("login-lock:{}:{}", scope, id)
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn seconds_until(expires_at: i64) -> i64 {
+    (expires_at - now_secs()).max(1)
+}
+
+pub(crate) fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+// Klientens IP. Proxy-headere kan forfalskes og bruges kun når TRUST_PROXY_HEADERS=true.
+pub fn client_ip(req: &HttpRequest) -> String {
+    let trust_proxy = std::env::var("TRUST_PROXY_HEADERS").map(|v| v == "true").unwrap_or(false);
+    let ip = if trust_proxy {
+        req.connection_info().realip_remote_addr().map(|addr| addr.to_string())
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    };
+    ip.unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(Arc::new(MemoryStore::new()))
+    }
+
+    #[tokio::test]
+    async fn account_is_locked_at_threshold() {
+        let limiter = limiter();
+        for _ in 1..LOGIN_FAILURES_PER_ACCOUNT {
+            limiter.login_failed("10.0.0.1", "a@example.com").await;
+        }
+        assert!(limiter.check_login("10.0.0.2", "a@example.com").await.is_ok());
+
+        limiter.login_failed("10.0.0.1", "a@example.com").await;
+        let locked = limiter.check_login("10.0.0.2", "a@example.com").await.unwrap_err();
+        assert!(locked.retry_after > 0 && locked.retry_after <= LOCKOUT_BASE_SECS);
+    }
+
+    #[tokio::test]
+    async fn lock_doubles_for_each_failure_over_threshold() {
+        let limiter = limiter();
+        for _ in 0..LOGIN_FAILURES_PER_ACCOUNT + 2 {
+            limiter.login_failed("10.0.0.1", "a@example.com").await;
+        }
+        let locked = limiter.check_login("10.0.0.2", "a@example.com").await.unwrap_err();
+        assert!(locked.retry_after > LOCKOUT_BASE_SECS * 2);
+        assert!(locked.retry_after <= LOCKOUT_BASE_SECS * 4);
+    }
+
+    #[tokio::test]
+    async fn account_lock_ignores_email_case_and_whitespace() {
+        let limiter = limiter();
+        for _ in 0..LOGIN_FAILURES_PER_ACCOUNT {
+            limiter.login_failed("10.0.0.1", " A@Example.com").await;
+        }
+        assert!(limiter.check_login("10.0.0.2", "a@example.com").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn successful_login_clears_account_lock() {
+        let limiter = limiter();
+        for _ in 0..LOGIN_FAILURES_PER_ACCOUNT {
+            limiter.login_failed("10.0.0.1", "a@example.com").await;
+        }
+        limiter.login_succeeded("a@example.com").await;
+        assert!(limiter.check_login("10.0.0.1", "a@example.com").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn ip_is_locked_across_accounts() {
+        let limiter = limiter();
+        for i in 0..LOGIN_FAILURES_PER_IP {
+            limiter.login_failed("10.0.0.1", &format!("user{}@example.com", i)).await;
+        }
+        assert!(limiter.check_login("10.0.0.1", "new@example.com").await.is_err());
+        assert!(limiter.check_login("10.0.0.2", "new@example.com").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn login_attempts_are_limited_per_ip() {
+        let limiter = limiter();
+        for _ in 0..LOGIN_ATTEMPTS_PER_IP {
+            assert!(limiter.check_login("10.0.0.1", "a@example.com").await.is_ok());
+        }
+        assert!(limiter.check_login("10.0.0.1", "a@example.com").await.is_err());
+        assert!(limiter.check_login("10.0.0.2", "a@example.com").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn quota_is_counted_per_ip_and_action() {
+        let limiter = limiter();
+        let (limit, window_secs) = Quota::Register.limit();
+        for _ in 0..limit {
+            assert!(limiter.check_quota(Quota::Register, "10.0.0.1").await.is_ok());
+        }
+        let limited = limiter.check_quota(Quota::Register, "10.0.0.1").await.unwrap_err();
+        assert!(limited.retry_after > 0 && limited.retry_after <= window_secs);
+        assert!(limiter.check_quota(Quota::Register, "10.0.0.2").await.is_ok());
+        assert!(limiter.check_quota(Quota::RoomCreate, "10.0.0.1").await.is_ok());
+    }
+}
@@ -1,7 +1,8 @@
 use actix::{ Actor, StreamHandler, Handler, Message, Context, Running, Addr, Recipient };
 use actix_web_actors::ws;
 use serde::{ Deserialize, Serialize };
-use std::collections::HashMap;
+use std::collections::{ BTreeMap, HashMap, VecDeque };
+use std::time::{ Duration, Instant };
 use actix::prelude::*;
 use mongodb::Database;
 use crate::handlers::game_room::{
@@ -9,8 +10,37 @@ use crate::handlers::game_room::{
     handle_vote,
     handle_end_voting,
     handle_save_final_score,
+    handle_round_timeout,
+    current_round_voters,
+    save_confidence_result,
+    handle_dot_vote,
+    room_ranking,
+    reveal_async_round,
+    due_async_rooms,
+    RoundSummary,
+};
+use crate::handlers::reference::new_story_content;
+use crate::handlers::chat::{
+    normalize_chat_text,
+    save_chat_message,
+    load_chat_history,
+    delete_chat_message,
+    CHAT_PAGE_SIZE,
+};
+use crate::models::game_room::{
+    Story,
+    CompletedStory,
+    RoomSettings,
+    RoomMode,
+    Vote,
+    ConfidenceVote,
+    ConfidenceResult,
+    MIN_CONFIDENCE,
+    MAX_CONFIDENCE,
+    MIN_TIMER_SECS,
+    MAX_TIMER_SECS,
+    anonymize_votes,
 };
-use crate::models::game_room::{ Story, CompletedStory };
 use serde_json::json;
 use lazy_static::lazy_static;
 
@@ -29,6 +59,8 @@ pub struct Connect {
     pub user_id: String,
     pub username: String,
     pub profile_image: Option<String>,
+    // Rummets indstillinger hentet før forbindelsen blev oprettet, så de gælder fra første besked
+    pub settings: RoomSettings,
 }
 
 #[derive(Message)]
@@ -47,12 +79,152 @@ pub struct WebSocketMessage {
     pub user_id: String,
 }
 
+// Hvad der skal ske med runden når nedtællingen udløber
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TimerExpiryPolicy {
+    #[default]
+    None,
+    Reveal,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+pub enum TimerAction {
+    // Uden varighed bruges rummets standardlængde
+    Start {
+        duration_secs: Option<u64>,
+        on_expire: TimerExpiryPolicy,
+    },
+    Pause,
+    Resume,
+    Extend {
+        seconds: u64,
+    },
+    Cancel,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct TimerCommand {
+    pub room_id: String,
+    pub action: TimerAction,
+}
+
+// Sendes af sessionen ved hver indkommende besked, så serveren kan spore aktivitet
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Activity {
+    pub room_id: String,
+    pub user_id: String,
+}
+
+// Emoji-reaktion. Uden `to_user_id` gælder den hele rummet.
+// Feltnavnene er camelCase for at matche frontendens format.
+#[derive(Message, Debug, Serialize, Deserialize, Clone)]
+#[rtype(result = "()")]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiReaction {
+    pub emoji: String,
+    #[serde(default)]
+    pub from_user_id: String,
+    #[serde(default, alias = "to_user_id")]
+    pub to_user_id: Option<String>,
+    #[serde(default)]
+    pub timestamp: i64,
+    #[serde(skip)]
+    pub room_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ChatPost {
+    pub room_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub profile_image: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum ConfidenceAction {
+    Start {
+        completed_story_id: String,
+    },
+    Vote {
+        user_id: String,
+        value: i32,
+    },
+    End,
+}
+
+// Styrer "fist of five" tillidsrunden efter en historie har fået sin endelige score
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ConfidenceCommand {
+    pub room_id: String,
+    pub action: ConfidenceAction,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RoomSettingsChanged {
+    pub room_id: String,
+    pub settings: RoomSettings,
+}
+
+// En asynkron historie er afsløret af planlæggeren eller fordi alle har stemt
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RoundRevealed {
+    pub room_id: String,
+    pub story: Story,
+    pub summary: RoundSummary,
+}
+
+// Afslør rummets asynkrone runde hvis fristen er udløbet eller alle har stemt
+pub async fn reveal_if_ready(db: &Database, room_id: &str, addr: &Addr<GameServer>) {
+    match reveal_async_round(db, room_id).await {
+        Ok(Some((story, summary))) => {
+            addr.do_send(RoundRevealed {
+                room_id: room_id.to_string(),
+                story,
+                summary,
+            });
+        }
+        Ok(None) => {}
+        Err(e) => println!("Fejl ved afsløring af asynkron runde i rum {}: {:?}", room_id, e),
+    }
+}
+
+// En bruger har skiftet navn; alle rum hvor brugeren er forbundet får besked
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ProfileChanged {
+    pub user_id: String,
+    pub username: String,
+    pub profile_image: Option<String>,
+}
+
+// Facilitator forespørgsel om hvem der mangler at stemme.
+// Med `notify` sættes får de manglende deltagere også en påmindelse.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Nudge {
+    pub room_id: String,
+    pub requested_by: String,
+    pub notify: bool,
+}
+
 // WebSocket session actor
 pub struct WebSocketSession {
     pub room_id: String,
     pub user_id: String,
     pub username: String,
     pub profile_image: Option<String>,
+    pub is_admin: bool,
+    // Sendes med til game serveren når sessionen tilmeldes
+    pub settings: RoomSettings,
     pub addr: Addr<GameServer>,
     pub db: Database,
 }
@@ -63,6 +235,7 @@ impl WebSocketSession {
         user_id: String,
         username: String,
         profile_image: Option<String>,
+        is_admin: bool,
         addr: Addr<GameServer>,
         db: Database
     ) -> Self {
@@ -71,10 +244,89 @@ impl WebSocketSession {
             user_id,
             username,
             profile_image,
+            is_admin,
+            settings: RoomSettings::default(),
             addr,
             db,
         }
     }
+
+    // Tjek i baggrunden om en asynkron runde kan afsløres efter en stemme
+    fn reveal_if_ready(&self) {
+        let db = self.db.clone();
+        let room_id = self.room_id.clone();
+        let addr = self.addr.clone();
+        actix::spawn(async move {
+            reveal_if_ready(&db, &room_id, &addr).await;
+        });
+    }
+
+    // Send en fejlbesked kun til denne klient
+    fn send_error(&self, ctx: &mut ws::WebsocketContext<Self>, message: &str) {
+        let error_msg = WebSocketMessage {
+            message_type: "error".to_string(),
+            content: json!({ "message": message }),
+            room_id: self.room_id.clone(),
+            user_id: "system".to_string(),
+        };
+        if let Ok(text) = serde_json::to_string(&error_msg) {
+            ctx.text(text);
+        }
+    }
+
+    // Hent en side chathistorik og send den kun til denne klient
+    fn send_chat_history(&self, ctx: &mut ws::WebsocketContext<Self>, before: Option<String>) {
+        let db = self.db.clone();
+        let room_id = self.room_id.clone();
+        ctx.spawn(
+            (async move {
+                load_chat_history(&db, &room_id, before.as_deref(), CHAT_PAGE_SIZE).await.map_err(
+                    |e| e.to_string()
+                )
+            })
+                .into_actor(self)
+                .map(|result, act, ctx| {
+                    match result {
+                        Ok(history) => {
+                            let history_msg = WebSocketMessage {
+                                message_type: "chat_history".to_string(),
+                                content: json!(history),
+                                room_id: act.room_id.clone(),
+                                user_id: "system".to_string(),
+                            };
+                            if let Ok(text) = serde_json::to_string(&history_msg) {
+                                ctx.text(text);
+                            }
+                        }
+                        Err(e) => {
+                            println!("Fejl ved hentning af chathistorik: {}", e);
+                            act.send_error(ctx, "Kunne ikke hente chathistorik");
+                        }
+                    }
+                })
+        );
+    }
+
+    fn parse_timer_action(message: &WebSocketMessage) -> Option<TimerAction> {
+        match message.message_type.as_str() {
+            "timer_start" => {
+                let duration_secs = message.content.get("duration_secs").and_then(|v| v.as_u64());
+                let on_expire = message.content
+                    .get("on_expire")
+                    .and_then(|v| serde_json::from_value::<TimerExpiryPolicy>(v.clone()).ok())
+                    .unwrap_or_default();
+                Some(TimerAction::Start { duration_secs, on_expire })
+            }
+            "timer_pause" => Some(TimerAction::Pause),
+            "timer_resume" => Some(TimerAction::Resume),
+            "timer_extend" => {
+                let seconds = message.content.get("seconds")?.as_u64()?;
+                Some(TimerAction::Extend { seconds })
+            }
+            "timer_cancel" => Some(TimerAction::Cancel),
+            _ => None,
+        }
+    }
 }
 
 impl Actor for WebSocketSession {
@@ -90,7 +342,10 @@ impl Actor for WebSocketSession {
             user_id: self.user_id.clone(),
             username: self.username.clone(),
             profile_image: self.profile_image.clone(),
+            settings: self.settings.clone(),
         });
+        // Den nyeste chathistorik sendes med det samme
+        self.send_chat_history(ctx, None);
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
@@ -110,6 +365,10 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketSession
         match msg {
             Ok(ws::Message::Text(text)) => {
                 println!("Tekst besked modtaget: {}", text);
+                self.addr.do_send(Activity {
+                    room_id: self.room_id.clone(),
+                    user_id: self.user_id.clone(),
+                });
                 // Håndter indkommende beskeder
                 if let Ok(message) = serde_json::from_str::<WebSocketMessage>(&text) {
                     println!("Besked parset succesfuldt");
@@ -139,37 +398,44 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketSession
                                         description: description.map(|s| s.to_string()),
                                         votes: Vec::new(),
                                         final_score: None,
+                                        voting_deadline: None,
+                                        started_at: None,
+                                        rounds: 0,
+                                        anonymous: false,
                                     };
 
                                     // Opdater databasen
                                     let db = self.db.clone();
                                     let room_id = self.room_id.clone();
-                                    let story_clone = story.clone();
-
-                                    // Opret en ny besked med den opdaterede historie
-                                    let updated_message = WebSocketMessage {
-                                        message_type: "new_story".to_string(),
-                                        content: serde_json
-                                            ::to_value(story)
-                                            .unwrap_or(message.content.clone()),
-                                        room_id: message.room_id.clone(),
-                                        user_id: message.user_id.clone(),
-                                    };
 
-                                    actix::spawn(async move {
-                                        if
-                                            let Err(e) = handle_new_story(
-                                                &db,
-                                                &room_id,
-                                                story_clone
-                                            ).await
-                                        {
-                                            println!("Fejl ved opdatering af database: {:?}", e);
-                                        }
-                                    });
-
-                                    // Send den opdaterede besked videre til game server
-                                    self.addr.do_send(updated_message);
+                                    // Historien sendes først videre når den er gemt, så
+                                    // klienterne får en eventuel frist sat af serveren med
+                                    ctx.spawn(
+                                        (async move {
+                                            let story = handle_new_story(&db, &room_id, story).await.map_err(
+                                                |e| e.to_string()
+                                            )?;
+                                            // Lignende tidligere historier sendes med som forslag
+                                            Ok::<_, String>(new_story_content(&db, &room_id, &story).await)
+                                        })
+                                            .into_actor(self)
+                                            .map(move |result, act, ctx| {
+                                                match result {
+                                                    Ok(content) => {
+                                                        act.addr.do_send(WebSocketMessage {
+                                                            message_type: "new_story".to_string(),
+                                                            content,
+                                                            room_id: message.room_id.clone(),
+                                                            user_id: message.user_id.clone(),
+                                                        });
+                                                    }
+                                                    Err(e) => {
+                                                        println!("Fejl ved opdatering af database: {:?}", e);
+                                                        act.send_error(ctx, &e);
+                                                    }
+                                                }
+                                            })
+                                    );
                                     return; // Stop yderligere behandling af beskeden
                                 } else {
                                     println!(
@@ -182,6 +448,43 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketSession
                         }
                         "vote" => {
                             println!("Forsøger at parse vote content: {:?}", message.content);
+                            // Stemme med en værdi pr. dimension. Den samlede værdi beregnes
+                            // af serveren, så beskeden sendes først videre når stemmen er gemt.
+                            if
+                                let (Some(story_id), Some(values)) = (
+                                    message.content.get("story_id").and_then(|v| v.as_str()),
+                                    message.content.get("values").and_then(|v| {
+                                        serde_json::from_value::<BTreeMap<String, i32>>(v.clone()).ok()
+                                    }),
+                                )
+                            {
+                                let db = self.db.clone();
+                                let room_id = self.room_id.clone();
+                                let user_id = self.user_id.clone();
+                                let story_id = story_id.to_string();
+                                let mut message = message;
+
+                                ctx.spawn(
+                                    (async move {
+                                        handle_vote(&db, &room_id, &user_id, &story_id, 0, Some(values)).await.map_err(
+                                            |e| e.to_string()
+                                        )
+                                    })
+                                        .into_actor(self)
+                                        .map(move |result, act, ctx| {
+                                            match result {
+                                                Ok(vote) => {
+                                                    message.content["value"] = json!(vote.value);
+                                                    message.content["values"] = json!(vote.dimension_values);
+                                                    act.addr.do_send(message);
+                                                    act.reveal_if_ready();
+                                                }
+                                                Err(e) => act.send_error(ctx, &e),
+                                            }
+                                        })
+                                );
+                                return;
+                            }
                             if
                                 let Ok(vote_content) = serde_json::from_value::<serde_json::Value>(
                                     message.content.clone()
@@ -199,22 +502,24 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketSession
                                     let story_id = story_id.to_string();
                                     let value = value as i32;
 
-                                    actix::spawn(async move {
-                                        if
-                                            let Err(e) = handle_vote(
-                                                &db,
-                                                &room_id,
-                                                &user_id,
-                                                &story_id,
-                                                value
-                                            ).await
-                                        {
-                                            println!("Fejl ved gemning af vote: {:?}", e);
-                                        }
-                                    });
-
-                                    // Send beskeden videre til game server
-                                    self.addr.do_send(message);
+                                    // Beskeden sendes først videre til game server når stemmen er godkendt
+                                    ctx.spawn(
+                                        (async move {
+                                            handle_vote(&db, &room_id, &user_id, &story_id, value, None).await.map_err(
+                                                |e| e.to_string()
+                                            )
+                                        })
+                                            .into_actor(self)
+                                            .map(move |result, act, ctx| {
+                                                match result {
+                                                    Ok(_) => {
+                                                        act.addr.do_send(message);
+                                                        act.reveal_if_ready();
+                                                    }
+                                                    Err(e) => act.send_error(ctx, &e),
+                                                }
+                                            })
+                                    );
                                     return;
                                 }
                             }
@@ -236,26 +541,215 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketSession
                                     let room_id = self.room_id.clone();
                                     let story_id = story_id.to_string();
                                     let final_score = final_score as i32;
+                                    let addr = self.addr.clone();
+                                    let mut message = message;
 
                                     actix::spawn(async move {
-                                        if
-                                            let Err(e) = handle_end_voting(
+                                        match
+                                            handle_end_voting(
                                                 &db,
                                                 &room_id,
                                                 &story_id,
                                                 final_score
                                             ).await
                                         {
-                                            println!("Fejl ved afslutning af voting: {:?}", e);
+                                            Ok(summary) => {
+                                                // Fordelingen gør det muligt at vise resultatet
+                                                // uden at kende de enkelte stemmer
+                                                message.content["final_score"] = json!(summary.final_score);
+                                                message.content["wsjf"] = json!(summary.wsjf);
+                                                message.content["distribution"] = json!(summary.distribution);
+                                                message.content["dimension_stats"] = json!(
+                                                    summary.dimension_stats
+                                                );
+                                            }
+                                            Err(e) => println!("Fejl ved afslutning af voting: {:?}", e),
                                         }
-                                    });
 
-                                    // Send beskeden videre til game server
-                                    self.addr.do_send(message);
+                                        // Send beskeden videre til game server
+                                        addr.do_send(message);
+                                    });
                                     return;
                                 }
                             }
                         }
+                        "timer_start" | "timer_pause" | "timer_resume" | "timer_extend" | "timer_cancel" => {
+                            if !self.is_admin {
+                                self.send_error(ctx, "Kun admin kan styre timeren");
+                                return;
+                            }
+                            match Self::parse_timer_action(&message) {
+                                Some(action) =>
+                                    self.addr.do_send(TimerCommand {
+                                        room_id: self.room_id.clone(),
+                                        action,
+                                    }),
+                                None => self.send_error(ctx, "Ugyldig timer kommando"),
+                            }
+                            // Timer kommandoer sendes aldrig direkte videre til rummet
+                            return;
+                        }
+                        "emoji_reaction" => {
+                            match serde_json::from_value::<EmojiReaction>(message.content.clone()) {
+                                Ok(mut reaction) => {
+                                    // Afsender og tidspunkt bestemmes af serveren
+                                    reaction.from_user_id = self.user_id.clone();
+                                    reaction.room_id = self.room_id.clone();
+                                    reaction.timestamp = std::time::SystemTime
+                                        ::now()
+                                        .duration_since(std::time::UNIX_EPOCH)
+                                        .map(|d| d.as_millis() as i64)
+                                        .unwrap_or(0);
+                                    self.addr.do_send(reaction);
+                                }
+                                Err(_) => self.send_error(ctx, "Ugyldig emoji-reaktion"),
+                            }
+                            // Reaktioner valideres og rate-limites af game serveren
+                            return;
+                        }
+                        "chat_message" => {
+                            let text = message.content
+                                .get("text")
+                                .and_then(|v| v.as_str())
+                                .unwrap_or_default();
+                            match normalize_chat_text(text) {
+                                Ok(text) =>
+                                    self.addr.do_send(ChatPost {
+                                        room_id: self.room_id.clone(),
+                                        user_id: self.user_id.clone(),
+                                        username: self.username.clone(),
+                                        profile_image: self.profile_image.clone(),
+                                        text,
+                                    }),
+                                Err(e) => self.send_error(ctx, e),
+                            }
+                            return;
+                        }
+                        "chat_history" => {
+                            let before = message.content
+                                .get("before")
+                                .and_then(|v| v.as_str())
+                                .map(|s| s.to_string());
+                            self.send_chat_history(ctx, before);
+                            return;
+                        }
+                        "chat_delete" => {
+                            if !self.is_admin {
+                                self.send_error(ctx, "Kun admin kan slette beskeder");
+                                return;
+                            }
+                            let Some(message_id) = message.content
+                                .get("message_id")
+                                .and_then(|v| v.as_str())
+                                .map(|s| s.to_string()) else {
+                                self.send_error(ctx, "Manglende message_id");
+                                return;
+                            };
+                            let db = self.db.clone();
+                            let room_id = self.room_id.clone();
+                            let addr = self.addr.clone();
+                            actix::spawn(async move {
+                                match delete_chat_message(&db, &room_id, &message_id).await {
+                                    Ok(true) =>
+                                        addr.do_send(WebSocketMessage {
+                                            message_type: "chat_deleted".to_string(),
+                                            content: json!({ "message_id": message_id }),
+                                            room_id,
+                                            user_id: "system".to_string(),
+                                        }),
+                                    Ok(false) => println!("Chatbesked {} findes ikke", message_id),
+                                    Err(e) => println!("Fejl ved sletning af chatbesked: {:?}", e),
+                                }
+                            });
+                            return;
+                        }
+                        "dot_vote" => {
+                            let Some(allocations) = message.content
+                                .get("allocations")
+                                .and_then(|v| serde_json::from_value::<BTreeMap<String, u32>>(v.clone()).ok()) else {
+                                self.send_error(ctx, "Ugyldig fordeling af prikker");
+                                return;
+                            };
+                            let db = self.db.clone();
+                            let room_id = self.room_id.clone();
+                            let user_id = self.user_id.clone();
+
+                            ctx.spawn(
+                                (async move {
+                                    handle_dot_vote(&db, &room_id, &user_id, allocations).await.map_err(|e|
+                                        e.to_string()
+                                    )
+                                })
+                                    .into_actor(self)
+                                    .map(|result, act, ctx| {
+                                        match result {
+                                            Ok(ranking) =>
+                                                act.addr.do_send(WebSocketMessage {
+                                                    message_type: "ranking".to_string(),
+                                                    content: json!({
+                                                        "mode": RoomMode::DotVoting,
+                                                        "ranking": ranking
+                                                    }),
+                                                    room_id: act.room_id.clone(),
+                                                    user_id: act.user_id.clone(),
+                                                }),
+                                            Err(e) => act.send_error(ctx, &e),
+                                        }
+                                    })
+                            );
+                            return;
+                        }
+                        "confidence_start" | "confidence_end" => {
+                            if !self.is_admin {
+                                self.send_error(ctx, "Kun admin kan styre tillidsrunden");
+                                return;
+                            }
+                            let action = if message.message_type == "confidence_end" {
+                                ConfidenceAction::End
+                            } else {
+                                match message.content.get("completed_story_id").and_then(|v| v.as_str()) {
+                                    Some(id) => ConfidenceAction::Start { completed_story_id: id.to_string() },
+                                    None => {
+                                        self.send_error(ctx, "Manglende completed_story_id");
+                                        return;
+                                    }
+                                }
+                            };
+                            self.addr.do_send(ConfidenceCommand {
+                                room_id: self.room_id.clone(),
+                                action,
+                            });
+                            return;
+                        }
+                        "confidence_vote" => {
+                            match message.content.get("value").and_then(|v| v.as_i64()) {
+                                Some(value) if
+                                    (MIN_CONFIDENCE as i64..=MAX_CONFIDENCE as i64).contains(&value)
+                                => {
+                                    self.addr.do_send(ConfidenceCommand {
+                                        room_id: self.room_id.clone(),
+                                        action: ConfidenceAction::Vote {
+                                            user_id: self.user_id.clone(),
+                                            value: value as i32,
+                                        },
+                                    });
+                                }
+                                _ => self.send_error(ctx, "Tilliden skal være mellem 1 og 5"),
+                            }
+                            return;
+                        }
+                        "nudge" | "missing_voters" => {
+                            if !self.is_admin {
+                                self.send_error(ctx, "Kun facilitatoren kan se manglende stemmer");
+                                return;
+                            }
+                            self.addr.do_send(Nudge {
+                                room_id: self.room_id.clone(),
+                                requested_by: self.user_id.clone(),
+                                notify: message.message_type == "nudge",
+                            });
+                            return;
+                        }
                         "save_final_score" => {
                             println!(
                                 "Forsøger at parse save_final_score content: {:?}",
@@ -317,6 +811,13 @@ impl Handler<WebSocketMessage> for WebSocketSession {
     type Result = ();
 
     fn handle(&mut self, msg: WebSocketMessage, ctx: &mut Self::Context) {
+        // Rettighederne til admin-kommandoer følger rummet når det får en ny admin
+        if msg.message_type == "admin_changed" {
+            if let Some(admin_id) = msg.content.get("admin_id").and_then(|v| v.as_str()) {
+                self.is_admin = admin_id == self.user_id;
+            }
+        }
+
         // Send beskeden som tekst til WebSocket klienten
         if let Ok(text) = serde_json::to_string(&msg) {
             ctx.text(text);
@@ -324,28 +825,471 @@ impl Handler<WebSocketMessage> for WebSocketSession {
     }
 }
 
+const TIMER_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+struct RoundTimer {
+    duration_secs: u64,
+    // Resterende tid ved seneste start/pause
+    remaining: Duration,
+    // Sat når timeren kører, None når den er sat på pause
+    running_since: Option<Instant>,
+    on_expire: TimerExpiryPolicy,
+    expiry_handle: Option<SpawnHandle>,
+    tick_handle: Option<SpawnHandle>,
+}
+
+impl RoundTimer {
+    fn remaining(&self) -> Duration {
+        match self.running_since {
+            Some(since) => self.remaining.saturating_sub(since.elapsed()),
+            None => self.remaining,
+        }
+    }
+
+    fn state(&self) -> &'static str {
+        if self.running_since.is_some() { "running" } else { "paused" }
+    }
+}
+
+// Hvor ofte en facilitator må prikke til deltagere der mangler at stemme
+const NUDGE_COOLDOWN: Duration = Duration::from_secs(30);
+// Hver bruger må sende højst REACTION_LIMIT reaktioner inden for REACTION_WINDOW
+const REACTION_LIMIT: usize = 5;
+const REACTION_WINDOW: Duration = Duration::from_secs(10);
+// Tilsvarende grænse for chatbeskeder
+const CHAT_LIMIT: usize = 5;
+const CHAT_WINDOW: Duration = Duration::from_secs(10);
+// Hvor ofte planlæggeren leder efter asynkrone historier hvis frist er udløbet
+const ASYNC_REVEAL_INTERVAL: Duration = Duration::from_secs(60);
+
+// Glidende vindue: højst `limit` hændelser pr. nøgle inden for `window`
+#[derive(Clone)]
+struct MessageThrottle {
+    limit: usize,
+    window: Duration,
+    hits: HashMap<String, VecDeque<Instant>>,
+}
+
+impl MessageThrottle {
+    fn new(limit: usize, window: Duration) -> Self {
+        MessageThrottle {
+            limit,
+            window,
+            hits: HashMap::new(),
+        }
+    }
+
+    // Returnerer hvor længe der skal ventes hvis grænsen er nået
+    fn check(&mut self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let window = self.window;
+        let recent = self.hits.entry(key.to_string()).or_default();
+        while recent.front().is_some_and(|t| now.duration_since(*t) >= window) {
+            recent.pop_front();
+        }
+        if recent.len() >= self.limit {
+            let oldest = *recent.front().unwrap();
+            return Err(window - now.duration_since(oldest));
+        }
+        recent.push_back(now);
+        Ok(())
+    }
+
+    // Fjern nøgler uden hændelser i det aktuelle vindue
+    fn prune(&mut self) {
+        let window = self.window;
+        self.hits.retain(|_, recent| recent.back().is_some_and(|t| t.elapsed() < window));
+    }
+}
+
+#[derive(Clone)]
+struct SessionInfo {
+    addr: Recipient<WebSocketMessage>,
+    username: String,
+    profile_image: Option<String>,
+    last_active: Instant,
+}
+
+#[derive(Clone)]
+struct ConfidenceRound {
+    completed_story_id: String,
+    votes: HashMap<String, i32>, // user_id -> antal fingre
+}
+
 #[derive(Clone)]
 pub struct GameServer {
-    sessions: HashMap<String, HashMap<String, Recipient<WebSocketMessage>>>, // room_id -> (user_id -> recipient)
+    sessions: HashMap<String, HashMap<String, SessionInfo>>, // room_id -> (user_id -> session)
+    room_settings: HashMap<String, RoomSettings>, // room_id -> indstillinger for aktive rum
+    timers: HashMap<String, RoundTimer>, // room_id -> aktiv nedtælling
+    confidence_rounds: HashMap<String, ConfidenceRound>, // room_id -> aktiv tillidsrunde
+    last_nudge: HashMap<String, Instant>, // user_id -> tidspunkt for seneste nudge
+    reaction_throttle: MessageThrottle, // user_id -> seneste reaktioner
+    chat_throttle: MessageThrottle, // user_id -> seneste chatbeskeder
+    db: Database,
 }
 
 impl GameServer {
-    pub fn new() -> Self {
+    pub fn new(db: Database) -> Self {
         GameServer {
             sessions: HashMap::new(),
+            room_settings: HashMap::new(),
+            timers: HashMap::new(),
+            confidence_rounds: HashMap::new(),
+            last_nudge: HashMap::new(),
+            reaction_throttle: MessageThrottle::new(REACTION_LIMIT, REACTION_WINDOW),
+            chat_throttle: MessageThrottle::new(CHAT_LIMIT, CHAT_WINDOW),
+            db,
+        }
+    }
+
+    fn settings_for(&self, room_id: &str) -> RoomSettings {
+        self.room_settings.get(room_id).cloned().unwrap_or_default()
+    }
+
+    // I anonym tilstand må ingen broadcast knytte en stemmeværdi til en bruger
+    fn apply_vote_privacy(&self, mut msg: WebSocketMessage) -> WebSocketMessage {
+        let settings = self.settings_for(&msg.room_id);
+        // Asynkrone rum skjuler stemmerne indtil runden afsløres
+        let sealed = settings.mode == RoomMode::Async;
+        if !settings.anonymous_votes && !sealed {
+            return msg;
+        }
+
+        match msg.message_type.as_str() {
+            "vote" => {
+                // Andre ser kun at brugeren har stemt, ikke hvad
+                msg.content = json!({
+                    "story_id": msg.content.get("story_id"),
+                    "has_voted": true
+                });
+            }
+            "save_final_score" | "completed_story" if settings.anonymous_votes => {
+                let votes_field = if msg.message_type == "save_final_score" {
+                    msg.content.get_mut("story").and_then(|story| story.get_mut("votes"))
+                } else {
+                    msg.content.get_mut("votes")
+                };
+                if let Some(votes_field) = votes_field {
+                    let votes = serde_json
+                        ::from_value::<Vec<Vote>>(votes_field.clone())
+                        .unwrap_or_default();
+                    *votes_field = json!(anonymize_votes(votes, None));
+                }
+            }
+            _ => {}
+        }
+        msg
+    }
+
+    // Send en besked til en enkelt bruger i et rum
+    fn send_to_user(&self, message: &WebSocketMessage, room_id: &str, user_id: &str) {
+        if let Some(session) = self.sessions.get(room_id).and_then(|room| room.get(user_id)) {
+            session.addr.do_send(message.clone());
+        }
+    }
+
+    fn send_error_to_user(&self, room_id: &str, user_id: &str, content: serde_json::Value) {
+        let error_msg = WebSocketMessage {
+            message_type: "error".to_string(),
+            content,
+            room_id: room_id.to_string(),
+            user_id: "system".to_string(),
+        };
+        self.send_to_user(&error_msg, room_id, user_id);
+    }
+
+    // Find tilsluttede deltagere der ikke har stemt i den aktuelle runde,
+    // og prik til dem hvis `notify` er sat
+    fn report_missing_voters(
+        &self,
+        room_id: &str,
+        requested_by: &str,
+        story_id: &str,
+        voted: &[String],
+        notify: bool
+    ) {
+        let Some(room) = self.sessions.get(room_id) else {
+            return;
+        };
+
+        let missing: Vec<(&String, &SessionInfo)> = room
+            .iter()
+            .filter(|(user_id, _)| *user_id != requested_by && !voted.contains(user_id))
+            .collect();
+
+        if notify {
+            let from_username = room
+                .get(requested_by)
+                .map(|session| session.username.clone())
+                .unwrap_or_default();
+            let nudge_msg = WebSocketMessage {
+                message_type: "nudge".to_string(),
+                content: json!({
+                    "story_id": story_id,
+                    "from_user_id": requested_by,
+                    "from_username": from_username
+                }),
+                room_id: room_id.to_string(),
+                user_id: "system".to_string(),
+            };
+            for (_, session) in missing.iter() {
+                session.addr.do_send(nudge_msg.clone());
+            }
+        }
+
+        let report = WebSocketMessage {
+            message_type: "missing_voters".to_string(),
+            content: json!({
+                "story_id": story_id,
+                "nudged": notify,
+                "missing": missing
+                    .iter()
+                    .map(|(user_id, session)| json!({
+                        "user_id": user_id,
+                        "username": session.username,
+                        "profile_image": session.profile_image
+                    }))
+                    .collect::<Vec<_>>()
+            }),
+            room_id: room_id.to_string(),
+            user_id: "system".to_string(),
+        };
+        self.send_to_user(&report, room_id, requested_by);
+    }
+
+    fn timer_message(room_id: &str, state: &str, timer: Option<&RoundTimer>) -> WebSocketMessage {
+        WebSocketMessage {
+            message_type: "timer_update".to_string(),
+            content: json!({
+                "state": state,
+                "duration_secs": timer.map(|t| t.duration_secs),
+                "remaining_secs": timer.map(|t| t.remaining().as_secs_f64().ceil() as u64).unwrap_or(0),
+                "on_expire": timer.map(|t| t.on_expire),
+            }),
+            room_id: room_id.to_string(),
+            user_id: "system".to_string(),
+        }
+    }
+
+    fn broadcast_timer(&self, room_id: &str) {
+        if let Some(timer) = self.timers.get(room_id) {
+            let message = Self::timer_message(room_id, timer.state(), Some(timer));
+            self.send_message(&message, room_id);
+        }
+    }
+
+    // Planlæg udløb og sekund-tick for en kørende timer
+    fn schedule_timer(&mut self, room_id: &str, ctx: &mut Context<Self>) {
+        let Some(timer) = self.timers.get_mut(room_id) else {
+            return;
+        };
+
+        let expiry_room_id = room_id.to_string();
+        timer.expiry_handle = Some(
+            ctx.run_later(timer.remaining, move |act, ctx| {
+                act.expire_timer(&expiry_room_id, ctx);
+            })
+        );
+
+        let tick_room_id = room_id.to_string();
+        timer.tick_handle = Some(
+            ctx.run_interval(TIMER_TICK_INTERVAL, move |act, _| {
+                act.broadcast_timer(&tick_room_id);
+            })
+        );
+    }
+
+    fn unschedule_timer(timer: &mut RoundTimer, ctx: &mut Context<Self>) {
+        if let Some(handle) = timer.expiry_handle.take() {
+            ctx.cancel_future(handle);
+        }
+        if let Some(handle) = timer.tick_handle.take() {
+            ctx.cancel_future(handle);
+        }
+    }
+
+    fn cancel_timer(&mut self, room_id: &str, ctx: &mut Context<Self>) {
+        if let Some(mut timer) = self.timers.remove(room_id) {
+            Self::unschedule_timer(&mut timer, ctx);
+            let message = Self::timer_message(room_id, "cancelled", None);
+            self.send_message(&message, room_id);
         }
     }
 
+    // Systemets end_voting besked når serveren selv afslører en runde
+    fn end_voting_message(room_id: &str, story_id: &str, summary: &RoundSummary) -> WebSocketMessage {
+        WebSocketMessage {
+            message_type: "end_voting".to_string(),
+            content: json!({
+                "story_id": story_id,
+                "final_score": summary.final_score,
+                "wsjf": summary.wsjf,
+                "distribution": summary.distribution,
+                "dimension_stats": summary.dimension_stats
+            }),
+            room_id: room_id.to_string(),
+            user_id: "system".to_string(),
+        }
+    }
+
+    // Planlæggeren: afslør asynkrone historier hvis frist er udløbet, også
+    // i rum hvor ingen er forbundet
+    fn reveal_due_rounds(&self, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        let addr = ctx.address();
+        actix::spawn(async move {
+            let room_ids = match due_async_rooms(&db).await {
+                Ok(room_ids) => room_ids,
+                Err(e) => {
+                    println!("Fejl ved søgning efter asynkrone runder: {:?}", e);
+                    return;
+                }
+            };
+            for room_id in room_ids {
+                reveal_if_ready(&db, &room_id, &addr).await;
+            }
+        });
+    }
+
+    fn expire_timer(&mut self, room_id: &str, ctx: &mut Context<Self>) {
+        let Some(mut timer) = self.timers.remove(room_id) else {
+            return;
+        };
+        Self::unschedule_timer(&mut timer, ctx);
+        println!("Timer udløbet i rum {} (politik: {:?})", room_id, timer.on_expire);
+
+        let message = Self::timer_message(room_id, "expired", None);
+        self.send_message(&message, room_id);
+
+        let close = match timer.on_expire {
+            TimerExpiryPolicy::None => {
+                return;
+            }
+            TimerExpiryPolicy::Reveal => false,
+            TimerExpiryPolicy::Close => true,
+        };
+
+        let db = self.db.clone();
+        let room_id = room_id.to_string();
+        let addr = ctx.address();
+        actix::spawn(async move {
+            match handle_round_timeout(&db, &room_id, close).await {
+                Ok(Some((story, summary))) => {
+                    let final_score = summary.final_score;
+                    addr.do_send(Self::end_voting_message(&room_id, &story.id, &summary));
+                    if close {
+                        addr.do_send(WebSocketMessage {
+                            message_type: "save_final_score".to_string(),
+                            content: json!({
+                                "story_id": story.id,
+                                "final_score": final_score,
+                                "story": story
+                            }),
+                            room_id,
+                            user_id: "system".to_string(),
+                        });
+                    }
+                }
+                Ok(None) => println!("Ingen aktiv historie at afslutte i rum {}", room_id),
+                Err(e) => println!("Fejl ved automatisk afslutning af runde: {:?}", e),
+            }
+        });
+    }
+
+    fn broadcast_ranking(&self, room_id: &str, ctx: &mut Context<Self>) {
+        let db = self.db.clone();
+        let room_id = room_id.to_string();
+        let addr = ctx.address();
+        actix::spawn(async move {
+            match room_ranking(&db, &room_id).await {
+                Ok((mode, ranking)) =>
+                    addr.do_send(WebSocketMessage {
+                        message_type: "ranking".to_string(),
+                        content: json!({
+                            "mode": mode,
+                            "ranking": ranking
+                        }),
+                        room_id,
+                        user_id: "system".to_string(),
+                    }),
+                Err(e) => println!("Fejl ved beregning af prioritering: {:?}", e),
+            }
+        });
+    }
+
+    fn start_confidence_round(&mut self, room_id: &str, completed_story_id: String) {
+        let message = WebSocketMessage {
+            message_type: "confidence_started".to_string(),
+            content: json!({ "completed_story_id": completed_story_id }),
+            room_id: room_id.to_string(),
+            user_id: "system".to_string(),
+        };
+        self.confidence_rounds.insert(room_id.to_string(), ConfidenceRound {
+            completed_story_id,
+            votes: HashMap::new(),
+        });
+        self.send_message(&message, room_id);
+    }
+
+    fn end_confidence_round(&mut self, room_id: &str) {
+        let Some(round) = self.confidence_rounds.remove(room_id) else {
+            return;
+        };
+
+        let threshold = self.settings_for(room_id).low_confidence_threshold;
+        let now = std::time::SystemTime
+            ::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let votes = round.votes
+            .into_iter()
+            .map(|(user_id, value)| ConfidenceVote { user_id, value })
+            .collect();
+        let result = ConfidenceResult::new(votes, threshold, now);
+
+        // Kun fordelingen sendes ud, så ingen bliver hængt op på andres tillid
+        let message = WebSocketMessage {
+            message_type: "confidence_result".to_string(),
+            content: json!({
+                "completed_story_id": round.completed_story_id,
+                "average": result.average,
+                "distribution": result.distribution,
+                "low_confidence": result.low_confidence,
+                "vote_count": result.votes.len()
+            }),
+            room_id: room_id.to_string(),
+            user_id: "system".to_string(),
+        };
+        self.send_message(&message, room_id);
+
+        let db = self.db.clone();
+        let room_id = room_id.to_string();
+        actix::spawn(async move {
+            if
+                let Err(e) = save_confidence_result(
+                    &db,
+                    &room_id,
+                    &round.completed_story_id,
+                    &result
+                ).await
+            {
+                println!("Fejl ved gemning af tillidsrunde: {:?}", e);
+            }
+        });
+    }
+
     fn get_room_sessions(&self, room_id: &str) -> Option<Vec<Recipient<WebSocketMessage>>> {
-        self.sessions.get(room_id).map(|room| room.values().cloned().collect())
+        self.sessions.get(room_id).map(|room| room.values().map(|session| session.addr.clone()).collect())
     }
 
     fn send_message(&self, message: &WebSocketMessage, room_id: &str) {
         if let Some(room) = self.sessions.get(room_id) {
             println!("Sender besked til alle deltagere i rum {}", room_id);
-            for (user_id, recipient) in room.iter() {
+            for (user_id, session) in room.iter() {
                 println!("Sender besked til bruger {}", user_id);
-                recipient.do_send(message.clone());
+                session.addr.do_send(message.clone());
                 println!("Besked sendt til bruger {}", user_id);
             }
         } else {
@@ -356,6 +1300,10 @@ impl GameServer {
 
 impl Actor for GameServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(ASYNC_REVEAL_INTERVAL, |act, ctx| act.reveal_due_rounds(ctx));
+    }
 }
 
 impl Handler<Connect> for GameServer {
@@ -363,7 +1311,15 @@ impl Handler<Connect> for GameServer {
 
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) {
         println!("Ny forbindelse: Bruger {} tilslutter sig rum {}", msg.user_id, msg.room_id);
-        let room = self.sessions.entry(msg.room_id.clone()).or_insert_with(HashMap::new);
+        // Den første forbindelse bestemmer cachen. Senere ændringer kommer via RoomSettingsChanged.
+        self.room_settings.entry(msg.room_id.clone()).or_insert_with(|| msg.settings.clone());
+        let room = self.sessions.entry(msg.room_id.clone()).or_default();
+        let session = SessionInfo {
+            addr: msg.addr.clone(),
+            username: msg.username.clone(),
+            profile_image: msg.profile_image.clone(),
+            last_active: Instant::now(),
+        };
 
         println!("Eksisterende deltagere i rum: {:?}", room.keys().collect::<Vec<_>>());
 
@@ -371,12 +1327,12 @@ impl Handler<Connect> for GameServer {
         if room.contains_key(&msg.user_id) {
             println!("Bruger {} er allerede i rum {}, opdaterer forbindelse", msg.user_id, msg.room_id);
             // Update existing connection
-            room.insert(msg.user_id.clone(), msg.addr.clone());
+            room.insert(msg.user_id.clone(), session);
             return;
         }
 
         // Send beskeder om eksisterende deltagere til den nye bruger (Note: we don't send username/profile_image here as we don't have that info stored)
-        for existing_user_id in room.keys() {
+        for (existing_user_id, existing) in room.iter() {
             println!(
                 "Sender besked om eksisterende bruger {} til ny bruger {}",
                 existing_user_id,
@@ -384,7 +1340,12 @@ impl Handler<Connect> for GameServer {
             );
             let existing_user_msg = WebSocketMessage {
                 message_type: "existing_user".to_string(),
-                content: serde_json::json!({ "user_id": existing_user_id }),
+                content: serde_json::json!({
+                    "user_id": existing_user_id,
+                    "username": existing.username,
+                    "profile_image": existing.profile_image,
+                    "idle_secs": existing.last_active.elapsed().as_secs()
+                }),
                 room_id: msg.room_id.clone(),
                 user_id: existing_user_id.clone(),
             };
@@ -396,9 +1357,14 @@ impl Handler<Connect> for GameServer {
         }
 
         // Tilføj den nye bruger til rummet
-        room.insert(msg.user_id.clone(), msg.addr.clone());
+        room.insert(msg.user_id.clone(), session);
         println!("Antal deltagere i rum {} efter tilføjelse: {}", msg.room_id, room.len());
 
+        // Send en eventuel kørende nedtælling til den nye bruger
+        if let Some(timer) = self.timers.get(&msg.room_id) {
+            msg.addr.do_send(Self::timer_message(&msg.room_id, timer.state(), Some(timer)));
+        }
+
         // Send besked om ny deltager til alle ANDRE i rummet (ikke til den nye bruger selv)
         let connect_msg = WebSocketMessage {
             message_type: "user_connected".to_string(),
@@ -414,10 +1380,10 @@ impl Handler<Connect> for GameServer {
         // Send to all OTHER users in the room (exclude the newly connected user)
         if let Some(room_sessions) = self.sessions.get(&msg.room_id) {
             println!("Sender user_connected besked til alle andre i rummet");
-            for (user_id, recipient) in room_sessions.iter() {
+            for (user_id, session) in room_sessions.iter() {
                 if user_id != &msg.user_id {
                     println!("Sender besked til bruger {}", user_id);
-                    recipient.do_send(connect_msg.clone());
+                    session.addr.do_send(connect_msg.clone());
                 }
             }
         }
@@ -427,12 +1393,20 @@ impl Handler<Connect> for GameServer {
 impl Handler<Disconnect> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Context<Self>) {
         if let Some(room) = self.sessions.get_mut(&msg.room_id) {
             room.remove(&msg.user_id);
             if room.is_empty() {
                 self.sessions.remove(&msg.room_id);
+                self.room_settings.remove(&msg.room_id);
+                self.confidence_rounds.remove(&msg.room_id);
+                // Også en timer på pause, ellers bliver den liggende for evigt
+                if let Some(mut timer) = self.timers.remove(&msg.room_id) {
+                    Self::unschedule_timer(&mut timer, ctx);
+                }
             }
+            self.reaction_throttle.prune();
+            self.chat_throttle.prune();
 
             // Send besked om afbrudt forbindelse til alle andre i rummet
             let disconnect_msg = WebSocketMessage {
@@ -450,123 +1424,419 @@ impl Handler<Disconnect> for GameServer {
 #[derive(Message)]
 #[rtype(result = "()")]
 pub enum GameMessage {
-    NewStory {
-        story: Story,
-    },
-    StartVoting {
-        story_id: String,
-    },
-    Vote {
-        user_id: String,
-        story_id: String,
-        value: i32,
-        username: String,
-        profile_image: Option<String>,
-    },
-    EndVoting {
-        story_id: String,
-        final_score: i32,
-    },
-    SaveFinalScore {
-        story_id: String,
-        final_score: i32,
-    },
     CompletedStory {
-        story: CompletedStory,
-    },
-    UserConnected {
-        user_id: String,
-        username: String,
-        profile_image: Option<String>,
-    },
-    UserDisconnected {
-        user_id: String,
+        story: Box<CompletedStory>,
     },
 }
 
 impl Handler<GameMessage> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, msg: GameMessage, _: &mut Context<Self>) {
-        let room_sessions = match &msg {
-            GameMessage::NewStory { ref story } => self.get_room_sessions(&story.room_id),
-            GameMessage::StartVoting { ref story_id } => self.get_room_sessions(story_id),
-            GameMessage::Vote { ref story_id, .. } => self.get_room_sessions(story_id),
-            GameMessage::EndVoting { ref story_id, .. } => self.get_room_sessions(story_id),
-            GameMessage::SaveFinalScore { ref story_id, .. } => self.get_room_sessions(story_id),
-            GameMessage::CompletedStory { ref story } => self.get_room_sessions(&story.room_id),
-            GameMessage::UserConnected { ref user_id, .. } => self.get_room_sessions(user_id),
-            GameMessage::UserDisconnected { ref user_id } => self.get_room_sessions(user_id),
-        };
+    fn handle(&mut self, msg: GameMessage, ctx: &mut Context<Self>) {
+        let GameMessage::CompletedStory { story } = msg;
 
-        if let Some(sessions) = room_sessions {
+        if let Some(sessions) = self.get_room_sessions(&story.room_id) {
+            let mut content = json!(story);
+            content["id"] = json!(story.id.map(|id| id.to_string()));
             let message = WebSocketMessage {
-                message_type: (
-                    match &msg {
-                        GameMessage::NewStory { .. } => "new_story",
-                        GameMessage::StartVoting { .. } => "start_voting",
-                        GameMessage::Vote { .. } => "vote",
-                        GameMessage::EndVoting { .. } => "end_voting",
-                        GameMessage::SaveFinalScore { .. } => "save_final_score",
-                        GameMessage::CompletedStory { .. } => "completed_story",
-                        GameMessage::UserConnected { .. } => "user_connected",
-                        GameMessage::UserDisconnected { .. } => "user_disconnected",
-                    }
-                ).to_string(),
-                content: match &msg {
-                    GameMessage::NewStory { story } => json!(story),
-                    GameMessage::StartVoting { story_id } => json!({ "story_id": story_id }),
-                    GameMessage::Vote { user_id, story_id, value, username, profile_image } =>
-                        json!({
-                        "story_id": story_id,
-                        "value": value,
-                        "username": username,
-                        "profile_image": profile_image
-                    }),
-                    GameMessage::EndVoting { story_id, final_score } =>
-                        json!({
-                        "story_id": story_id,
-                        "final_score": final_score
-                    }),
-                    GameMessage::SaveFinalScore { story_id, final_score } =>
-                        json!({
-                        "story_id": story_id,
-                        "final_score": final_score
-                    }),
-                    GameMessage::CompletedStory { story } => json!(story),
-                    GameMessage::UserConnected { user_id, username, profile_image } =>
-                        json!({
-                        "user_id": user_id,
-                        "username": username,
-                        "profile_image": profile_image
-                    }),
-                    GameMessage::UserDisconnected { user_id } => json!({ "user_id": user_id }),
-                },
-                room_id: match &msg {
-                    GameMessage::NewStory { story } => story.room_id.clone(),
-                    GameMessage::StartVoting { story_id } => story_id.clone(),
-                    GameMessage::Vote { story_id, .. } => story_id.clone(),
-                    GameMessage::EndVoting { story_id, .. } => story_id.clone(),
-                    GameMessage::SaveFinalScore { story_id, .. } => story_id.clone(),
-                    GameMessage::CompletedStory { story } => story.room_id.clone(),
-                    GameMessage::UserConnected { user_id, .. } => user_id.clone(),
-                    GameMessage::UserDisconnected { user_id } => user_id.clone(),
-                },
+                message_type: "completed_story".to_string(),
+                content,
+                room_id: story.room_id.clone(),
                 user_id: "system".to_string(),
             };
 
+            let message = self.apply_vote_privacy(message);
             for addr in sessions {
                 addr.do_send(message.clone());
             }
         }
+
+        let settings = self.settings_for(&story.room_id);
+
+        // WSJF-rum får den opdaterede prioritering hver gang en historie er scoret
+        if settings.mode == RoomMode::Wsjf {
+            self.broadcast_ranking(&story.room_id, ctx);
+        }
+
+        // Tillidsrunden starter automatisk når en historie er gemt, hvis rummet ønsker det
+        if settings.confidence_check {
+            if let Some(id) = story.id {
+                self.start_confidence_round(&story.room_id, id.to_string());
+            }
+        }
     }
 }
 
 impl Handler<WebSocketMessage> for GameServer {
     type Result = ();
 
-    fn handle(&mut self, msg: WebSocketMessage, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: WebSocketMessage, ctx: &mut Context<Self>) {
         println!("GameServer håndterer WebSocketMessage: {:?}", msg.message_type);
+        // En runde der afsluttes eller erstattes stopper også nedtællingen
+        if matches!(msg.message_type.as_str(), "new_story" | "end_voting" | "save_final_score") {
+            self.cancel_timer(&msg.room_id, ctx);
+        }
+        let msg = self.apply_vote_privacy(msg);
         self.send_message(&msg, &msg.room_id);
     }
 }
+
+impl Handler<TimerCommand> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: TimerCommand, ctx: &mut Context<Self>) {
+        println!("Timer kommando {:?} i rum {}", msg.action, msg.room_id);
+        let room_id = msg.room_id;
+
+        match msg.action {
+            TimerAction::Start { duration_secs, on_expire } => {
+                let duration_secs = match
+                    duration_secs.or(self.settings_for(&room_id).timer_secs.map(u64::from))
+                {
+                    Some(secs) => secs.clamp(MIN_TIMER_SECS, MAX_TIMER_SECS),
+                    None => {
+                        println!("Ingen varighed for nedtællingen i rum {}", room_id);
+                        return;
+                    }
+                };
+                if let Some(mut old) = self.timers.remove(&room_id) {
+                    Self::unschedule_timer(&mut old, ctx);
+                }
+                self.timers.insert(room_id.clone(), RoundTimer {
+                    duration_secs,
+                    remaining: Duration::from_secs(duration_secs),
+                    running_since: Some(Instant::now()),
+                    on_expire,
+                    expiry_handle: None,
+                    tick_handle: None,
+                });
+                self.schedule_timer(&room_id, ctx);
+            }
+            TimerAction::Pause => {
+                let Some(timer) = self.timers.get_mut(&room_id) else {
+                    return;
+                };
+                if timer.running_since.is_none() {
+                    return;
+                }
+                timer.remaining = timer.remaining();
+                timer.running_since = None;
+                Self::unschedule_timer(timer, ctx);
+            }
+            TimerAction::Resume => {
+                let Some(timer) = self.timers.get_mut(&room_id) else {
+                    return;
+                };
+                if timer.running_since.is_some() {
+                    return;
+                }
+                timer.running_since = Some(Instant::now());
+                self.schedule_timer(&room_id, ctx);
+            }
+            TimerAction::Extend { seconds } => {
+                let Some(timer) = self.timers.get_mut(&room_id) else {
+                    return;
+                };
+                let seconds = seconds.clamp(MIN_TIMER_SECS, MAX_TIMER_SECS);
+                let running = timer.running_since.is_some();
+                // Den samlede tid begrænses også, så gentagne forlængelser ikke kan gå over grænsen
+                timer.remaining = (timer.remaining() + Duration::from_secs(seconds)).min(
+                    Duration::from_secs(MAX_TIMER_SECS)
+                );
+                timer.duration_secs = (timer.duration_secs + seconds)
+                    .min(MAX_TIMER_SECS)
+                    .max(timer.remaining.as_secs());
+                Self::unschedule_timer(timer, ctx);
+                if running {
+                    timer.running_since = Some(Instant::now());
+                    self.schedule_timer(&room_id, ctx);
+                }
+            }
+            TimerAction::Cancel => {
+                self.cancel_timer(&room_id, ctx);
+                return;
+            }
+        }
+
+        self.broadcast_timer(&room_id);
+    }
+}
+
+impl Handler<Nudge> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Nudge, ctx: &mut Context<Self>) {
+        if msg.notify {
+            if let Some(last) = self.last_nudge.get(&msg.requested_by) {
+                let elapsed = last.elapsed();
+                if elapsed < NUDGE_COOLDOWN {
+                    let retry_after = (NUDGE_COOLDOWN - elapsed).as_secs_f64().ceil() as u64;
+                    self.send_error_to_user(
+                        &msg.room_id,
+                        &msg.requested_by,
+                        json!({
+                            "message": "Vent lidt før du prikker til deltagerne igen",
+                            "retry_after_secs": retry_after
+                        })
+                    );
+                    return;
+                }
+            }
+            self.last_nudge.insert(msg.requested_by.clone(), Instant::now());
+        }
+
+        let db = self.db.clone();
+        let room_id = msg.room_id.clone();
+        ctx.spawn(
+            (async move { current_round_voters(&db, &room_id).await.map_err(|e| e.to_string()) })
+                .into_actor(self)
+                .map(move |result, act, _| {
+                    match result {
+                        Ok(Some((story_id, voted))) =>
+                            act.report_missing_voters(
+                                &msg.room_id,
+                                &msg.requested_by,
+                                &story_id,
+                                &voted,
+                                msg.notify
+                            ),
+                        Ok(None) =>
+                            act.send_error_to_user(
+                                &msg.room_id,
+                                &msg.requested_by,
+                                json!({ "message": "Der er ingen aktiv runde" })
+                            ),
+                        Err(e) => println!("Fejl ved opslag af stemmer: {}", e),
+                    }
+                })
+        );
+    }
+}
+
+impl Handler<Activity> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Activity, _: &mut Context<Self>) {
+        if let Some(session) = self.sessions.get_mut(&msg.room_id).and_then(|room| room.get_mut(&msg.user_id)) {
+            session.last_active = Instant::now();
+        }
+    }
+}
+
+impl Handler<EmojiReaction> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: EmojiReaction, _: &mut Context<Self>) {
+        let room_id = msg.room_id.clone();
+
+        if !self.settings_for(&room_id).allowed_emojis.contains(&msg.emoji) {
+            self.send_error_to_user(
+                &room_id,
+                &msg.from_user_id,
+                json!({ "message": "Denne emoji er ikke tilladt i rummet" })
+            );
+            return;
+        }
+
+        if let Some(to_user_id) = &msg.to_user_id {
+            let target_connected = self.sessions
+                .get(&room_id)
+                .is_some_and(|room| room.contains_key(to_user_id));
+            if !target_connected || to_user_id == &msg.from_user_id {
+                self.send_error_to_user(
+                    &room_id,
+                    &msg.from_user_id,
+                    json!({ "message": "Modtageren er ikke i rummet" })
+                );
+                return;
+            }
+        }
+
+        if let Err(wait) = self.reaction_throttle.check(&msg.from_user_id) {
+            self.send_error_to_user(
+                &room_id,
+                &msg.from_user_id,
+                json!({
+                    "message": "Du sender reaktioner for hurtigt",
+                    "retry_after_secs": wait.as_secs_f64().ceil() as u64
+                })
+            );
+            return;
+        }
+
+        let message = WebSocketMessage {
+            message_type: "emoji_reaction".to_string(),
+            content: json!(msg),
+            room_id: room_id.clone(),
+            user_id: msg.from_user_id.clone(),
+        };
+        self.send_message(&message, &room_id);
+    }
+}
+
+impl Handler<RoundRevealed> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoundRevealed, ctx: &mut Context<Self>) {
+        if !self.sessions.contains_key(&msg.room_id) {
+            // Ingen er forbundet; deltagerne får resultatet via deres notifikationer
+            return;
+        }
+        // Afsløringen behandles som en almindelig end_voting besked
+        ctx.address().do_send(Self::end_voting_message(&msg.room_id, &msg.story.id, &msg.summary));
+
+        let results_ready = WebSocketMessage {
+            message_type: "results_ready".to_string(),
+            content: json!({
+                "story_id": msg.story.id,
+                "title": msg.story.title,
+                "final_score": msg.summary.final_score,
+                "distribution": msg.summary.distribution
+            }),
+            room_id: msg.room_id.clone(),
+            user_id: "system".to_string(),
+        };
+        ctx.address().do_send(results_ready);
+    }
+}
+
+impl Handler<ProfileChanged> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProfileChanged, _: &mut Context<Self>) {
+        let mut rooms = Vec::new();
+        for (room_id, room) in self.sessions.iter_mut() {
+            if let Some(session) = room.get_mut(&msg.user_id) {
+                session.username = msg.username.clone();
+                session.profile_image = msg.profile_image.clone();
+                rooms.push(room_id.clone());
+            }
+        }
+
+        for room_id in rooms {
+            let message = WebSocketMessage {
+                message_type: "user_updated".to_string(),
+                content: json!({
+                    "user_id": msg.user_id,
+                    "username": msg.username,
+                    "profile_image": msg.profile_image
+                }),
+                room_id: room_id.clone(),
+                user_id: msg.user_id.clone(),
+            };
+            self.send_message(&message, &room_id);
+        }
+    }
+}
+
+impl Handler<RoomSettingsChanged> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoomSettingsChanged, _: &mut Context<Self>) {
+        if !self.sessions.contains_key(&msg.room_id) {
+            return;
+        }
+        let message = WebSocketMessage {
+            message_type: "room_settings".to_string(),
+            content: json!(msg.settings),
+            room_id: msg.room_id.clone(),
+            user_id: "system".to_string(),
+        };
+        self.room_settings.insert(msg.room_id.clone(), msg.settings);
+        self.send_message(&message, &msg.room_id);
+    }
+}
+
+impl Handler<ChatPost> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ChatPost, ctx: &mut Context<Self>) {
+        if let Err(wait) = self.chat_throttle.check(&msg.user_id) {
+            self.send_error_to_user(
+                &msg.room_id,
+                &msg.user_id,
+                json!({
+                    "message": "Du skriver for hurtigt",
+                    "retry_after_secs": wait.as_secs_f64().ceil() as u64
+                })
+            );
+            return;
+        }
+
+        let db = self.db.clone();
+        let room_id = msg.room_id.clone();
+        ctx.spawn(
+            (async move {
+                save_chat_message(
+                    &db,
+                    &msg.room_id,
+                    &msg.user_id,
+                    &msg.username,
+                    msg.profile_image,
+                    msg.text
+                ).await.map_err(|e| e.to_string())
+            })
+                .into_actor(self)
+                .map(move |result, act, _| {
+                    match result {
+                        Ok(saved) => {
+                            let message = WebSocketMessage {
+                                message_type: "chat_message".to_string(),
+                                content: json!(saved),
+                                room_id: room_id.clone(),
+                                user_id: saved.user_id.clone(),
+                            };
+                            act.send_message(&message, &room_id);
+                        }
+                        Err(e) => println!("Fejl ved gemning af chatbesked: {}", e),
+                    }
+                })
+        );
+    }
+}
+
+impl Handler<ConfidenceCommand> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ConfidenceCommand, _: &mut Context<Self>) {
+        let room_id = msg.room_id;
+
+        match msg.action {
+            ConfidenceAction::Start { completed_story_id } => {
+                self.start_confidence_round(&room_id, completed_story_id);
+            }
+            ConfidenceAction::Vote { user_id, value } => {
+                let Some(round) = self.confidence_rounds.get_mut(&room_id) else {
+                    self.send_error_to_user(
+                        &room_id,
+                        &user_id,
+                        json!({ "message": "Der er ingen aktiv tillidsrunde" })
+                    );
+                    return;
+                };
+                round.votes.insert(user_id.clone(), value);
+                let completed_story_id = round.completed_story_id.clone();
+
+                let everyone_voted = self.sessions
+                    .get(&room_id)
+                    .is_some_and(|room| room.keys().all(|id| round.votes.contains_key(id)));
+
+                let message = WebSocketMessage {
+                    message_type: "confidence_vote".to_string(),
+                    content: json!({
+                        "completed_story_id": completed_story_id,
+                        "has_voted": true
+                    }),
+                    room_id: room_id.clone(),
+                    user_id,
+                };
+                self.send_message(&message, &room_id);
+
+                if everyone_voted {
+                    self.end_confidence_round(&room_id);
+                }
+            }
+            ConfidenceAction::End => self.end_confidence_round(&room_id),
+        }
+    }
+}
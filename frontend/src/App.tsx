@@ -3,6 +3,7 @@ import { BrowserRouter as Router, Routes, Route } from "react-router-dom";
 import { AuthProvider } from "./contexts/AuthContext";
 import Landing from "./pages/Landing";
 import Auth from "./pages/Auth";
+import SsoCallback from "./pages/SsoCallback";
 import GameRoom from "./components/GameRoom";
 import CreateRoom from "./components/CreateRoom";
 import JoinRoom from "./components/JoinRoom";
@@ -20,6 +21,7 @@ const App: React.FC = () => {
           <Routes>
             <Route path="/" element={<Landing />} />
             <Route path="/auth" element={<Auth />} />
+            <Route path="/auth/sso" element={<SsoCallback />} />
             <Route
               path="/rooms/create"
               element={
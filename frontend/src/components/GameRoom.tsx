@@ -121,10 +121,20 @@ interface GameRoomData {
   current_story?: Story;
   completed_stories: Story[];
   stories: Story[];
+  team_id?: string;
+  settings?: RoomSettings;
   created_at: number;
   updated_at: number;
 }
 
+interface RoomSettings {
+  // -1 er "?" kortet
+  deck?: number[];
+  timer_secs?: number;
+}
+
+const DEFAULT_DECK = [0, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, -1];
+
 interface ParticipantInfo {
   id: string;
   username: string;
@@ -351,7 +361,7 @@ const GameRoom: React.FC = () => {
         // Use different endpoints for regular users vs guests
         let wsUrl;
         if (guestUser) {
-          wsUrl = `${wsBaseUrl}/rooms/${roomId}/guest-ws/${guestUser.id}`;
+          wsUrl = `${wsBaseUrl}/rooms/${roomId}/guest-ws?token=${guestUser.token}`;
           console.log(
             `=== CREATING GUEST WEBSOCKET CONNECTION (attempt ${
               connectionAttempts + 1
@@ -579,6 +589,13 @@ const GameRoom: React.FC = () => {
                 handleEmojiReaction(message.content);
                 break;
 
+              case "admin_changed":
+                // Rummet er overdraget, fx fordi admin har slettet sin konto
+                setRoom((prev) =>
+                  prev ? { ...prev, admin_id: message.content.admin_id } : prev
+                );
+                break;
+
               default:
                 console.log("Ukendt besked type:", message.message_type);
             }
@@ -1703,8 +1720,8 @@ const GameRoom: React.FC = () => {
             </p>
           )}
           <div className="flex justify-center gap-3">
-            {[0, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, "?"].map((value, index) => {
-              const numValue = typeof value === "number" ? value : -1;
+            {(room?.settings?.deck ?? DEFAULT_DECK).map((numValue, index) => {
+              const value = numValue === -1 ? "?" : numValue;
               const getCardColor = () => {
                 if (!isVotingOpen || hasVoted)
                   return "bg-gray-100 border-gray-300 text-gray-400";
@@ -34,16 +34,19 @@ const ProfileImageUpload: React.FC<ProfileImageUploadProps> = ({
 
     try {
       if (isGuest) {
-        // For guests, upload to guest endpoint (no auth required)
+        // For guests, upload to guest endpoint with the guest token
         const formData = new FormData();
         formData.append("image", file);
 
         const response = await fetch(
           `${
             import.meta.env.VITE_API_BASE_URL || "http://localhost:8080"
-          }/guest/profile-image/${guestUser?.id}`,
+          }/guest/profile-image`,
           {
             method: "POST",
+            headers: {
+              Authorization: `Bearer ${guestUser?.token}`,
+            },
             body: formData,
           }
         );
@@ -25,6 +25,8 @@ interface GuestUser {
   username: string;
   profile_image?: string;
   is_guest: boolean;
+  // Rumbundet token til gæstens WebSocket og REST kald
+  token?: string;
 }
 
 interface AuthContextType {
@@ -32,7 +34,13 @@ interface AuthContextType {
   guestUser: GuestUser | null;
   token: string | null;
   userId: string | null;
-  login: (email: string, password: string) => Promise<void>;
+  // Returnerer et challenge token hvis kontoen kræver en to-faktor kode
+  login: (email: string, password: string) => Promise<string | null>;
+  verifyTwoFactor: (
+    challengeToken: string,
+    code: string,
+    isRecoveryCode?: boolean
+  ) => Promise<void>;
   register: (
     email: string,
     password: string,
@@ -103,19 +111,56 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({
     }
   }, []);
 
+  // Gæsten er flettet ind i kontoen og findes ikke længere
+  const clearGuestSession = () => {
+    localStorage.removeItem("guest_session");
+    setGuestUser(null);
+    setIsGuest(false);
+  };
+
   const login = async (email: string, password: string) => {
     try {
+      // En gæst der logger ind beholder sin historik fra gæstesessionen
       const response = await axios.post("/auth/login", {
         email,
         password,
+        guest_token: guestUser?.token,
       });
 
+      if (response.data.two_factor_required) {
+        return response.data.challenge_token as string;
+      }
+
       const { token, user } = response.data;
+      clearGuestSession();
       setAuthToken(token, user);
       setIsAuthenticated(true);
 
       // Sæt isAuthenticated til true når vi har en bruger
       setIsAuthenticated(true);
+      return null;
+    } catch (error) {
+      if (axios.isAxiosError(error) && error.response) {
+        throw new Error(error.response.data.message || "Login fejlede");
+      }
+      throw new Error("Der opstod en fejl under login");
+    }
+  };
+
+  const verifyTwoFactor = async (
+    challengeToken: string,
+    code: string,
+    isRecoveryCode = false
+  ) => {
+    try {
+      const response = await axios.post("/auth/login/2fa", {
+        challenge_token: challengeToken,
+        ...(isRecoveryCode ? { recovery_code: code } : { code }),
+      });
+
+      const { token, user } = response.data;
+      clearGuestSession();
+      setAuthToken(token, user);
     } catch (error) {
       if (axios.isAxiosError(error) && error.response) {
         throw new Error(error.response.data.message || "Login fejlede");
@@ -134,9 +179,11 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({
         email,
         password,
         username,
+        guest_token: guestUser?.token,
       });
 
       const { token, user } = response.data;
+      clearGuestSession();
       setAuthToken(token, user);
       setIsAuthenticated(true);
 
@@ -187,12 +234,13 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({
         room_code: roomCode,
       });
 
-      const { room, guest_id } = response.data;
+      const { room, guest_id, guest_token } = response.data;
 
       const guestUserData: GuestUser = {
         id: guest_id,
         username,
         is_guest: true,
+        token: guest_token,
       };
 
       // Store guest session with room information
@@ -231,12 +279,13 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({
         room_name: roomName,
       });
 
-      const { room, guest_id } = response.data;
+      const { room, guest_id, guest_token } = response.data;
 
       const guestUserData: GuestUser = {
         id: guest_id,
         username,
         is_guest: true,
+        token: guest_token,
       };
 
       // Store guest session with room information
@@ -296,6 +345,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({
     token,
     userId: user?.id || guestUser?.id || null,
     login,
+    verifyTwoFactor,
     register,
     joinAsGuest,
     createRoomAsGuest,
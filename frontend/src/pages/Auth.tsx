@@ -1,6 +1,7 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
+import axios from "axios";
 import { useAuth } from "../contexts/AuthContext";
-import { useNavigate } from "react-router-dom";
+import { useNavigate, useSearchParams } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import LanguageSwitcher from "../components/LanguageSwitcher";
 import estimerLogo from "../assets/estimer.png";
@@ -19,17 +20,49 @@ export default function Auth() {
     username: "",
   });
   const [error, setError] = useState<string>("");
-  const { login, register } = useAuth();
+  const [sso, setSso] = useState({ enabled: false, sso_required: false });
+  const [challengeToken, setChallengeToken] = useState<string | null>(null);
+  const [twoFactorCode, setTwoFactorCode] = useState("");
+  const [useRecoveryCode, setUseRecoveryCode] = useState(false);
+  const { login, verifyTwoFactor, register, guestUser } = useAuth();
   const navigate = useNavigate();
+  const [searchParams] = useSearchParams();
   const { t } = useTranslation();
 
+  useEffect(() => {
+    axios
+      .get("/auth/oidc/config")
+      .then((response) => setSso(response.data))
+      .catch(() => setSso({ enabled: false, sso_required: false }));
+  }, []);
+
+  useEffect(() => {
+    if (searchParams.get("sso_error")) {
+      setError(t("auth.ssoError"));
+    }
+  }, [searchParams, t]);
+
+  const startSsoLogin = () => {
+    // En gæst beholder sin historik når de logger ind med SSO
+    const params = guestUser?.token
+      ? `?guest_token=${encodeURIComponent(guestUser.token)}`
+      : "";
+    window.location.href = `${axios.defaults.baseURL}/auth/oidc/login${params}`;
+  };
+
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
     setError("");
 
     try {
-      if (isLogin) {
-        await login(formData.email, formData.password);
+      if (challengeToken) {
+        await verifyTwoFactor(challengeToken, twoFactorCode, useRecoveryCode);
+      } else if (isLogin) {
+        const challenge = await login(formData.email, formData.password);
+        if (challenge) {
+          setChallengeToken(challenge);
+          return;
+        }
       } else {
         if (!formData.username) {
           throw new Error(t("auth.usernameRequired"));
@@ -82,7 +115,57 @@ export default function Auth() {
             </div>
           )}
 
+          {sso.enabled && (
+            <div className="mt-8 space-y-4">
+              <button
+                type="button"
+                onClick={startSsoLogin}
+                className="w-full flex justify-center py-2 px-4 border border-gray-300 text-sm font-medium rounded-md text-gray-700 bg-white hover:bg-gray-50 focus:outline-none focus:ring-2 focus:ring-offset-2 focus:ring-blue-500"
+              >
+                {t("auth.ssoLogin")}
+              </button>
+              {!sso.sso_required && (
+                <p className="text-center text-sm text-gray-500">
+                  {t("auth.ssoOr")}
+                </p>
+              )}
+            </div>
+          )}
+
+          {!sso.sso_required && (
           <form className="mt-8 space-y-6" onSubmit={handleSubmit}>
+            {challengeToken ? (
+            <div className="space-y-4">
+              <label
+                htmlFor="twoFactorCode"
+                className="block text-sm font-medium text-gray-700"
+              >
+                {useRecoveryCode
+                  ? t("auth.recoveryCode")
+                  : t("auth.twoFactorCode")}
+              </label>
+              <input
+                id="twoFactorCode"
+                name="twoFactorCode"
+                type="text"
+                autoComplete="one-time-code"
+                required
+                autoFocus
+                className="appearance-none rounded-md relative block w-full px-3 py-2 border border-gray-300 placeholder-gray-500 text-gray-900 focus:outline-none focus:ring-primary focus:border-primary focus:z-10 sm:text-sm"
+                value={twoFactorCode}
+                onChange={(e) => setTwoFactorCode(e.target.value)}
+              />
+              <button
+                type="button"
+                className="text-sm text-blue-600 hover:text-blue-800"
+                onClick={() => setUseRecoveryCode(!useRecoveryCode)}
+              >
+                {useRecoveryCode
+                  ? t("auth.useAuthenticatorCode")
+                  : t("auth.useRecoveryCode")}
+              </button>
+            </div>
+            ) : (
             <div className="rounded-md  space-y-4">
               {!isLogin && (
                 <div>
@@ -147,6 +230,7 @@ export default function Auth() {
                 />
               </div>
             </div>
+            )}
 
             <div>
               <button
@@ -157,16 +241,19 @@ export default function Auth() {
               </button>
             </div>
           </form>
+          )}
 
-          <div className="text-center">
-            <button
-              type="button"
-              className="text-sm text-blue-600 hover:text-blue-800"
-              onClick={() => setIsLogin(!isLogin)}
-            >
-              {isLogin ? t("auth.noAccountSignUp") : t("auth.haveAccountLogin")}
-            </button>
-          </div>
+          {!sso.sso_required && (
+            <div className="text-center">
+              <button
+                type="button"
+                className="text-sm text-blue-600 hover:text-blue-800"
+                onClick={() => setIsLogin(!isLogin)}
+              >
+                {isLogin ? t("auth.noAccountSignUp") : t("auth.haveAccountLogin")}
+              </button>
+            </div>
+          )}
         </div>
       </div>
     </div>
@@ -0,0 +1,43 @@
+import { useEffect, useRef } from "react";
+import axios from "axios";
+import { useNavigate } from "react-router-dom";
+import { useTranslation } from "react-i18next";
+import { useAuth } from "../contexts/AuthContext";
+
+// Backenden sender browseren hertil efter SSO login med tokens i URL-fragmentet
+export default function SsoCallback() {
+  const { setAuthToken } = useAuth();
+  const navigate = useNavigate();
+  const { t } = useTranslation();
+  const handled = useRef(false);
+
+  useEffect(() => {
+    if (handled.current) return;
+    handled.current = true;
+
+    const params = new URLSearchParams(window.location.hash.slice(1));
+    const token = params.get("token");
+    // Fjern tokens fra adresselinjen og historikken
+    window.history.replaceState(null, "", window.location.pathname);
+
+    if (!token) {
+      navigate("/auth?sso_error=invalid_request", { replace: true });
+      return;
+    }
+
+    axios
+      .get("/auth/me", { headers: { Authorization: `Bearer ${token}` } })
+      .then((response) => {
+        localStorage.removeItem("guest_session");
+        setAuthToken(token, response.data);
+        navigate("/rooms/create", { replace: true });
+      })
+      .catch(() => navigate("/auth?sso_error=invalid_token", { replace: true }));
+  }, [navigate, setAuthToken]);
+
+  return (
+    <div className="min-h-screen flex items-center justify-center bg-gray-50">
+      <p className="text-gray-600">{t("auth.ssoSigningIn")}</p>
+    </div>
+  );
+}